@@ -1,5 +1,5 @@
 declare module '*/query.idl.js' {
     import { IDL } from '@dfinity/candid';
     export const idlFactory: IDL.InterfaceFactory;
-    export const init: (args: { IDL: IDL }) => [];
+    export const init: (args: { IDL: typeof IDL }) => IDL.Type[];
 } 
\ No newline at end of file
@@ -23,8 +23,12 @@ export const contestService = {
   async joinContest(contestId: string, teamId: string): Promise<boolean> {
     try {
       const actor = getActor();
-      const result = await actor.join_contest(contestId, teamId);
-      return result as boolean;
+      const result = (await actor.join_contest(contestId, teamId)) as { Ok: ContestEntry } | { Err: unknown };
+      if ('Err' in result) {
+        console.error(`Error joining contest ${contestId}:`, result.Err);
+        return false;
+      }
+      return true;
     } catch (error) {
       console.error(`Error joining contest ${contestId}:`, error);
       return false;
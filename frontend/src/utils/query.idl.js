@@ -1,5 +1,9 @@
 export const idlFactory = ({ IDL }) => {
-    const KYCStatus = IDL.Variant({       
+    const InitPayload = IDL.Record({
+      'token_ledger' : IDL.Opt(IDL.Principal),
+      'admin_principal' : IDL.Opt(IDL.Principal),
+    });
+    const KYCStatus = IDL.Variant({
       'Rejected' : IDL.Null,
       'Verified' : IDL.Null,
       'Pending' : IDL.Null,
@@ -11,6 +15,7 @@ export const idlFactory = ({ IDL }) => {
       'balance' : IDL.Nat64,
       'created_at' : IDL.Nat64,
       'email' : IDL.Opt(IDL.Text),
+      'deposit_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
       'kyc_status' : KYCStatus,
       'phone' : IDL.Opt(IDL.Text),
     });
@@ -19,16 +24,42 @@ export const idlFactory = ({ IDL }) => {
       'error' : IDL.Opt(IDL.Text),
       'success' : IDL.Bool,
     });
+    const Role = IDL.Variant({
+      'Operator' : IDL.Null,
+      'KYCReviewer' : IDL.Null,
+      'Admin' : IDL.Null,
+      'ScoreFeeder' : IDL.Null,
+    });
+    const RoleAssignment = IDL.Record({
+      'principal' : IDL.Principal,
+      'roles' : IDL.Vec(Role),
+    });
+    const RolesResponse = IDL.Record({
+      'data' : IDL.Opt(IDL.Vec(RoleAssignment)),
+      'error' : IDL.Opt(IDL.Text),
+      'success' : IDL.Bool,
+    });
     const Sport = IDL.Variant({
       'Basketball' : IDL.Null,
       'Tennis' : IDL.Null,
       'Football' : IDL.Null,
       'Cricket' : IDL.Null,
     });
+    const Comparison = IDL.Variant({
+      'LessThanOrEqual' : IDL.Null,
+      'GreaterThan' : IDL.Null,
+      'LessThan' : IDL.Null,
+      'GreaterThanOrEqual' : IDL.Null,
+    });
+    const RuleCondition = IDL.Record({
+      'comparison' : Comparison,
+      'threshold' : IDL.Float64,
+    });
     const ScoringRule = IDL.Record({
       'action' : IDL.Text,
       'sport' : Sport,
       'points' : IDL.Float64,
+      'condition' : IDL.Opt(RuleCondition),
     });
     const ScoringRuleResponse = IDL.Record({
       'data' : IDL.Opt(ScoringRule),
@@ -107,31 +138,50 @@ export const idlFactory = ({ IDL }) => {
       'error' : IDL.Opt(IDL.Text),
       'success' : IDL.Bool,
     });
-    const RewardStatus = IDL.Variant({
+    const WithdrawalStatus = IDL.Variant({
       'Failed' : IDL.Null,
-      'Claimed' : IDL.Null,
-      'Pending' : IDL.Null,
+      'Rejected' : IDL.Null,
+      'PendingApproval' : IDL.Null,
+      'Processing' : IDL.Null,
+      'Completed' : IDL.Null,
     });
-    const UserReward = IDL.Record({
+    const Account = IDL.Record({
+      'owner' : IDL.Principal,
+      'subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    });
+    const WithdrawalRequest = IDL.Record({
       'id' : IDL.Text,
-      'status' : RewardStatus,
-      'claimed_at' : IDL.Opt(IDL.Nat64),
-      'contest_id' : IDL.Text,
-      'rank' : IDL.Nat32,
+      'fee' : IDL.Opt(IDL.Nat64),
+      'status' : WithdrawalStatus,
+      'destination' : Account,
       'created_at' : IDL.Nat64,
       'user_id' : IDL.Principal,
+      'error' : IDL.Opt(IDL.Text),
+      'sent_at' : IDL.Opt(IDL.Nat64),
+      'completed_at' : IDL.Opt(IDL.Nat64),
       'amount' : IDL.Nat64,
+      'decided_by' : IDL.Opt(IDL.Principal),
     });
-    const UserRewardResponse = IDL.Record({
-      'data' : IDL.Opt(UserReward),
+    const WithdrawalResponse = IDL.Record({
+      'data' : IDL.Opt(WithdrawalRequest),
       'error' : IDL.Opt(IDL.Text),
       'success' : IDL.Bool,
     });
-    const ContestType = IDL.Variant({
-      'MultiPlayer' : IDL.Null,
-      'Guaranteed' : IDL.Null,
-      'HeadToHead' : IDL.Null,
-      'WinnerTakesAll' : IDL.Null,
+    const LedgerAccount = IDL.Variant({
+      'MatchmakingEscrow' : IDL.Text,
+      'ContestEscrow' : IDL.Text,
+      'Promo' : IDL.Null,
+      'UserWallet' : IDL.Principal,
+      'House' : IDL.Null,
+      'Treasury' : IDL.Null,
+      'PendingWithdrawals' : IDL.Null,
+    });
+    const LedgerAudit = IDL.Record({
+      'account_count' : IDL.Nat64,
+      'entry_count' : IDL.Nat64,
+      'mismatched_accounts' : IDL.Vec(LedgerAccount),
+      'total_balance' : IDL.Int,
+      'balanced' : IDL.Bool,
     });
     const ContestStatus = IDL.Variant({
       'Full' : IDL.Null,
@@ -140,10 +190,35 @@ export const idlFactory = ({ IDL }) => {
       'Cancelled' : IDL.Null,
       'Completed' : IDL.Null,
     });
+    const ContestType = IDL.Variant({
+      'MultiPlayer' : IDL.Null,
+      'Guaranteed' : IDL.Null,
+      'HeadToHead' : IDL.Null,
+      'WinnerTakesAll' : IDL.Null,
+    });
+    const ContestVisibility = IDL.Variant({
+      'Private' : IDL.Null,
+      'Public' : IDL.Null,
+    });
+    const RankingMethod = IDL.Variant({
+      'Dense' : IDL.Null,
+      'Standard' : IDL.Null,
+    });
+    const TieBreaker = IDL.Variant({
+      'CaptainPoints' : IDL.Null,
+      'EarliestEntry' : IDL.Null,
+    });
+    const RankingConfig = IDL.Record({
+      'method' : RankingMethod,
+      'tie_breakers' : IDL.Vec(TieBreaker),
+    });
     const Contest = IDL.Record({
       'id' : IDL.Text,
       'status' : ContestStatus,
       'total_spots' : IDL.Nat32,
+      'creator' : IDL.Opt(IDL.Principal),
+      'min_entries' : IDL.Opt(IDL.Nat32),
+      'max_entries_per_user' : IDL.Opt(IDL.Nat32),
       'contest_type' : ContestType,
       'entry_fee' : IDL.Nat64,
       'name' : IDL.Text,
@@ -152,12 +227,36 @@ export const idlFactory = ({ IDL }) => {
       'start_time' : IDL.Nat64,
       'prize_pool' : IDL.Nat64,
       'match_id' : IDL.Text,
+      'scoring_rule_set_id' : IDL.Opt(IDL.Text),
+      'visibility' : IDL.Opt(ContestVisibility),
+      'ranking' : IDL.Opt(RankingConfig),
     });
     const ContestResponse = IDL.Record({
       'data' : IDL.Opt(Contest),
       'error' : IDL.Opt(IDL.Text),
       'success' : IDL.Bool,
     });
+    const RewardStatus = IDL.Variant({
+      'Failed' : IDL.Null,
+      'Claimed' : IDL.Null,
+      'Pending' : IDL.Null,
+    });
+    const UserReward = IDL.Record({
+      'id' : IDL.Text,
+      'transaction_id' : IDL.Opt(IDL.Text),
+      'status' : RewardStatus,
+      'claimed_at' : IDL.Opt(IDL.Nat64),
+      'contest_id' : IDL.Text,
+      'rank' : IDL.Nat32,
+      'created_at' : IDL.Nat64,
+      'user_id' : IDL.Principal,
+      'amount' : IDL.Nat64,
+    });
+    const UserRewardResponse = IDL.Record({
+      'data' : IDL.Opt(UserReward),
+      'error' : IDL.Opt(IDL.Text),
+      'success' : IDL.Bool,
+    });
     const FantasyTeam = IDL.Record({
       'id' : IDL.Text,
       'total_points' : IDL.Float64,
@@ -165,10 +264,26 @@ export const idlFactory = ({ IDL }) => {
       'total_price' : IDL.Nat64,
       'players' : IDL.Vec(IDL.Text),
       'captain_id' : IDL.Text,
+      'match_id' : IDL.Opt(IDL.Text),
       'vice_captain_id' : IDL.Text,
     });
+    const CompositionRule = IDL.Variant({
+      'PositionLimit' : PlayerPosition,
+      'CreditBudget' : IDL.Null,
+      'SquadSize' : IDL.Null,
+      'UnknownPlayer' : IDL.Null,
+      'MaxPlayersPerTeam' : IDL.Null,
+      'PlayerNotInMatch' : IDL.Null,
+      'Captaincy' : IDL.Null,
+      'DuplicatePlayer' : IDL.Null,
+    });
+    const TeamRuleViolation = IDL.Record({
+      'rule' : CompositionRule,
+      'message' : IDL.Text,
+    });
     const FantasyTeamResponse = IDL.Record({
       'data' : IDL.Opt(FantasyTeam),
+      'violations' : IDL.Opt(IDL.Vec(TeamRuleViolation)),
       'error' : IDL.Opt(IDL.Text),
       'success' : IDL.Bool,
     });
@@ -181,6 +296,7 @@ export const idlFactory = ({ IDL }) => {
       'ContestReminder' : IDL.Null,
       'SystemUpdate' : IDL.Null,
       'ContestResult' : IDL.Null,
+      'ContestCancelled' : IDL.Null,
       'PrizeWon' : IDL.Null,
     });
     const Notification = IDL.Record({
@@ -203,14 +319,25 @@ export const idlFactory = ({ IDL }) => {
       'success' : IDL.Bool,
     });
     const PrizeDistribution = IDL.Record({
+      'rank_end' : IDL.Opt(IDL.Nat32),
       'rank' : IDL.Nat32,
       'amount' : IDL.Nat64,
       'percentage' : IDL.Float64,
     });
+    const PrivateContest = IDL.Record({
+      'contest' : Contest,
+      'invite_code' : IDL.Text,
+    });
+    const PrivateContestResponse = IDL.Record({
+      'data' : IDL.Opt(PrivateContest),
+      'error' : IDL.Opt(IDL.Text),
+      'success' : IDL.Bool,
+    });
     const PrizePool = IDL.Record({
       'distributed' : IDL.Bool,
       'total_amount' : IDL.Nat64,
       'contest_id' : IDL.Text,
+      'rake' : IDL.Opt(IDL.Nat64),
       'distribution' : IDL.Vec(PrizeDistribution),
     });
     const PrizePoolResponse = IDL.Record({
@@ -218,13 +345,62 @@ export const idlFactory = ({ IDL }) => {
       'error' : IDL.Opt(IDL.Text),
       'success' : IDL.Bool,
     });
+    const SeasonLeagueStatus = IDL.Variant({
+      'Open' : IDL.Null,
+      'Cancelled' : IDL.Null,
+      'Completed' : IDL.Null,
+    });
+    const SeasonLeague = IDL.Record({
+      'id' : IDL.Text,
+      'transfers_per_matchday' : IDL.Nat32,
+      'status' : SeasonLeagueStatus,
+      'entry_fee' : IDL.Nat64,
+      'name' : IDL.Text,
+      'created_at' : IDL.Nat64,
+      'tournament_id' : IDL.Text,
+      'scoring_rule_set_id' : IDL.Opt(IDL.Text),
+      'member_count' : IDL.Nat32,
+      'distribution' : IDL.Vec(PrizeDistribution),
+      'max_members' : IDL.Nat32,
+    });
+    const SeasonLeagueResponse = IDL.Record({
+      'data' : IDL.Opt(SeasonLeague),
+      'error' : IDL.Opt(IDL.Text),
+      'success' : IDL.Bool,
+    });
     const TeamResponse = IDL.Record({
       'data' : IDL.Opt(Team),
       'error' : IDL.Opt(IDL.Text),
       'success' : IDL.Bool,
     });
-    const UserRewardsResponse = IDL.Record({
-      'data' : IDL.Opt(IDL.Vec(UserReward)),
+    const TransactionStatus = IDL.Variant({
+      'Failed' : IDL.Null,
+      'Cancelled' : IDL.Null,
+      'Completed' : IDL.Null,
+      'Pending' : IDL.Null,
+    });
+    const TransactionType = IDL.Variant({
+      'HouseShortfall' : IDL.Null,
+      'ContestWin' : IDL.Null,
+      'Deposit' : IDL.Null,
+      'Refund' : IDL.Null,
+      'Rake' : IDL.Null,
+      'Bonus' : IDL.Null,
+      'HouseRevenue' : IDL.Null,
+      'Withdrawal' : IDL.Null,
+      'ContestEntry' : IDL.Null,
+    });
+    const RewardTransaction = IDL.Record({
+      'id' : IDL.Text,
+      'status' : TransactionStatus,
+      'transaction_type' : TransactionType,
+      'created_at' : IDL.Nat64,
+      'user_id' : IDL.Principal,
+      'completed_at' : IDL.Opt(IDL.Nat64),
+      'amount' : IDL.Nat64,
+    });
+    const RewardTransactionResponse = IDL.Record({
+      'data' : IDL.Opt(RewardTransaction),
       'error' : IDL.Opt(IDL.Text),
       'success' : IDL.Bool,
     });
@@ -235,6 +411,7 @@ export const idlFactory = ({ IDL }) => {
       'team' : FantasyTeam,
       'created_at' : IDL.Nat64,
       'user_id' : IDL.Principal,
+      'refunded_at' : IDL.Opt(IDL.Nat64),
       'prize' : IDL.Opt(IDL.Nat64),
       'points' : IDL.Float64,
     });
@@ -243,6 +420,25 @@ export const idlFactory = ({ IDL }) => {
       'error' : IDL.Opt(IDL.Text),
       'success' : IDL.Bool,
     });
+    const FantasyError = IDL.Variant({
+      'ContestFull' : IDL.Null,
+      'SystemError' : IDL.Null,
+      'InsufficientBalance' : IDL.Null,
+      'AlreadyJoined' : IDL.Null,
+      'ContestNotFound' : IDL.Null,
+      'TeamNotFound' : IDL.Null,
+      'Unauthorized' : IDL.Null,
+      'InvalidTeam' : IDL.Null,
+      'TournamentNotFound' : IDL.Null,
+      'ContestAlreadyStarted' : IDL.Null,
+      'ContestCompleted' : IDL.Null,
+      'InvalidOperation' : IDL.Null,
+      'UserNotFound' : IDL.Null,
+    });
+    const AccountBalanceResult = IDL.Variant({
+      'Ok' : IDL.Int,
+      'Err' : FantasyError,
+    });
     const PlayerStats = IDL.Record({
       'stumpings' : IDL.Opt(IDL.Nat32),
       'steals' : IDL.Opt(IDL.Nat32),
@@ -271,16 +467,127 @@ export const idlFactory = ({ IDL }) => {
       'match_id' : IDL.Text,
       'points' : IDL.Float64,
     });
+    const ContestEscrowSummary = IDL.Record({
+      'status' : ContestStatus,
+      'contest_id' : IDL.Text,
+      'rake' : IDL.Opt(IDL.Nat64),
+      'collected' : IDL.Nat64,
+      'shortfall' : IDL.Nat64,
+      'advertised_pool' : IDL.Nat64,
+      'prize_pool' : IDL.Nat64,
+      'escrow_balance' : IDL.Int,
+    });
+    const LeaderboardEntry = IDL.Record({
+      'username' : IDL.Text,
+      'rank' : IDL.Nat32,
+      'user_id' : IDL.Principal,
+      'prize' : IDL.Opt(IDL.Nat64),
+      'points' : IDL.Float64,
+    });
+    const LeaderboardPage = IDL.Record({
+      'total' : IDL.Nat64,
+      'entries' : IDL.Vec(LeaderboardEntry),
+    });
+    const LeaderboardResponse = IDL.Record({
+      'data' : IDL.Opt(LeaderboardPage),
+      'error' : IDL.Opt(IDL.Text),
+      'success' : IDL.Bool,
+    });
+    const LeaderboardMetric = IDL.Variant({
+      'Winnings' : IDL.Null,
+      'Points' : IDL.Null,
+    });
+    const LedgerEntry = IDL.Record({
+      'id' : IDL.Nat64,
+      'to' : LedgerAccount,
+      'transaction_type' : TransactionType,
+      'from' : LedgerAccount,
+      'created_at' : IDL.Nat64,
+      'amount' : IDL.Nat64,
+    });
+    const LockTimeResponse = IDL.Record({
+      'data' : IDL.Opt(IDL.Nat64),
+      'error' : IDL.Opt(IDL.Text),
+      'success' : IDL.Bool,
+    });
     const MatchScoreResponse = IDL.Record({
       'data' : IDL.Opt(MatchScore),
       'error' : IDL.Opt(IDL.Text),
       'success' : IDL.Bool,
     });
+    const MatchmakingTicket = IDL.Record({
+      'fantasy_team_id' : IDL.Text,
+      'created_at' : IDL.Nat64,
+      'user_id' : IDL.Principal,
+      'stake' : IDL.Nat64,
+      'refund_failed' : IDL.Opt(IDL.Bool),
+      'match_id' : IDL.Text,
+    });
+    const UserRewardsResponse = IDL.Record({
+      'data' : IDL.Opt(IDL.Vec(UserReward)),
+      'error' : IDL.Opt(IDL.Text),
+      'success' : IDL.Bool,
+    });
     const PlayerScoreResponse = IDL.Record({
       'data' : IDL.Opt(PlayerScore),
       'error' : IDL.Opt(IDL.Text),
       'success' : IDL.Bool,
     });
+    const ScoringRuleSet = IDL.Record({
+      'id' : IDL.Text,
+      'created_at' : IDL.Nat64,
+      'version' : IDL.Nat32,
+      'sport' : Sport,
+      'rules' : IDL.Vec(ScoringRule),
+    });
+    const ScoringRuleSetResponse = IDL.Record({
+      'data' : IDL.Opt(ScoringRuleSet),
+      'error' : IDL.Opt(IDL.Text),
+      'success' : IDL.Bool,
+    });
+    const SeasonMatchday = IDL.Record({
+      'team' : FantasyTeam,
+      'match_id' : IDL.Text,
+      'points' : IDL.Float64,
+    });
+    const SeasonEntry = IDL.Record({
+      'id' : IDL.Text,
+      'matchdays' : IDL.Vec(SeasonMatchday),
+      'transfers_used' : IDL.Nat32,
+      'league_id' : IDL.Text,
+      'total_points' : IDL.Float64,
+      'rank' : IDL.Opt(IDL.Nat32),
+      'team' : FantasyTeam,
+      'user_id' : IDL.Principal,
+      'joined_at' : IDL.Nat64,
+      'refunded_at' : IDL.Opt(IDL.Nat64),
+      'prize' : IDL.Opt(IDL.Nat64),
+      'transfer_window' : IDL.Opt(IDL.Text),
+    });
+    const SeasonEntriesResponse = IDL.Record({
+      'data' : IDL.Opt(IDL.Vec(SeasonEntry)),
+      'error' : IDL.Opt(IDL.Text),
+      'success' : IDL.Bool,
+    });
+    const PositionLimit = IDL.Record({
+      'max' : IDL.Nat32,
+      'min' : IDL.Nat32,
+      'position' : PlayerPosition,
+    });
+    const TeamCompositionRules = IDL.Record({
+      'max_squad_size' : IDL.Nat32,
+      'max_players_per_team' : IDL.Nat32,
+      'sport' : Sport,
+      'position_limits' : IDL.Vec(PositionLimit),
+      'min_credits' : IDL.Nat64,
+      'max_credits' : IDL.Nat64,
+      'min_squad_size' : IDL.Nat32,
+    });
+    const TeamCompositionRulesResponse = IDL.Record({
+      'data' : IDL.Opt(TeamCompositionRules),
+      'error' : IDL.Opt(IDL.Text),
+      'success' : IDL.Bool,
+    });
     const TeamsResponse = IDL.Record({
       'data' : IDL.Opt(IDL.Vec(Team)),
       'error' : IDL.Opt(IDL.Text),
@@ -296,30 +603,40 @@ export const idlFactory = ({ IDL }) => {
       'error' : IDL.Opt(IDL.Text),
       'success' : IDL.Bool,
     });
-    const TransactionStatus = IDL.Variant({
-      'Failed' : IDL.Null,
-      'Cancelled' : IDL.Null,
-      'Completed' : IDL.Null,
-      'Pending' : IDL.Null,
+    const RewardTransactionsResponse = IDL.Record({
+      'data' : IDL.Opt(IDL.Vec(RewardTransaction)),
+      'error' : IDL.Opt(IDL.Text),
+      'success' : IDL.Bool,
     });
-    const TransactionType = IDL.Variant({
-      'ContestWin' : IDL.Null,
-      'Deposit' : IDL.Null,
-      'Bonus' : IDL.Null,
-      'Withdrawal' : IDL.Null,
-      'ContestEntry' : IDL.Null,
+    const WithdrawalLimits = IDL.Record({
+      'approval_threshold' : IDL.Nat64,
+      'per_day' : IDL.Nat64,
+      'per_transaction' : IDL.Nat64,
     });
-    const RewardTransaction = IDL.Record({
-      'id' : IDL.Text,
-      'status' : TransactionStatus,
-      'transaction_type' : TransactionType,
-      'created_at' : IDL.Nat64,
-      'user_id' : IDL.Principal,
-      'completed_at' : IDL.Opt(IDL.Nat64),
-      'amount' : IDL.Nat64,
+    const ContestEntryResult = IDL.Variant({
+      'Ok' : ContestEntry,
+      'Err' : FantasyError,
     });
-    const RewardTransactionsResponse = IDL.Record({
-      'data' : IDL.Opt(IDL.Vec(RewardTransaction)),
+    const SeasonEntryResponse = IDL.Record({
+      'data' : IDL.Opt(SeasonEntry),
+      'error' : IDL.Opt(IDL.Text),
+      'success' : IDL.Bool,
+    });
+    const MatchmakingOutcome = IDL.Variant({
+      'Queued' : MatchmakingTicket,
+      'Matched' : ContestEntry,
+    });
+    const MatchmakingResult = IDL.Variant({
+      'Ok' : MatchmakingOutcome,
+      'Err' : FantasyError,
+    });
+    const PlatformRakeResponse = IDL.Record({
+      'data' : IDL.Opt(IDL.Float64),
+      'error' : IDL.Opt(IDL.Text),
+      'success' : IDL.Bool,
+    });
+    const WithdrawalLimitsResponse = IDL.Record({
+      'data' : IDL.Opt(WithdrawalLimits),
       'error' : IDL.Opt(IDL.Text),
       'success' : IDL.Bool,
     });
@@ -328,14 +645,24 @@ export const idlFactory = ({ IDL }) => {
       'error' : IDL.Opt(IDL.Text),
       'success' : IDL.Bool,
     });
+    const PlayerStatsUpdate = IDL.Record({
+      'player_id' : IDL.Text,
+      'stats' : PlayerStats,
+    });
+    const PlayerScoresResponse = IDL.Record({
+      'data' : IDL.Opt(IDL.Vec(PlayerScore)),
+      'error' : IDL.Opt(IDL.Text),
+      'success' : IDL.Bool,
+    });
     return IDL.Service({
       'add_balance' : IDL.Func(
           [IDL.Principal, IDL.Nat64],
           [UserProfileResponse],
           [],
         ),
+      'add_role' : IDL.Func([IDL.Principal, Role], [RolesResponse], []),
       'add_scoring_rule' : IDL.Func(
-          [Sport, IDL.Text, IDL.Float64],
+          [Sport, IDL.Text, IDL.Float64, IDL.Opt(RuleCondition)],
           [ScoringRuleResponse],
           [],
         ),
@@ -344,12 +671,21 @@ export const idlFactory = ({ IDL }) => {
           [TournamentResponse],
           [],
         ),
+      'approve_withdrawal' : IDL.Func([IDL.Text], [WithdrawalResponse], []),
+      'audit_ledger' : IDL.Func([], [LedgerAudit], ['query']),
       'calculate_fantasy_team_points' : IDL.Func(
           [IDL.Vec(IDL.Text), IDL.Text, IDL.Text, IDL.Text],
           [IDL.Float64],
           ['query'],
         ),
+      'cancel_contest' : IDL.Func([IDL.Text, IDL.Text], [ContestResponse], []),
+      'cancel_private_contest' : IDL.Func([IDL.Text], [ContestResponse], []),
       'claim_reward' : IDL.Func([IDL.Text], [UserRewardResponse], []),
+      'clone_fantasy_team' : IDL.Func(
+          [IDL.Text, IDL.Text],
+          [FantasyTeamResponse],
+          [],
+        ),
       'create_bonus_reward' : IDL.Func(
           [IDL.Principal, IDL.Nat64, IDL.Text],
           [UserRewardResponse],
@@ -364,12 +700,14 @@ export const idlFactory = ({ IDL }) => {
             IDL.Nat64,
             ContestType,
             IDL.Nat64,
+            IDL.Opt(IDL.Nat32),
+            IDL.Opt(IDL.Nat32),
           ],
           [ContestResponse],
           [],
         ),
       'create_fantasy_team' : IDL.Func(
-          [IDL.Text, IDL.Text, IDL.Text, IDL.Vec(IDL.Text)],
+          [IDL.Text, IDL.Text, IDL.Text, IDL.Text, IDL.Vec(IDL.Text)],
           [FantasyTeamResponse],
           [],
         ),
@@ -388,11 +726,28 @@ export const idlFactory = ({ IDL }) => {
           [PlayerResponse],
           [],
         ),
+      'create_private_contest' : IDL.Func(
+          [IDL.Text, IDL.Text, IDL.Nat64, IDL.Nat32, IDL.Vec(PrizeDistribution)],
+          [PrivateContestResponse],
+          [],
+        ),
       'create_prize_pool' : IDL.Func(
           [IDL.Text, IDL.Nat64, IDL.Vec(PrizeDistribution)],
           [PrizePoolResponse],
           [],
         ),
+      'create_season_league' : IDL.Func(
+          [
+            IDL.Text,
+            IDL.Text,
+            IDL.Nat64,
+            IDL.Nat32,
+            IDL.Nat32,
+            IDL.Vec(PrizeDistribution),
+          ],
+          [SeasonLeagueResponse],
+          [],
+        ),
       'create_team' : IDL.Func([IDL.Text, IDL.Text], [TeamResponse], []),
       'create_tournament' : IDL.Func(
           [IDL.Text, Sport, IDL.Nat64, IDL.Nat64],
@@ -404,12 +759,17 @@ export const idlFactory = ({ IDL }) => {
           [UserProfileResponse],
           [],
         ),
-      'distribute_contest_rewards' : IDL.Func(
-          [IDL.Text, IDL.Vec(IDL.Tuple(IDL.Principal, IDL.Nat32, IDL.Nat64))],
-          [UserRewardsResponse],
+      'deposit_tokens' : IDL.Func(
+          [IDL.Nat64, IDL.Opt(IDL.Vec(IDL.Nat8))],
+          [RewardTransactionResponse],
           [],
         ),
       'finalize_contest' : IDL.Func([IDL.Text], [ContestEntriesResponse], []),
+      'get_account_balance' : IDL.Func(
+          [LedgerAccount],
+          [AccountBalanceResult],
+          ['query'],
+        ),
       'get_all_player_scores' : IDL.Func([], [IDL.Vec(PlayerScore)], ['query']),
       'get_all_players' : IDL.Func([], [IDL.Vec(Player)], ['query']),
       'get_all_teams' : IDL.Func([], [IDL.Vec(Team)], ['query']),
@@ -422,15 +782,37 @@ export const idlFactory = ({ IDL }) => {
           [IDL.Vec(ContestEntry)],
           ['query'],
         ),
+      'get_contest_escrows' : IDL.Func(
+          [],
+          [IDL.Vec(ContestEscrowSummary)],
+          ['query'],
+        ),
+      'get_contest_leaderboard' : IDL.Func(
+          [IDL.Text, IDL.Nat32, IDL.Nat32],
+          [LeaderboardResponse],
+          ['query'],
+        ),
       'get_contests_by_match' : IDL.Func(
           [IDL.Text],
           [IDL.Vec(Contest)],
           ['query'],
         ),
+      'get_deposit_account' : IDL.Func([], [Account], ['query']),
       'get_entry_count' : IDL.Func([], [IDL.Nat64], ['query']),
       'get_fantasy_team' : IDL.Func([IDL.Text], [FantasyTeamResponse], []),
+      'get_global_leaderboard' : IDL.Func(
+          [IDL.Opt(IDL.Text), LeaderboardMetric, IDL.Nat32, IDL.Nat32],
+          [LeaderboardResponse],
+          ['query'],
+        ),
+      'get_ledger_entries' : IDL.Func(
+          [IDL.Nat64, IDL.Nat64],
+          [IDL.Vec(LedgerEntry)],
+          ['query'],
+        ),
       'get_live_matches' : IDL.Func([], [IDL.Vec(Match)], ['query']),
       'get_live_tournaments' : IDL.Func([], [IDL.Vec(Tournament)], ['query']),
+      'get_lock_time' : IDL.Func([IDL.Text], [LockTimeResponse], ['query']),
       'get_match' : IDL.Func([IDL.Text], [MatchResponse], []),
       'get_match_count' : IDL.Func([], [IDL.Nat64], ['query']),
       'get_match_score' : IDL.Func([IDL.Text], [MatchScoreResponse], []),
@@ -444,12 +826,29 @@ export const idlFactory = ({ IDL }) => {
           [IDL.Vec(Match)],
           ['query'],
         ),
+      'get_my_matchmaking_tickets' : IDL.Func(
+          [],
+          [IDL.Vec(MatchmakingTicket)],
+          ['query'],
+        ),
+      'get_my_rank' : IDL.Func([IDL.Text], [LeaderboardResponse], ['query']),
+      'get_my_withdrawals' : IDL.Func(
+          [],
+          [IDL.Vec(WithdrawalRequest)],
+          ['query'],
+        ),
       'get_open_contests' : IDL.Func([], [IDL.Vec(Contest)], ['query']),
       'get_pending_rewards' : IDL.Func(
           [IDL.Opt(IDL.Principal)],
           [UserRewardsResponse],
           [],
         ),
+      'get_pending_withdrawals' : IDL.Func(
+          [],
+          [IDL.Vec(WithdrawalRequest)],
+          ['query'],
+        ),
+      'get_platform_rake' : IDL.Func([], [IDL.Float64], ['query']),
       'get_player' : IDL.Func([IDL.Text], [PlayerResponse], []),
       'get_player_score' : IDL.Func(
           [IDL.Text, IDL.Text],
@@ -462,11 +861,47 @@ export const idlFactory = ({ IDL }) => {
           ['query'],
         ),
       'get_players_by_team' : IDL.Func([IDL.Text], [IDL.Vec(Player)], ['query']),
+      'get_private_contest' : IDL.Func(
+          [IDL.Text],
+          [PrivateContestResponse],
+          ['query'],
+        ),
       'get_prize_pool' : IDL.Func([IDL.Text], [PrizePoolResponse], []),
       'get_reward_count' : IDL.Func([], [IDL.Nat64], ['query']),
       'get_score_count' : IDL.Func([], [IDL.Nat64], ['query']),
+      'get_scoring_rule_set' : IDL.Func(
+          [IDL.Text],
+          [ScoringRuleSetResponse],
+          ['query'],
+        ),
+      'get_scoring_rule_sets' : IDL.Func(
+          [Sport],
+          [IDL.Vec(ScoringRuleSet)],
+          ['query'],
+        ),
       'get_scoring_rules' : IDL.Func([Sport], [IDL.Vec(ScoringRule)], ['query']),
+      'get_season_leaderboard' : IDL.Func(
+          [IDL.Text],
+          [SeasonEntriesResponse],
+          ['query'],
+        ),
+      'get_season_league' : IDL.Func(
+          [IDL.Text],
+          [SeasonLeagueResponse],
+          ['query'],
+        ),
+      'get_season_leagues' : IDL.Func(
+          [IDL.Text],
+          [IDL.Vec(SeasonLeague)],
+          ['query'],
+        ),
       'get_team' : IDL.Func([IDL.Text], [TeamResponse], []),
+      'get_team_composition_rules' : IDL.Func(
+          [Sport],
+          [TeamCompositionRulesResponse],
+          ['query'],
+        ),
+      'get_token_ledger' : IDL.Func([], [IDL.Opt(IDL.Principal)], ['query']),
       'get_total_rewards_distributed' : IDL.Func([], [IDL.Nat64], ['query']),
       'get_tournament' : IDL.Func([IDL.Text], [TournamentResponse], []),
       'get_tournament_count' : IDL.Func([], [IDL.Nat64], ['query']),
@@ -509,14 +944,105 @@ export const idlFactory = ({ IDL }) => {
           [RewardTransactionsResponse],
           [],
         ),
-      'init' : IDL.Func([], [], []),
-      'join_contest' : IDL.Func([IDL.Text, IDL.Text], [ContestEntryResponse], []),
+      'get_withdrawal_limits' : IDL.Func([], [WithdrawalLimits], ['query']),
+      'join_contest' : IDL.Func([IDL.Text, IDL.Text], [ContestEntryResult], []),
+      'join_contest_by_code' : IDL.Func(
+          [IDL.Text, IDL.Text],
+          [ContestEntryResult],
+          [],
+        ),
+      'join_season_league' : IDL.Func(
+          [IDL.Text, IDL.Text, IDL.Text, IDL.Text, IDL.Vec(IDL.Text)],
+          [SeasonEntryResponse],
+          [],
+        ),
+      'list_roles' : IDL.Func([], [RolesResponse], ['query']),
       'mark_notification_read' : IDL.Func([IDL.Text], [NotificationResponse], []),
+      'notify_deposit' : IDL.Func([], [RewardTransactionResponse], []),
+      'publish_scoring_rule_set' : IDL.Func(
+          [Sport, IDL.Vec(ScoringRule)],
+          [ScoringRuleSetResponse],
+          [],
+        ),
+      'queue_head_to_head' : IDL.Func(
+          [IDL.Text, IDL.Text, IDL.Nat64],
+          [MatchmakingResult],
+          [],
+        ),
+      'recompute_contest_results' : IDL.Func(
+          [IDL.Text],
+          [ContestEntriesResponse],
+          ['query'],
+        ),
       'register_user' : IDL.Func(
           [IDL.Text, IDL.Opt(IDL.Text), IDL.Opt(IDL.Text)],
           [UserProfileResponse],
           [],
         ),
+      'reject_withdrawal' : IDL.Func(
+          [IDL.Text, IDL.Text],
+          [WithdrawalResponse],
+          [],
+        ),
+      'remove_role' : IDL.Func([IDL.Principal, Role], [RolesResponse], []),
+      'request_withdrawal' : IDL.Func(
+          [IDL.Nat64, Account],
+          [WithdrawalResponse],
+          [],
+        ),
+      'rescore_contest' : IDL.Func([IDL.Text], [ContestEntriesResponse], []),
+      'resume_deposit' : IDL.Func([], [RewardTransactionResponse], []),
+      'resume_withdrawal' : IDL.Func([IDL.Text], [WithdrawalResponse], []),
+      'retry_contest_refunds' : IDL.Func([IDL.Text], [ContestResponse], []),
+      'retry_reward' : IDL.Func([IDL.Text], [UserRewardResponse], []),
+      'retry_season_league_refunds' : IDL.Func(
+          [IDL.Text],
+          [SeasonLeagueResponse],
+          [],
+        ),
+      'retry_season_league_settlement' : IDL.Func(
+          [IDL.Text],
+          [SeasonLeagueResponse],
+          [],
+        ),
+      'review_pending_deposit' : IDL.Func(
+          [IDL.Principal, IDL.Bool],
+          [RewardTransactionResponse],
+          [],
+        ),
+      'review_withdrawal' : IDL.Func(
+          [IDL.Text, IDL.Bool],
+          [WithdrawalResponse],
+          [],
+        ),
+      'set_contest_ranking' : IDL.Func(
+          [IDL.Text, RankingConfig],
+          [ContestResponse],
+          [],
+        ),
+      'set_lock_buffer' : IDL.Func([IDL.Nat64], [LockTimeResponse], []),
+      'set_platform_rake' : IDL.Func([IDL.Float64], [PlatformRakeResponse], []),
+      'set_team_composition_rules' : IDL.Func(
+          [TeamCompositionRules],
+          [TeamCompositionRulesResponse],
+          [],
+        ),
+      'set_token_ledger' : IDL.Func(
+          [IDL.Principal],
+          [IDL.Opt(IDL.Principal)],
+          [],
+        ),
+      'set_withdrawal_limits' : IDL.Func(
+          [WithdrawalLimits],
+          [WithdrawalLimitsResponse],
+          [],
+        ),
+      'start_contest' : IDL.Func([IDL.Text], [ContestResponse], []),
+      'switch_entry_team' : IDL.Func(
+          [IDL.Text, IDL.Text],
+          [ContestEntryResult],
+          [],
+        ),
       'update_contest_status' : IDL.Func(
           [IDL.Text, ContestStatus],
           [ContestResponse],
@@ -527,6 +1053,11 @@ export const idlFactory = ({ IDL }) => {
           [ContestEntryResponse],
           [],
         ),
+      'update_fantasy_team' : IDL.Func(
+          [IDL.Text, IDL.Text, IDL.Text, IDL.Text, IDL.Vec(IDL.Text)],
+          [FantasyTeamResponse],
+          [],
+        ),
       'update_kyc_status' : IDL.Func(
           [IDL.Principal, KYCStatus],
           [UserProfileResponse],
@@ -562,6 +1093,16 @@ export const idlFactory = ({ IDL }) => {
           [PlayerScoreResponse],
           [],
         ),
+      'update_player_scores' : IDL.Func(
+          [IDL.Text, IDL.Vec(PlayerStatsUpdate)],
+          [PlayerScoresResponse],
+          [],
+        ),
+      'update_season_team' : IDL.Func(
+          [IDL.Text, IDL.Text, IDL.Text, IDL.Vec(IDL.Text)],
+          [SeasonEntryResponse],
+          [],
+        ),
       'update_tournament_status' : IDL.Func(
           [IDL.Text, TournamentStatus],
           [TournamentResponse],
@@ -574,4 +1115,10 @@ export const idlFactory = ({ IDL }) => {
         ),
     });
   };
-  export const init = ({ IDL }) => { return []; };
\ No newline at end of file
+  export const init = ({ IDL }) => {
+    const InitPayload = IDL.Record({
+      'token_ledger' : IDL.Opt(IDL.Principal),
+      'admin_principal' : IDL.Opt(IDL.Principal),
+    });
+    return [IDL.Opt(InitPayload)];
+  };
\ No newline at end of file
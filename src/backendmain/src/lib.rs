@@ -1,19 +1,33 @@
 // Unified backend lib.rs
 
 // --- Shared Types ---
-use candid::{CandidType, Deserialize, Principal};
+use candid::{CandidType, Decode, Deserialize, Encode, Nat, Principal};
 use serde::Serialize;
-use std::collections::HashMap;
-use ic_cdk::{api::caller, init, post_upgrade, pre_upgrade, query, update};
+use std::borrow::Cow;
+use ic_cdk::{api::call::CallResult, init, post_upgrade, query, update};
 use ic_stable_structures::{
     memory_manager::{MemoryId, MemoryManager, VirtualMemory},
-    DefaultMemoryImpl, StableBTreeMap, StableCell,
+    storable::Bound,
+    DefaultMemoryImpl, StableBTreeMap, StableCell, Storable,
 };
+use ic_cdk_timers::TimerId;
 use std::cell::RefCell;
+use std::collections::{BTreeMap, HashSet};
 use std::thread::LocalKey;
+use std::time::Duration;
+
+// Unit tests run outside a canister, so they swap the system API for a clock,
+// caller and timer they control (see `test_env`).
+#[cfg(not(test))]
+use ic_cdk::api::{caller, time};
+#[cfg(not(test))]
+use ic_cdk_timers::{clear_timer, set_timer};
+#[cfg(test)]
+use test_env::{caller, clear_timer, set_timer, time};
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 type StableMap<K, V> = StableBTreeMap<K, V, Memory>;
+type IdCounter = LocalKey<RefCell<Option<StableCell<u64, Memory>>>>;
 
 // Additional types for rewards
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
@@ -26,6 +40,7 @@ pub struct UserReward {
     pub status: RewardStatus,
     pub created_at: u64,
     pub claimed_at: Option<u64>,
+    pub transaction_id: Option<String>, // the reward's RewardTransaction
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug, Eq, Hash, PartialEq)]
@@ -53,6 +68,10 @@ pub enum TransactionType {
     Withdrawal,
     Deposit,
     Bonus,
+    Refund,
+    HouseShortfall,
+    HouseRevenue,
+    Rake,
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug, Eq, Hash, PartialEq)]
@@ -68,6 +87,33 @@ pub struct ScoringRule {
     pub action: String,
     pub points: f64,
     pub sport: Sport,
+    // Without a condition the rule awards `points` per unit of the stat; with
+    // one it awards `points` once when the stat satisfies the condition.
+    pub condition: Option<RuleCondition>,
+}
+
+// Published rule sets are immutable; contests pin the set they were created under.
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct ScoringRuleSet {
+    pub id: String,
+    pub sport: Sport,
+    pub version: u32,
+    pub rules: Vec<ScoringRule>,
+    pub created_at: u64,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct RuleCondition {
+    pub comparison: Comparison,
+    pub threshold: f64,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Comparison {
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
 }
 
 // User Management Types
@@ -81,6 +127,7 @@ pub struct UserProfile {
     pub balance: u64, // in tokens
     pub created_at: u64,
     pub updated_at: u64,
+    pub deposit_subaccount: Option<Vec<u8>>, // ICRC-1 subaccount of this canister
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
@@ -103,7 +150,7 @@ pub struct Tournament {
     pub matches: Vec<Match>,
 }
 
-#[derive(CandidType, Deserialize, Serialize, Clone, Debug, Eq, Hash, PartialEq)]
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
 pub enum Sport {
     Cricket,
     Football,
@@ -196,9 +243,327 @@ pub struct Contest {
     pub status: ContestStatus,
     pub created_at: u64,
     pub start_time: u64,
+    pub scoring_rule_set_id: Option<String>,
+    pub ranking: Option<RankingConfig>,
+    // Entries needed at start time for a non-guaranteed contest to go ahead
+    pub min_entries: Option<u32>,
+    // Entries a single user may hold, each with a different fantasy team
+    pub max_entries_per_user: Option<u32>,
+    pub creator: Option<Principal>,
+    pub visibility: Option<ContestVisibility>,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum ContestVisibility {
+    Public,
+    Private,
+}
+
+// Ledger Types
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
+pub enum LedgerAccount {
+    UserWallet(Principal),
+    ContestEscrow(String), // contest or season league id
+    MatchmakingEscrow(String), // match id, holds stakes of queued users
+    House,
+    Promo,
+    Treasury, // tokens held by the canister on the ICRC ledger
+    PendingWithdrawals, // funds held for withdrawals not yet sent
+}
+
+// One balanced movement: `amount` leaves `from` and arrives in `to`
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct LedgerEntry {
+    pub id: u64,
+    pub from: LedgerAccount,
+    pub to: LedgerAccount,
+    pub amount: u64,
+    pub transaction_type: TransactionType,
+    pub created_at: u64,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct AccountBalance {
+    pub account: LedgerAccount,
+    pub balance: i128,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct LedgerAudit {
+    pub entry_count: u64,
+    pub account_count: u64,
+    pub total_balance: i128, // zero when every entry balances
+    pub mismatched_accounts: Vec<LedgerAccount>,
+    pub balanced: bool,
+}
+
+// Escrow held for a contest against the prize pool it advertises
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct ContestEscrowSummary {
+    pub contest_id: String,
+    pub status: ContestStatus,
+    pub advertised_pool: u64,
+    pub prize_pool: u64, // from the contest's PrizePool, else the advertised pool
+    pub collected: u64,
+    pub escrow_balance: i128,
+    pub rake: Option<u64>,
+    pub shortfall: u64, // prize pool not yet covered by the escrow
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct PlatformRakeResponse {
+    pub success: bool,
+    pub data: Option<f64>,
+    pub error: Option<String>,
+}
+
+// ICRC-1/ICRC-2 Types, as defined by the token standards
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct Account {
+    pub owner: Principal,
+    pub subaccount: Option<Vec<u8>>,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct TransferArg {
+    pub from_subaccount: Option<Vec<u8>>,
+    pub to: Account,
+    pub fee: Option<Nat>,
+    pub created_at_time: Option<u64>,
+    pub memo: Option<Vec<u8>>,
+    pub amount: Nat,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub enum TransferError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    TemporarilyUnavailable,
+    Duplicate { duplicate_of: Nat },
+    GenericError { error_code: Nat, message: String },
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct TransferFromArgs {
+    pub spender_subaccount: Option<Vec<u8>>,
+    pub from: Account,
+    pub to: Account,
+    pub amount: Nat,
+    pub fee: Option<Nat>,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub enum TransferFromError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    InsufficientAllowance { allowance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+// ICRC-3 Types, for looking transfers up in the ledger's block log
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum Icrc3Value {
+    Blob(Vec<u8>),
+    Text(String),
+    Nat(Nat),
+    Int(candid::Int),
+    Array(Vec<Icrc3Value>),
+    Map(Vec<(String, Icrc3Value)>),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct GetBlocksArgs {
+    pub start: Nat,
+    pub length: Nat,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct BlockWithId {
+    pub id: Nat,
+    pub block: Icrc3Value,
+}
+
+// Blocks moved to archive canisters are left out, so they are never searched
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct GetBlocksResult {
+    pub log_length: Nat,
+    pub blocks: Vec<BlockWithId>,
+}
+
+// A deposit sent to the token ledger whose outcome is not settled yet
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct PendingDeposit {
+    pub transaction_id: String,
+    pub amount: u64,
+    pub created_at_time: u64, // sent to the ledger so a retry is deduplicated
+    pub source: DepositSource,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub enum DepositSource {
+    Allowance { from_subaccount: Option<Vec<u8>> },
+    // Swept from the user's deposit subaccount; `balance_before` includes the fee
+    Subaccount { balance_before: u64, fee: u64 },
+}
+
+// Withdrawal Types
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum WithdrawalStatus {
+    PendingApproval,
+    Processing,
+    Completed,
+    Rejected,
+    Failed,
+}
+
+// Keyed by the id of the withdrawal's reward transaction
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct WithdrawalRequest {
+    pub id: String,
+    pub user_id: Principal,
+    pub amount: u64,
+    pub destination: Account,
+    pub status: WithdrawalStatus,
+    pub created_at: u64,
+    pub decided_by: Option<Principal>,
+    pub completed_at: Option<u64>,
+    pub error: Option<String>, // failure or rejection reason
+    // Fixed when the transfer is first sent, so a resend is deduplicated
+    pub sent_at: Option<u64>,
+    pub fee: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct WithdrawalLimits {
+    pub per_transaction: u64,
+    pub per_day: u64,
+    pub approval_threshold: u64, // requests of this size or more need an admin
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct WithdrawalResponse {
+    pub success: bool,
+    pub data: Option<WithdrawalRequest>,
+    pub error: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct WithdrawalLimitsResponse {
+    pub success: bool,
+    pub data: Option<WithdrawalLimits>,
+    pub error: Option<String>,
+}
+
+// Matchmaking Types
+// A user waiting for a head-to-head opponent; the stake is already held
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct MatchmakingTicket {
+    pub user_id: Principal,
+    pub match_id: String,
+    pub fantasy_team_id: String,
+    pub stake: u64,
+    pub created_at: u64,
+    pub refund_failed: Option<bool>, // kept queued so the refund is retried
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub enum MatchmakingOutcome {
+    Queued(MatchmakingTicket),
+    Matched(ContestEntry),
+}
+
+// Season League Types
+// A league that runs across every match of a tournament. Members keep one squad
+// for the season and pay out once the tournament is completed.
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct SeasonLeague {
+    pub id: String,
+    pub name: String,
+    pub tournament_id: String,
+    pub entry_fee: u64,
+    pub max_members: u32,
+    pub member_count: u32,
+    pub transfers_per_matchday: u32,
+    // Percentages of the collected entry fees; amounts are fixed at payout
+    pub distribution: Vec<PrizeDistribution>,
+    pub scoring_rule_set_id: Option<String>,
+    pub status: SeasonLeagueStatus,
+    pub created_at: u64,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum SeasonLeagueStatus {
+    Open,
+    Completed,
+    Cancelled,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct SeasonEntry {
+    pub id: String,
+    pub league_id: String,
+    pub user_id: Principal,
+    pub team: FantasyTeam, // current squad, used for upcoming matches
+    pub matchdays: Vec<SeasonMatchday>,
+    pub total_points: f64,
+    // Upcoming match the transfer count applies to
+    pub transfer_window: Option<String>,
+    pub transfers_used: u32,
+    pub rank: Option<u32>,
+    pub prize: Option<u64>,
+    pub joined_at: u64,
+    pub refunded_at: Option<u64>, // set once the entry fee of a cancelled league is returned
+}
+
+// The squad a season entry fielded for one match, frozen when the match locked
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct SeasonMatchday {
+    pub match_id: String,
+    pub team: FantasyTeam,
+    pub points: f64,
 }
 
+// A private league together with the code its creator shares to invite others
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct PrivateContest {
+    pub contest: Contest,
+    pub invite_code: String,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, Default)]
+pub struct RankingConfig {
+    pub method: RankingMethod,
+    // Applied in order to separate entries with equal points; entries still
+    // equal afterwards share a rank and split the prizes of their positions.
+    pub tie_breakers: Vec<TieBreaker>,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, Default, Eq, PartialEq)]
+pub enum RankingMethod {
+    // 1, 1, 3
+    #[default]
+    Standard,
+    // 1, 1, 2
+    Dense,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, Eq, PartialEq)]
+pub enum TieBreaker {
+    EarliestEntry,
+    CaptainPoints,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, Eq, PartialEq)]
 pub enum ContestType {
     HeadToHead,
     MultiPlayer,
@@ -225,12 +590,14 @@ pub struct ContestEntry {
     pub rank: Option<u32>,
     pub prize: Option<u64>,
     pub created_at: u64,
+    pub refunded_at: Option<u64>, // set once the entry fee of a cancelled contest is returned
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct FantasyTeam {
     pub id: String,
     pub name: String,
+    pub match_id: Option<String>,
     pub captain_id: String,
     pub vice_captain_id: String,
     pub players: Vec<String>, // Player IDs
@@ -238,6 +605,43 @@ pub struct FantasyTeam {
     pub total_price: u64,
 }
 
+// Team Composition Types
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct PositionLimit {
+    pub position: PlayerPosition,
+    pub min: u32,
+    pub max: u32,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct TeamCompositionRules {
+    pub sport: Sport,
+    pub min_squad_size: u32,
+    pub max_squad_size: u32,
+    pub position_limits: Vec<PositionLimit>,
+    pub max_players_per_team: u32, // from a single real team
+    pub min_credits: u64,
+    pub max_credits: u64,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum CompositionRule {
+    SquadSize,
+    DuplicatePlayer,
+    UnknownPlayer,
+    PlayerNotInMatch,
+    Captaincy,
+    PositionLimit(PlayerPosition),
+    MaxPlayersPerTeam,
+    CreditBudget,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct TeamRuleViolation {
+    pub rule: CompositionRule,
+    pub message: String,
+}
+
 // Scoring Types
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct PlayerScore {
@@ -249,6 +653,12 @@ pub struct PlayerScore {
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct PlayerStatsUpdate {
+    pub player_id: String,
+    pub stats: PlayerStats,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, Default)]
 pub struct PlayerStats {
     // Cricket stats
     pub runs: Option<u32>,
@@ -277,17 +687,23 @@ pub struct PlayerStats {
 }
 
 // Rewards Types
+// Prize tiers for ranks that no entry reaches are not redistributed; their
+// amounts stay in the contest's escrow and go to the house at settlement.
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct PrizePool {
     pub contest_id: String,
     pub total_amount: u64,
     pub distribution: Vec<PrizeDistribution>,
     pub distributed: bool,
+    pub rake: Option<u64>, // taken by the platform when the pool was paid out
 }
 
+// A prize tier covering ranks `rank..=rank_end` (just `rank` when `rank_end`
+// is unset). `percentage` and `amount` apply to each rank in the tier.
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct PrizeDistribution {
     pub rank: u32,
+    pub rank_end: Option<u32>,
     pub percentage: f64,
     pub amount: u64,
 }
@@ -298,7 +714,9 @@ pub enum FantasyError {
     UserNotFound,
     TournamentNotFound,
     ContestNotFound,
+    TeamNotFound,
     ContestFull,
+    AlreadyJoined,
     InsufficientBalance,
     InvalidTeam,
     ContestAlreadyStarted,
@@ -365,6 +783,13 @@ pub struct MatchResponse {
     pub error: Option<String>,
 }
 
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct LockTimeResponse {
+    pub success: bool,
+    pub data: Option<u64>,
+    pub error: Option<String>,
+}
+
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct MatchScoreResponse {
     pub success: bool,
@@ -379,6 +804,13 @@ pub struct ContestResponse {
     pub error: Option<String>,
 }
 
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct PrivateContestResponse {
+    pub success: bool,
+    pub data: Option<PrivateContest>,
+    pub error: Option<String>,
+}
+
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct ContestEntryResponse {
     pub success: bool,
@@ -386,6 +818,27 @@ pub struct ContestEntryResponse {
     pub error: Option<String>,
 }
 
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct SeasonLeagueResponse {
+    pub success: bool,
+    pub data: Option<SeasonLeague>,
+    pub error: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct SeasonEntryResponse {
+    pub success: bool,
+    pub data: Option<SeasonEntry>,
+    pub error: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct SeasonEntriesResponse {
+    pub success: bool,
+    pub data: Option<Vec<SeasonEntry>>,
+    pub error: Option<String>,
+}
+
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct ContestEntriesResponse {
     pub success: bool,
@@ -400,6 +853,13 @@ pub struct PlayerScoreResponse {
     pub error: Option<String>,
 }
 
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct PlayerScoresResponse {
+    pub success: bool,
+    pub data: Option<Vec<PlayerScore>>,
+    pub error: Option<String>,
+}
+
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct ScoringRuleResponse {
     pub success: bool,
@@ -407,6 +867,13 @@ pub struct ScoringRuleResponse {
     pub error: Option<String>,
 }
 
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct ScoringRuleSetResponse {
+    pub success: bool,
+    pub data: Option<ScoringRuleSet>,
+    pub error: Option<String>,
+}
+
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct PrizePoolResponse {
     pub success: bool,
@@ -435,11 +902,26 @@ pub struct RewardTransactionsResponse {
     pub error: Option<String>,
 }
 
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct RewardTransactionResponse {
+    pub success: bool,
+    pub data: Option<RewardTransaction>,
+    pub error: Option<String>,
+}
+
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct FantasyTeamResponse {
     pub success: bool,
     pub data: Option<FantasyTeam>,
     pub error: Option<String>,
+    pub violations: Option<Vec<TeamRuleViolation>>,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct TeamCompositionRulesResponse {
+    pub success: bool,
+    pub data: Option<TeamCompositionRules>,
+    pub error: Option<String>,
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
@@ -465,6 +947,25 @@ pub struct LeaderboardEntry {
     pub prize: Option<u64>,
 }
 
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct LeaderboardPage {
+    pub entries: Vec<LeaderboardEntry>,
+    pub total: u64, // rows in the whole leaderboard
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum LeaderboardMetric {
+    Winnings,
+    Points,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct LeaderboardResponse {
+    pub success: bool,
+    pub data: Option<LeaderboardPage>,
+    pub error: Option<String>,
+}
+
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct Notification {
     pub id: String,
@@ -481,172 +982,596 @@ pub enum NotificationType {
     ContestResult,
     PrizeWon,
     ContestReminder,
+    ContestCancelled,
     SystemUpdate,
 }
 
+// Access Control Types
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
+pub enum Role {
+    Admin,
+    Operator,
+    ScoreFeeder,
+    KYCReviewer,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct RoleAssignment {
+    pub principal: Principal,
+    pub roles: Vec<Role>,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct RolesResponse {
+    pub success: bool,
+    pub data: Option<Vec<RoleAssignment>>,
+    pub error: Option<String>,
+}
+
 // Constants
 pub const MAX_TEAM_SIZE: u32 = 11;
 pub const MIN_TEAM_SIZE: u32 = 11;
 pub const MAX_TEAM_PRICE: u64 = 100; // in tokens
 pub const MIN_TEAM_PRICE: u64 = 80; // in tokens
+pub const CAPTAIN_MULTIPLIER: f64 = 2.0;
+pub const VICE_CAPTAIN_MULTIPLIER: f64 = 1.5;
+pub const DEFAULT_LOCK_BUFFER_SECONDS: u64 = 0;
+pub const DEFAULT_RAKE_PERCENTAGE: f64 = 0.0;
+pub const MAX_RAKE_PERCENTAGE: f64 = 50.0;
+pub const HEAD_TO_HEAD_SPOTS: u32 = 2;
+pub const DEFAULT_MIN_ENTRIES: u32 = 2;
+// Platform limits for user-created private leagues
+pub const MIN_PRIVATE_SPOTS: u32 = 2;
+pub const MAX_PRIVATE_SPOTS: u32 = 100;
+pub const MAX_PRIVATE_ENTRY_FEE: u64 = 10_000; // in tokens
+pub const MAX_OPEN_PRIVATE_CONTESTS_PER_USER: usize = 10;
+pub const INVITE_CODE_LENGTH: usize = 8;
+pub const MAX_LEADERBOARD_PAGE_SIZE: u32 = 100;
+pub const REGISTRATION_GRANT: u64 = 1000; // in tokens, paid from the promo pool
+pub const DEFAULT_WITHDRAWAL_LIMITS: WithdrawalLimits = WithdrawalLimits {
+    per_transaction: 10_000,
+    per_day: 50_000,
+    approval_threshold: 5_000,
+};
+pub const WITHDRAWAL_LIMIT_WINDOW_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+// How far back the token ledger's block log is searched for a transfer
+pub const LEDGER_LOG_PAGE_SIZE: u64 = 1_000;
+pub const MAX_LEDGER_LOG_PAGES: u32 = 10;
+// Allowed gap between a transfer's created_at_time and its block's timestamp
+pub const LEDGER_TIME_DRIFT_NANOS: u64 = 60 * 1_000_000_000;
+// No 0/O or 1/I so codes can be read out without confusion
+const INVITE_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+pub const SCORING_ACTIONS: [&str; 19] = [
+    "run", "wicket", "catch", "stumping", "run_out", "over", "maiden_over", "economy",
+    "goal", "assist", "clean_sheet", "save", "yellow_card", "red_card",
+    "point", "rebound", "block", "steal", "turnover",
+];
+
+// --- Stable Storage ---
+// Every domain type is stored candid-encoded in stable memory so that state
+// survives canister upgrades.
+macro_rules! impl_candid_storable {
+    ($($t:ty),* $(,)?) => {
+        $(
+            impl Storable for $t {
+                fn to_bytes(&self) -> Cow<'_, [u8]> {
+                    Cow::Owned(Encode!(self).unwrap())
+                }
+
+                fn from_bytes(bytes: Cow<[u8]>) -> Self {
+                    Decode!(bytes.as_ref(), Self).unwrap()
+                }
+
+                const BOUND: Bound = Bound::Unbounded;
+            }
+        )*
+    };
+}
+
+// Wrapper for list values (index maps, per-user collections) since `Vec<T>`
+// cannot implement `Storable` directly.
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct StorableVec<T>(pub Vec<T>);
+
+impl<T> Default for StorableVec<T> {
+    fn default() -> Self {
+        StorableVec(Vec::new())
+    }
+}
+
+impl<T> Storable for StorableVec<T>
+where
+    T: CandidType + for<'de> Deserialize<'de>,
+{
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl_candid_storable!(
+    UserProfile,
+    Notification,
+    Tournament,
+    Match,
+    Team,
+    Player,
+    Contest,
+    ContestEntry,
+    FantasyTeam,
+    PlayerScore,
+    MatchScore,
+    PrizePool,
+    RewardTransaction,
+    ScoringRuleSet,
+    Sport,
+    TeamCompositionRules,
+    SeasonLeague,
+    SeasonEntry,
+    MatchmakingTicket,
+    LedgerAccount,
+    LedgerEntry,
+    AccountBalance,
+    WithdrawalRequest,
+    WithdrawalLimits,
+    PendingDeposit,
+);
+
+// Memory ids 0-10 hold the id counters, domain maps start at 11.
+const USERS_MEMORY_ID: MemoryId = MemoryId::new(11);
+const USERNAMES_MEMORY_ID: MemoryId = MemoryId::new(12);
+const NOTIFICATIONS_MEMORY_ID: MemoryId = MemoryId::new(13);
+const TOURNAMENTS_MEMORY_ID: MemoryId = MemoryId::new(14);
+const MATCHES_MEMORY_ID: MemoryId = MemoryId::new(15);
+const TEAMS_MEMORY_ID: MemoryId = MemoryId::new(16);
+const PLAYERS_MEMORY_ID: MemoryId = MemoryId::new(17);
+const CONTESTS_MEMORY_ID: MemoryId = MemoryId::new(18);
+const CONTEST_ENTRIES_MEMORY_ID: MemoryId = MemoryId::new(19);
+const USER_CONTESTS_MEMORY_ID: MemoryId = MemoryId::new(20);
+const CONTEST_ENTRIES_BY_CONTEST_MEMORY_ID: MemoryId = MemoryId::new(21);
+const PLAYER_SCORES_MEMORY_ID: MemoryId = MemoryId::new(22);
+const MATCH_SCORES_MEMORY_ID: MemoryId = MemoryId::new(23);
+const SCORING_RULES_MEMORY_ID: MemoryId = MemoryId::new(24);
+const PRIZE_POOLS_MEMORY_ID: MemoryId = MemoryId::new(25);
+const USER_REWARDS_MEMORY_ID: MemoryId = MemoryId::new(26);
+const REWARD_HISTORY_MEMORY_ID: MemoryId = MemoryId::new(27);
+const FANTASY_TEAMS_MEMORY_ID: MemoryId = MemoryId::new(28);
+const USER_FANTASY_TEAMS_MEMORY_ID: MemoryId = MemoryId::new(29);
+const ROLES_MEMORY_ID: MemoryId = MemoryId::new(30);
+const SCORING_RULE_SETS_MEMORY_ID: MemoryId = MemoryId::new(31);
+const LOCK_BUFFER_MEMORY_ID: MemoryId = MemoryId::new(32);
+const TEAM_COMPOSITION_RULES_MEMORY_ID: MemoryId = MemoryId::new(33);
+const INVITE_CODES_MEMORY_ID: MemoryId = MemoryId::new(34);
+const SEASON_LEAGUES_MEMORY_ID: MemoryId = MemoryId::new(35);
+const SEASON_ENTRIES_MEMORY_ID: MemoryId = MemoryId::new(36);
+const MATCHMAKING_QUEUE_MEMORY_ID: MemoryId = MemoryId::new(37);
+const LEDGER_MEMORY_ID: MemoryId = MemoryId::new(38);
+const ACCOUNT_BALANCES_MEMORY_ID: MemoryId = MemoryId::new(39);
+const PLATFORM_RAKE_MEMORY_ID: MemoryId = MemoryId::new(40);
+const TOKEN_LEDGER_MEMORY_ID: MemoryId = MemoryId::new(41);
+const WITHDRAWALS_MEMORY_ID: MemoryId = MemoryId::new(42);
+const WITHDRAWAL_LIMITS_MEMORY_ID: MemoryId = MemoryId::new(43);
+const PENDING_DEPOSITS_MEMORY_ID: MemoryId = MemoryId::new(44);
+const CONTESTS_BY_MATCH_MEMORY_ID: MemoryId = MemoryId::new(45);
+
+fn get_memory(id: MemoryId) -> Memory {
+    MEMORY_MANAGER.with(|mm| mm.borrow().get(id))
+}
 
 // --- All Module Statics ---
 thread_local! {
     static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
     
-    // User Management - Using simpler storage for complex types
-    static USERS: RefCell<HashMap<Principal, UserProfile>> = RefCell::new(HashMap::new());
-    static USERNAMES: RefCell<HashMap<String, Principal>> = RefCell::new(HashMap::new());
-    static NOTIFICATIONS: RefCell<HashMap<String, Notification>> = RefCell::new(HashMap::new());
-    static NEXT_NOTIFICATION_ID: RefCell<Option<StableCell<u64, Memory>>> = RefCell::new(None);
-    
-    // Tournament Management - Using simpler storage for complex types
-    static TOURNAMENTS: RefCell<HashMap<String, Tournament>> = RefCell::new(HashMap::new());
-    static MATCHES: RefCell<HashMap<String, Match>> = RefCell::new(HashMap::new());
-    static TEAMS: RefCell<HashMap<String, Team>> = RefCell::new(HashMap::new());
-    static PLAYERS: RefCell<HashMap<String, Player>> = RefCell::new(HashMap::new());
-    static NEXT_TOURNAMENT_ID: RefCell<Option<StableCell<u64, Memory>>> = RefCell::new(None);
-    static NEXT_MATCH_ID: RefCell<Option<StableCell<u64, Memory>>> = RefCell::new(None);
-    static NEXT_TEAM_ID: RefCell<Option<StableCell<u64, Memory>>> = RefCell::new(None);
-    static NEXT_PLAYER_ID: RefCell<Option<StableCell<u64, Memory>>> = RefCell::new(None);
-    
-    // Contest Management - Using simpler storage for complex types
-    static CONTESTS: RefCell<HashMap<String, Contest>> = RefCell::new(HashMap::new());
-    static CONTEST_ENTRIES: RefCell<HashMap<String, ContestEntry>> = RefCell::new(HashMap::new());
-    static USER_CONTESTS: RefCell<HashMap<Principal, Vec<String>>> = RefCell::new(HashMap::new());
-    static CONTEST_ENTRIES_BY_CONTEST: RefCell<HashMap<String, Vec<String>>> = RefCell::new(HashMap::new());
-    static NEXT_CONTEST_ID: RefCell<Option<StableCell<u64, Memory>>> = RefCell::new(None);
-    static NEXT_ENTRY_ID: RefCell<Option<StableCell<u64, Memory>>> = RefCell::new(None);
-    
-    // Scoring Management - Using simpler storage for complex types
-    static PLAYER_SCORES: RefCell<HashMap<String, PlayerScore>> = RefCell::new(HashMap::new());
-    static MATCH_SCORES: RefCell<HashMap<String, MatchScore>> = RefCell::new(HashMap::new());
-    static SCORING_RULES: RefCell<HashMap<Sport, Vec<ScoringRule>>> = RefCell::new(HashMap::new());
-    static NEXT_SCORE_ID: RefCell<Option<StableCell<u64, Memory>>> = RefCell::new(None);
-    
-    // Rewards Management - Using simpler storage for complex types
-    static PRIZE_POOLS: RefCell<HashMap<String, PrizePool>> = RefCell::new(HashMap::new());
-    static USER_REWARDS: RefCell<HashMap<Principal, Vec<UserReward>>> = RefCell::new(HashMap::new());
-    static REWARD_HISTORY: RefCell<HashMap<String, RewardTransaction>> = RefCell::new(HashMap::new());
-    static NEXT_REWARD_ID: RefCell<Option<StableCell<u64, Memory>>> = RefCell::new(None);
-    static NEXT_TRANSACTION_ID: RefCell<Option<StableCell<u64, Memory>>> = RefCell::new(None);
-    
-    // Team Management - Using simpler storage for complex types
-    static FANTASY_TEAMS: RefCell<HashMap<String, FantasyTeam>> = RefCell::new(HashMap::new());
-    static USER_FANTASY_TEAMS: RefCell<HashMap<Principal, Vec<String>>> = RefCell::new(HashMap::new());
-    static NEXT_FANTASY_TEAM_ID: RefCell<Option<StableCell<u64, Memory>>> = RefCell::new(None);
+    // User Management
+    static USERS: RefCell<StableMap<Principal, UserProfile>> = RefCell::new(StableMap::init(get_memory(USERS_MEMORY_ID)));
+    static USERNAMES: RefCell<StableMap<String, Principal>> = RefCell::new(StableMap::init(get_memory(USERNAMES_MEMORY_ID)));
+    static NOTIFICATIONS: RefCell<StableMap<String, Notification>> = RefCell::new(StableMap::init(get_memory(NOTIFICATIONS_MEMORY_ID)));
+    static NEXT_NOTIFICATION_ID: RefCell<Option<StableCell<u64, Memory>>> = const { RefCell::new(None) };
+    
+    // Tournament Management
+    static TOURNAMENTS: RefCell<StableMap<String, Tournament>> = RefCell::new(StableMap::init(get_memory(TOURNAMENTS_MEMORY_ID)));
+    static MATCHES: RefCell<StableMap<String, Match>> = RefCell::new(StableMap::init(get_memory(MATCHES_MEMORY_ID)));
+    static TEAMS: RefCell<StableMap<String, Team>> = RefCell::new(StableMap::init(get_memory(TEAMS_MEMORY_ID)));
+    static PLAYERS: RefCell<StableMap<String, Player>> = RefCell::new(StableMap::init(get_memory(PLAYERS_MEMORY_ID)));
+    static NEXT_TOURNAMENT_ID: RefCell<Option<StableCell<u64, Memory>>> = const { RefCell::new(None) };
+    static NEXT_MATCH_ID: RefCell<Option<StableCell<u64, Memory>>> = const { RefCell::new(None) };
+    // Seconds before a match's start time at which teams and entries lock
+    static LOCK_BUFFER_SECONDS: RefCell<StableCell<u64, Memory>> = RefCell::new(StableCell::init(get_memory(LOCK_BUFFER_MEMORY_ID), DEFAULT_LOCK_BUFFER_SECONDS).unwrap());
+    static NEXT_TEAM_ID: RefCell<Option<StableCell<u64, Memory>>> = const { RefCell::new(None) };
+    static NEXT_PLAYER_ID: RefCell<Option<StableCell<u64, Memory>>> = const { RefCell::new(None) };
+    
+    // Contest Management
+    static CONTESTS: RefCell<StableMap<String, Contest>> = RefCell::new(StableMap::init(get_memory(CONTESTS_MEMORY_ID)));
+    static CONTEST_ENTRIES: RefCell<StableMap<String, ContestEntry>> = RefCell::new(StableMap::init(get_memory(CONTEST_ENTRIES_MEMORY_ID)));
+    static USER_CONTESTS: RefCell<StableMap<Principal, StorableVec<String>>> = RefCell::new(StableMap::init(get_memory(USER_CONTESTS_MEMORY_ID)));
+    static CONTEST_ENTRIES_BY_CONTEST: RefCell<StableMap<String, StorableVec<String>>> = RefCell::new(StableMap::init(get_memory(CONTEST_ENTRIES_BY_CONTEST_MEMORY_ID)));
+    // Match id -> ids of the contests played on it
+    static CONTESTS_BY_MATCH: RefCell<StableMap<String, StorableVec<String>>> = RefCell::new(StableMap::init(get_memory(CONTESTS_BY_MATCH_MEMORY_ID)));
+    static NEXT_CONTEST_ID: RefCell<Option<StableCell<u64, Memory>>> = const { RefCell::new(None) };
+    static NEXT_ENTRY_ID: RefCell<Option<StableCell<u64, Memory>>> = const { RefCell::new(None) };
+    
+    // Scoring Management
+    static PLAYER_SCORES: RefCell<StableMap<String, PlayerScore>> = RefCell::new(StableMap::init(get_memory(PLAYER_SCORES_MEMORY_ID)));
+    static MATCH_SCORES: RefCell<StableMap<String, MatchScore>> = RefCell::new(StableMap::init(get_memory(MATCH_SCORES_MEMORY_ID)));
+    static SCORING_RULES: RefCell<StableMap<Sport, StorableVec<ScoringRule>>> = RefCell::new(StableMap::init(get_memory(SCORING_RULES_MEMORY_ID)));
+    static SCORING_RULE_SETS: RefCell<StableMap<String, ScoringRuleSet>> = RefCell::new(StableMap::init(get_memory(SCORING_RULE_SETS_MEMORY_ID)));
+    static TEAM_COMPOSITION_RULES: RefCell<StableMap<Sport, TeamCompositionRules>> = RefCell::new(StableMap::init(get_memory(TEAM_COMPOSITION_RULES_MEMORY_ID)));
+    // Invite code -> contest id
+    static INVITE_CODES: RefCell<StableMap<String, String>> = RefCell::new(StableMap::init(get_memory(INVITE_CODES_MEMORY_ID)));
+    static SEASON_LEAGUES: RefCell<StableMap<String, SeasonLeague>> = RefCell::new(StableMap::init(get_memory(SEASON_LEAGUES_MEMORY_ID)));
+    // Keyed by "league_id:user_principal" so a league's entries share a prefix
+    static SEASON_ENTRIES: RefCell<StableMap<String, SeasonEntry>> = RefCell::new(StableMap::init(get_memory(SEASON_ENTRIES_MEMORY_ID)));
+    // Keyed by "match_id:stake:user_principal" so users waiting at a stake share a prefix
+    static MATCHMAKING_QUEUE: RefCell<StableMap<String, MatchmakingTicket>> = RefCell::new(StableMap::init(get_memory(MATCHMAKING_QUEUE_MEMORY_ID)));
+    // Append-only, keyed by entry sequence number
+    static LEDGER: RefCell<StableMap<u64, LedgerEntry>> = RefCell::new(StableMap::init(get_memory(LEDGER_MEMORY_ID)));
+    static ACCOUNT_BALANCES: RefCell<StableMap<LedgerAccount, AccountBalance>> = RefCell::new(StableMap::init(get_memory(ACCOUNT_BALANCES_MEMORY_ID)));
+    // Percentage of each contest's escrow kept by the platform at payout
+    static PLATFORM_RAKE_PERCENTAGE: RefCell<StableCell<f64, Memory>> = RefCell::new(StableCell::init(get_memory(PLATFORM_RAKE_MEMORY_ID), DEFAULT_RAKE_PERCENTAGE).unwrap());
+    // ICRC-1 ledger canister that deposits and withdrawals go through
+    static TOKEN_LEDGER: RefCell<StableCell<Option<Principal>, Memory>> = RefCell::new(StableCell::init(get_memory(TOKEN_LEDGER_MEMORY_ID), None).unwrap());
+    static WITHDRAWALS: RefCell<StableMap<String, WithdrawalRequest>> = RefCell::new(StableMap::init(get_memory(WITHDRAWALS_MEMORY_ID)));
+    static WITHDRAWAL_LIMITS: RefCell<StableCell<WithdrawalLimits, Memory>> = RefCell::new(StableCell::init(get_memory(WITHDRAWAL_LIMITS_MEMORY_ID), DEFAULT_WITHDRAWAL_LIMITS).unwrap());
+    // At most one unsettled deposit per user
+    static PENDING_DEPOSITS: RefCell<StableMap<Principal, PendingDeposit>> = RefCell::new(StableMap::init(get_memory(PENDING_DEPOSITS_MEMORY_ID)));
+    static NEXT_SCORE_ID: RefCell<Option<StableCell<u64, Memory>>> = const { RefCell::new(None) };
+    
+    // Rewards Management
+    static PRIZE_POOLS: RefCell<StableMap<String, PrizePool>> = RefCell::new(StableMap::init(get_memory(PRIZE_POOLS_MEMORY_ID)));
+    static USER_REWARDS: RefCell<StableMap<Principal, StorableVec<UserReward>>> = RefCell::new(StableMap::init(get_memory(USER_REWARDS_MEMORY_ID)));
+    static REWARD_HISTORY: RefCell<StableMap<String, RewardTransaction>> = RefCell::new(StableMap::init(get_memory(REWARD_HISTORY_MEMORY_ID)));
+    static NEXT_REWARD_ID: RefCell<Option<StableCell<u64, Memory>>> = const { RefCell::new(None) };
+    static NEXT_TRANSACTION_ID: RefCell<Option<StableCell<u64, Memory>>> = const { RefCell::new(None) };
+    
+    // Team Management
+    static FANTASY_TEAMS: RefCell<StableMap<String, FantasyTeam>> = RefCell::new(StableMap::init(get_memory(FANTASY_TEAMS_MEMORY_ID)));
+    static USER_FANTASY_TEAMS: RefCell<StableMap<Principal, StorableVec<String>>> = RefCell::new(StableMap::init(get_memory(USER_FANTASY_TEAMS_MEMORY_ID)));
+    static NEXT_FANTASY_TEAM_ID: RefCell<Option<StableCell<u64, Memory>>> = const { RefCell::new(None) };
+    
+    // Access Control
+    static ROLES: RefCell<StableMap<Principal, StorableVec<Role>>> = RefCell::new(StableMap::init(get_memory(ROLES_MEMORY_ID)));
+    
+    // Lifecycle Scheduler - timers do not survive upgrades, so this stays on the heap
+    static LIFECYCLE_TIMER: RefCell<Option<TimerId>> = const { RefCell::new(None) };
 }
 
 #[derive(CandidType, Deserialize, Serialize)]
 struct InitPayload {
     admin_principal: Option<Principal>,
+    token_ledger: Option<Principal>,
 }
 
 #[init]
-fn init() {
-    // Initialize with default admin principal
-    let admin_principal = Principal::from_text("2vxsx-fae").unwrap(); // Default admin for development
+fn init(payload: Option<InitPayload>) {
+    let (admin_principal, token_ledger) = payload
+        .map(|payload| (payload.admin_principal, payload.token_ledger))
+        .unwrap_or_default();
+    // Fall back to the installing principal when no admin is supplied
+    let admin_principal = admin_principal.unwrap_or_else(caller);
     
-    MEMORY_MANAGER.with(|mm| {
-        let mut mm = mm.borrow_mut();
-        
-        NEXT_NOTIFICATION_ID.with(|next_id| {
-            *next_id.borrow_mut() = Some(StableCell::init(mm.get(MemoryId::new(0)), 0).unwrap());
-        });
-        
-        NEXT_TOURNAMENT_ID.with(|next_id| {
-            *next_id.borrow_mut() = Some(StableCell::init(mm.get(MemoryId::new(1)), 0).unwrap());
-        });
-        NEXT_MATCH_ID.with(|next_id| {
-            *next_id.borrow_mut() = Some(StableCell::init(mm.get(MemoryId::new(2)), 0).unwrap());
-        });
-        NEXT_TEAM_ID.with(|next_id| {
-            *next_id.borrow_mut() = Some(StableCell::init(mm.get(MemoryId::new(3)), 0).unwrap());
-        });
-        NEXT_PLAYER_ID.with(|next_id| {
-            *next_id.borrow_mut() = Some(StableCell::init(mm.get(MemoryId::new(4)), 0).unwrap());
-        });
-        
-        NEXT_CONTEST_ID.with(|next_id| {
-            *next_id.borrow_mut() = Some(StableCell::init(mm.get(MemoryId::new(5)), 0).unwrap());
-        });
-        NEXT_ENTRY_ID.with(|next_id| {
-            *next_id.borrow_mut() = Some(StableCell::init(mm.get(MemoryId::new(6)), 0).unwrap());
-        });
-        
-        NEXT_SCORE_ID.with(|next_id| {
-            *next_id.borrow_mut() = Some(StableCell::init(mm.get(MemoryId::new(7)), 0).unwrap());
-        });
-        
-        NEXT_REWARD_ID.with(|next_id| {
-            *next_id.borrow_mut() = Some(StableCell::init(mm.get(MemoryId::new(8)), 0).unwrap());
-        });
-        NEXT_TRANSACTION_ID.with(|next_id| {
-            *next_id.borrow_mut() = Some(StableCell::init(mm.get(MemoryId::new(9)), 0).unwrap());
-        });
-        
-        NEXT_FANTASY_TEAM_ID.with(|next_id| {
-            *next_id.borrow_mut() = Some(StableCell::init(mm.get(MemoryId::new(10)), 0).unwrap());
-        });
+    init_id_counters();
+    
+    TOKEN_LEDGER.with(|ledger| {
+        ledger.borrow_mut().set(token_ledger).unwrap();
+    });
+    
+    ROLES.with(|roles| {
+        roles.borrow_mut().insert(admin_principal, StorableVec(vec![Role::Admin]));
     });
     
     // Initialize default scoring rules
     initialize_default_scoring_rules();
+    initialize_default_composition_rules();
+    
+    schedule_lifecycle_timer();
+}
+
+// Attaches the id counters to their stable memory regions. `StableCell::init`
+// loads the existing value if the region is already populated, so this is
+// used both on install and after an upgrade.
+fn init_id_counters() {
+    let counters: [&'static IdCounter; 11] = [
+        &NEXT_NOTIFICATION_ID,
+        &NEXT_TOURNAMENT_ID,
+        &NEXT_MATCH_ID,
+        &NEXT_TEAM_ID,
+        &NEXT_PLAYER_ID,
+        &NEXT_CONTEST_ID,
+        &NEXT_ENTRY_ID,
+        &NEXT_SCORE_ID,
+        &NEXT_REWARD_ID,
+        &NEXT_TRANSACTION_ID,
+        &NEXT_FANTASY_TEAM_ID,
+    ];
+    
+    for (index, counter) in counters.into_iter().enumerate() {
+        let memory = get_memory(MemoryId::new(index as u8));
+        counter.with(|next_id| {
+            *next_id.borrow_mut() = Some(StableCell::init(memory, 0).unwrap());
+        });
+    }
 }
 
 fn initialize_default_scoring_rules() {
     // Cricket scoring rules
     let cricket_rules = vec![
-        ScoringRule { action: "run".to_string(), points: 1.0, sport: Sport::Cricket },
-        ScoringRule { action: "wicket".to_string(), points: 10.0, sport: Sport::Cricket },
-        ScoringRule { action: "catch".to_string(), points: 8.0, sport: Sport::Cricket },
-        ScoringRule { action: "stumping".to_string(), points: 10.0, sport: Sport::Cricket },
-        ScoringRule { action: "run_out".to_string(), points: 6.0, sport: Sport::Cricket },
-        ScoringRule { action: "maiden_over".to_string(), points: 4.0, sport: Sport::Cricket },
-        ScoringRule { action: "economy_bonus".to_string(), points: 2.0, sport: Sport::Cricket },
+        per_unit_rule(Sport::Cricket, "run", 1.0),
+        per_unit_rule(Sport::Cricket, "wicket", 10.0),
+        per_unit_rule(Sport::Cricket, "catch", 8.0),
+        per_unit_rule(Sport::Cricket, "stumping", 10.0),
+        per_unit_rule(Sport::Cricket, "run_out", 6.0),
+        per_unit_rule(Sport::Cricket, "maiden_over", 4.0),
+        ScoringRule {
+            action: "economy".to_string(),
+            points: 2.0,
+            sport: Sport::Cricket,
+            condition: Some(RuleCondition {
+                comparison: Comparison::LessThan,
+                threshold: 4.0,
+            }),
+        },
     ];
     
-    SCORING_RULES.with(|rules| {
-        rules.borrow_mut().insert(Sport::Cricket, cricket_rules);
-    });
+    publish_rule_set(Sport::Cricket, cricket_rules);
     
     // Football scoring rules
     let football_rules = vec![
-        ScoringRule { action: "goal".to_string(), points: 6.0, sport: Sport::Football },
-        ScoringRule { action: "assist".to_string(), points: 3.0, sport: Sport::Football },
-        ScoringRule { action: "clean_sheet".to_string(), points: 4.0, sport: Sport::Football },
-        ScoringRule { action: "save".to_string(), points: 1.0, sport: Sport::Football },
-        ScoringRule { action: "yellow_card".to_string(), points: -1.0, sport: Sport::Football },
-        ScoringRule { action: "red_card".to_string(), points: -3.0, sport: Sport::Football },
+        per_unit_rule(Sport::Football, "goal", 6.0),
+        per_unit_rule(Sport::Football, "assist", 3.0),
+        per_unit_rule(Sport::Football, "clean_sheet", 4.0),
+        per_unit_rule(Sport::Football, "save", 1.0),
+        per_unit_rule(Sport::Football, "yellow_card", -1.0),
+        per_unit_rule(Sport::Football, "red_card", -3.0),
     ];
     
-    SCORING_RULES.with(|rules| {
-        rules.borrow_mut().insert(Sport::Football, football_rules);
-    });
+    publish_rule_set(Sport::Football, football_rules);
     
     // Basketball scoring rules
     let basketball_rules = vec![
-        ScoringRule { action: "point".to_string(), points: 1.0, sport: Sport::Basketball },
-        ScoringRule { action: "rebound".to_string(), points: 1.2, sport: Sport::Basketball },
-        ScoringRule { action: "assist".to_string(), points: 1.5, sport: Sport::Basketball },
-        ScoringRule { action: "block".to_string(), points: 2.0, sport: Sport::Basketball },
-        ScoringRule { action: "steal".to_string(), points: 2.0, sport: Sport::Basketball },
-        ScoringRule { action: "turnover".to_string(), points: -1.0, sport: Sport::Basketball },
+        per_unit_rule(Sport::Basketball, "point", 1.0),
+        per_unit_rule(Sport::Basketball, "rebound", 1.2),
+        per_unit_rule(Sport::Basketball, "assist", 1.5),
+        per_unit_rule(Sport::Basketball, "block", 2.0),
+        per_unit_rule(Sport::Basketball, "steal", 2.0),
+        per_unit_rule(Sport::Basketball, "turnover", -1.0),
     ];
     
-    SCORING_RULES.with(|rules| {
-        rules.borrow_mut().insert(Sport::Basketball, basketball_rules);
+    publish_rule_set(Sport::Basketball, basketball_rules);
+}
+
+// Stores the default squad rules for every sport that has none yet
+fn initialize_default_composition_rules() {
+    let defaults = vec![
+        TeamCompositionRules {
+            sport: Sport::Cricket,
+            min_squad_size: MIN_TEAM_SIZE,
+            max_squad_size: MAX_TEAM_SIZE,
+            position_limits: vec![
+                position_limit(PlayerPosition::WicketKeeper, 1, 4),
+                position_limit(PlayerPosition::Batsman, 3, 6),
+                position_limit(PlayerPosition::AllRounder, 1, 4),
+                position_limit(PlayerPosition::Bowler, 3, 6),
+            ],
+            max_players_per_team: 7,
+            min_credits: MIN_TEAM_PRICE,
+            max_credits: MAX_TEAM_PRICE,
+        },
+        TeamCompositionRules {
+            sport: Sport::Football,
+            min_squad_size: 11,
+            max_squad_size: 11,
+            position_limits: vec![
+                position_limit(PlayerPosition::Goalkeeper, 1, 1),
+                position_limit(PlayerPosition::Defender, 3, 5),
+                position_limit(PlayerPosition::Midfielder, 3, 5),
+                position_limit(PlayerPosition::Forward, 1, 3),
+            ],
+            max_players_per_team: 7,
+            min_credits: MIN_TEAM_PRICE,
+            max_credits: MAX_TEAM_PRICE,
+        },
+        TeamCompositionRules {
+            sport: Sport::Basketball,
+            min_squad_size: 8,
+            max_squad_size: 8,
+            position_limits: vec![
+                position_limit(PlayerPosition::PointGuard, 1, 4),
+                position_limit(PlayerPosition::ShootingGuard, 1, 4),
+                position_limit(PlayerPosition::SmallForward, 1, 4),
+                position_limit(PlayerPosition::PowerForward, 1, 4),
+                position_limit(PlayerPosition::Center, 1, 4),
+            ],
+            max_players_per_team: 5,
+            min_credits: 0,
+            max_credits: MAX_TEAM_PRICE,
+        },
+        TeamCompositionRules {
+            sport: Sport::Tennis,
+            min_squad_size: 1,
+            max_squad_size: 2,
+            position_limits: vec![],
+            max_players_per_team: 1,
+            min_credits: 0,
+            max_credits: MAX_TEAM_PRICE,
+        },
+    ];
+    
+    TEAM_COMPOSITION_RULES.with(|rules| {
+        let mut rules = rules.borrow_mut();
+        for default in defaults {
+            if !rules.contains_key(&default.sport) {
+                rules.insert(default.sport.clone(), default);
+            }
+        }
+    });
+}
+
+fn position_limit(position: PlayerPosition, min: u32, max: u32) -> PositionLimit {
+    PositionLimit { position, min, max }
+}
+
+fn per_unit_rule(sport: Sport, action: &str, points: f64) -> ScoringRule {
+    ScoringRule {
+        action: action.to_string(),
+        points,
+        sport,
+        condition: None,
+    }
+}
+
+// --- Access Control Section ---
+fn has_role(principal: &Principal, role: &Role) -> bool {
+    ROLES.with(|roles| {
+        roles
+            .borrow()
+            .get(principal)
+            .map(|assigned| assigned.0.contains(&Role::Admin) || assigned.0.contains(role))
+            .unwrap_or(false)
+    })
+}
+
+fn require_role(role: Role) -> Result<(), String> {
+    if has_role(&caller(), &role) {
+        Ok(())
+    } else {
+        Err(format!("Caller is missing the {:?} role", role))
+    }
+}
+
+// Guards for privileged endpoints. Admins pass every guard.
+fn is_admin() -> Result<(), String> {
+    require_role(Role::Admin)
+}
+
+fn is_operator() -> Result<(), String> {
+    require_role(Role::Operator)
+}
+
+fn is_score_feeder() -> Result<(), String> {
+    require_role(Role::ScoreFeeder).or_else(|_| require_role(Role::Operator))
+}
+
+fn is_kyc_reviewer() -> Result<(), String> {
+    require_role(Role::KYCReviewer)
+}
+
+#[update(guard = "is_admin")]
+fn add_role(principal: Principal, role: Role) -> RolesResponse {
+    let roles = ROLES.with(|roles| {
+        let mut roles = roles.borrow_mut();
+        let mut assigned = roles.get(&principal).unwrap_or_default();
+        if !assigned.0.contains(&role) {
+            assigned.0.push(role);
+            assigned.0.sort();
+            roles.insert(principal, assigned.clone());
+        }
+        assigned.0
     });
+    
+    RolesResponse {
+        success: true,
+        data: Some(vec![RoleAssignment { principal, roles }]),
+        error: None,
+    }
+}
+
+#[update(guard = "is_admin")]
+fn remove_role(principal: Principal, role: Role) -> RolesResponse {
+    let admin_count = ROLES.with(|roles| {
+        roles
+            .borrow()
+            .iter()
+            .filter(|(_, assigned)| assigned.0.contains(&Role::Admin))
+            .count()
+    });
+    
+    let mut assigned = ROLES.with(|roles| roles.borrow().get(&principal).unwrap_or_default());
+    
+    if !assigned.0.contains(&role) {
+        return RolesResponse {
+            success: false,
+            data: None,
+            error: Some("Principal does not hold this role".to_string()),
+        };
+    }
+    
+    if role == Role::Admin && admin_count <= 1 {
+        return RolesResponse {
+            success: false,
+            data: None,
+            error: Some("Cannot remove the last admin".to_string()),
+        };
+    }
+    
+    assigned.0.retain(|r| *r != role);
+    
+    ROLES.with(|roles| {
+        let mut roles = roles.borrow_mut();
+        if assigned.0.is_empty() {
+            roles.remove(&principal);
+        } else {
+            roles.insert(principal, assigned.clone());
+        }
+    });
+    
+    RolesResponse {
+        success: true,
+        data: Some(vec![RoleAssignment { principal, roles: assigned.0 }]),
+        error: None,
+    }
 }
 
-#[pre_upgrade]
-fn pre_upgrade() {
-    // Save state before upgrade
+#[query(guard = "is_admin")]
+fn list_roles() -> RolesResponse {
+    let assignments = ROLES.with(|roles| {
+        roles
+            .borrow()
+            .iter()
+            .map(|(principal, assigned)| RoleAssignment {
+                principal,
+                roles: assigned.0,
+            })
+            .collect()
+    });
+    
+    RolesResponse {
+        success: true,
+        data: Some(assignments),
+        error: None,
+    }
 }
 
 #[post_upgrade]
 fn post_upgrade() {
-    // Restore state after upgrade
+    // Domain maps re-attach to their memory regions lazily on first access;
+    // only the id counters need to be restored explicitly.
+    init_id_counters();
+    
+    // Publish the live rules as a first version for sports that predate rule sets
+    let unversioned: Vec<(Sport, Vec<ScoringRule>)> = SCORING_RULES.with(|rules| {
+        rules
+            .borrow()
+            .iter()
+            .filter(|(sport, _)| latest_rule_set(sport).is_none())
+            .map(|(sport, rules)| (sport, rules.0))
+            .collect()
+    });
+    for (sport, rules) in unversioned {
+        publish_rule_set(sport, rules);
+    }
+    
+    initialize_default_composition_rules();
+    open_ledger_wallets();
+    assign_deposit_subaccounts();
+    index_contests_by_match();
+    
+    // Timers are dropped on upgrade
+    schedule_lifecycle_timer();
 }
 
 // --- User Management Section ---
@@ -673,29 +1598,38 @@ fn register_user(username: String, email: Option<String>, phone: Option<String>)
         };
     }
     
-    let now = ic_cdk::api::time();
+    let now = time();
     let user_profile = UserProfile {
         id: caller,
         username: username.clone(),
         email,
         phone,
         kyc_status: KYCStatus::Pending,
-        balance: 1000, // Starting balance
+        balance: 0, // credited below through the ledger
         created_at: now,
         updated_at: now,
+        deposit_subaccount: Some(deposit_subaccount(&caller)),
     };
     
     USERS.with(|users| {
-        users.borrow_mut().insert(caller, user_profile.clone());
+        users.borrow_mut().insert(caller, user_profile);
     });
     
     USERNAMES.with(|usernames| {
         usernames.borrow_mut().insert(username, caller);
     });
     
+    // Starting balance
+    let _ = post_transfer(
+        LedgerAccount::Promo,
+        LedgerAccount::UserWallet(caller),
+        REGISTRATION_GRANT,
+        TransactionType::Bonus,
+    );
+    
     UserProfileResponse {
         success: true,
-        data: Some(user_profile),
+        data: USERS.with(|users| users.borrow().get(&caller)),
         error: None,
     }
 }
@@ -704,7 +1638,7 @@ fn register_user(username: String, email: Option<String>, phone: Option<String>)
 fn get_user_profile(user_id: Option<Principal>) -> UserProfileResponse {
     let user_id = user_id.unwrap_or_else(caller);
     
-    let user = USERS.with(|users| users.borrow().get(&user_id).cloned());
+    let user = USERS.with(|users| users.borrow().get(&user_id));
     
     match user {
         Some(profile) => UserProfileResponse {
@@ -727,7 +1661,7 @@ fn update_user_profile(
 ) -> UserProfileResponse {
     let caller = caller();
     
-    let mut user = USERS.with(|users| users.borrow().get(&caller).cloned());
+    let mut user = USERS.with(|users| users.borrow().get(&caller));
     
     if let Some(ref mut profile) = user {
         if let Some(email) = email {
@@ -736,7 +1670,7 @@ fn update_user_profile(
         if let Some(phone) = phone {
             profile.phone = Some(phone);
         }
-        profile.updated_at = ic_cdk::api::time();
+        profile.updated_at = time();
         
         USERS.with(|users| {
             users.borrow_mut().insert(caller, profile.clone());
@@ -756,17 +1690,13 @@ fn update_user_profile(
     }
 }
 
-#[update]
+#[update(guard = "is_kyc_reviewer")]
 fn update_kyc_status(user_id: Principal, status: KYCStatus) -> UserProfileResponse {
-    // Only admin can update KYC status
-    let caller = caller();
-    // TODO: Add admin check
-    
-    let mut user = USERS.with(|users| users.borrow().get(&user_id).cloned());
+    let mut user = USERS.with(|users| users.borrow().get(&user_id));
     
     if let Some(ref mut profile) = user {
         profile.kyc_status = status;
-        profile.updated_at = ic_cdk::api::time();
+        profile.updated_at = time();
         
         USERS.with(|users| {
             users.borrow_mut().insert(user_id, profile.clone());
@@ -787,68 +1717,50 @@ fn update_kyc_status(user_id: Principal, status: KYCStatus) -> UserProfileRespon
 }
 
 // Balance Management
-#[update]
+// Manual adjustments by an admin are booked against the house account.
+#[update(guard = "is_admin")]
 fn add_balance(user_id: Principal, amount: u64) -> UserProfileResponse {
-    let mut user = USERS.with(|users| users.borrow().get(&user_id).cloned());
-    
-    if let Some(ref mut profile) = user {
-        profile.balance += amount;
-        profile.updated_at = ic_cdk::api::time();
-        
-        USERS.with(|users| {
-            users.borrow_mut().insert(user_id, profile.clone());
-        });
-        
-        UserProfileResponse {
-            success: true,
-            data: Some(profile.clone()),
-            error: None,
-        }
-    } else {
-        UserProfileResponse {
-            success: false,
-            data: None,
-            error: Some("User not found".to_string()),
-        }
-    }
+    adjust_user_balance(LedgerAccount::House, LedgerAccount::UserWallet(user_id), amount, TransactionType::Deposit)
 }
 
-#[update]
+#[update(guard = "is_admin")]
 fn deduct_balance(user_id: Principal, amount: u64) -> UserProfileResponse {
-    let mut user = USERS.with(|users| users.borrow().get(&user_id).cloned());
-    
-    if let Some(ref mut profile) = user {
-        if profile.balance < amount {
+    adjust_user_balance(LedgerAccount::UserWallet(user_id), LedgerAccount::House, amount, TransactionType::Withdrawal)
+}
+
+fn adjust_user_balance(
+    from: LedgerAccount,
+    to: LedgerAccount,
+    amount: u64,
+    transaction_type: TransactionType,
+) -> UserProfileResponse {
+    let user_id = match (&from, &to) {
+        (LedgerAccount::UserWallet(user_id), _) | (_, LedgerAccount::UserWallet(user_id)) => *user_id,
+        _ => {
             return UserProfileResponse {
                 success: false,
                 data: None,
-                error: Some("Insufficient balance".to_string()),
+                error: Some("User not found".to_string()),
             };
         }
-        
-        profile.balance -= amount;
-        profile.updated_at = ic_cdk::api::time();
-        
-        USERS.with(|users| {
-            users.borrow_mut().insert(user_id, profile.clone());
-        });
-        
-        UserProfileResponse {
+    };
+    
+    match post_transfer(from, to, amount, transaction_type) {
+        Ok(()) => UserProfileResponse {
             success: true,
-            data: Some(profile.clone()),
+            data: USERS.with(|users| users.borrow().get(&user_id)),
             error: None,
-        }
-    } else {
-        UserProfileResponse {
+        },
+        Err(error) => UserProfileResponse {
             success: false,
             data: None,
-            error: Some("User not found".to_string()),
-        }
+            error: Some(error),
+        },
     }
 }
 
 // Notification Management
-#[update]
+#[update(guard = "is_operator")]
 fn create_notification(
     user_id: Principal,
     title: String,
@@ -864,7 +1776,7 @@ fn create_notification(
         message,
         notification_type,
         read: false,
-        created_at: ic_cdk::api::time(),
+        created_at: time(),
     };
     
     NOTIFICATIONS.with(|notifications| {
@@ -887,7 +1799,7 @@ fn get_user_notifications(user_id: Option<Principal>) -> NotificationsResponse {
             .borrow()
             .iter()
             .filter(|(_, notification)| notification.user_id == user_id)
-            .map(|(_, notification)| notification.clone())
+            .map(|(_, notification)| notification)
             .collect()
     });
     
@@ -901,7 +1813,7 @@ fn get_user_notifications(user_id: Option<Principal>) -> NotificationsResponse {
 #[update]
 fn mark_notification_read(notification_id: String) -> NotificationResponse {
     let mut notification = NOTIFICATIONS.with(|notifications| {
-        notifications.borrow().get(&notification_id).cloned()
+        notifications.borrow().get(&notification_id)
     });
     
     if let Some(ref mut notif) = notification {
@@ -928,18 +1840,18 @@ fn mark_notification_read(notification_id: String) -> NotificationResponse {
 #[query]
 fn get_all_users() -> Vec<UserProfile> {
     USERS.with(|users| {
-        users.borrow().iter().map(|(_, user)| user.clone()).collect()
+        users.borrow().iter().map(|(_, user)| user).collect()
     })
 }
 
 #[query]
 fn get_user_count() -> u64 {
-    USERS.with(|users| users.borrow().len() as u64)
+    USERS.with(|users| users.borrow().len())
 }
 
 // --- Tournament Section ---
 // Tournament Management
-#[update]
+#[update(guard = "is_operator")]
 fn create_tournament(
     name: String,
     sport: Sport,
@@ -963,6 +1875,8 @@ fn create_tournament(
         tournaments.borrow_mut().insert(tournament_id.to_string(), tournament.clone());
     });
 
+    schedule_lifecycle_timer();
+
     TournamentResponse {
         success: true,
         data: Some(tournament),
@@ -973,7 +1887,7 @@ fn create_tournament(
 #[query]
 fn get_tournament(tournament_id: String) -> TournamentResponse {
     let tournament = TOURNAMENTS.with(|tournaments| {
-        tournaments.borrow().get(&tournament_id).cloned()
+        tournaments.borrow().get(&tournament_id)
     });
 
     match tournament {
@@ -993,7 +1907,7 @@ fn get_tournament(tournament_id: String) -> TournamentResponse {
 #[query]
 fn get_all_tournaments() -> Vec<Tournament> {
     TOURNAMENTS.with(|tournaments| {
-        tournaments.borrow().iter().map(|(_, tournament)| tournament.clone()).collect()
+        tournaments.borrow().iter().map(|(_, tournament)| tournament).collect()
     })
 }
 
@@ -1004,7 +1918,7 @@ fn get_tournaments_by_sport(sport: Sport) -> Vec<Tournament> {
             .borrow()
             .iter()
             .filter(|(_, tournament)| tournament.sport == sport)
-            .map(|(_, tournament)| tournament.clone())
+            .map(|(_, tournament)| tournament)
             .collect()
     })
 }
@@ -1016,24 +1930,27 @@ fn get_live_tournaments() -> Vec<Tournament> {
             .borrow()
             .iter()
             .filter(|(_, tournament)| tournament.status == TournamentStatus::Live)
-            .map(|(_, tournament)| tournament.clone())
+            .map(|(_, tournament)| tournament)
             .collect()
     })
 }
 
-#[update]
+#[update(guard = "is_operator")]
 fn update_tournament_status(tournament_id: String, status: TournamentStatus) -> TournamentResponse {
     let mut tournament = TOURNAMENTS.with(|tournaments| {
-        tournaments.borrow().get(&tournament_id).cloned()
+        tournaments.borrow().get(&tournament_id)
     });
 
     if let Some(ref mut tourney) = tournament {
         tourney.status = status;
 
         TOURNAMENTS.with(|tournaments| {
-            tournaments.borrow_mut().insert(tournament_id, tourney.clone());
+            tournaments.borrow_mut().insert(tournament_id.clone(), tourney.clone());
         });
 
+        close_season_leagues(&tournament_id, &tourney.status);
+        schedule_lifecycle_timer();
+
         TournamentResponse {
             success: true,
             data: Some(tourney.clone()),
@@ -1049,7 +1966,7 @@ fn update_tournament_status(tournament_id: String, status: TournamentStatus) ->
 }
 
 // Team Management
-#[update]
+#[update(guard = "is_operator")]
 fn create_team(name: String, short_name: String) -> TeamResponse {
     let team_id = get_next_id(&NEXT_TEAM_ID);
 
@@ -1073,7 +1990,7 @@ fn create_team(name: String, short_name: String) -> TeamResponse {
 
 #[query]
 fn get_team(team_id: String) -> TeamResponse {
-    let team = TEAMS.with(|teams| teams.borrow().get(&team_id).cloned());
+    let team = TEAMS.with(|teams| teams.borrow().get(&team_id));
 
     match team {
         Some(t) => TeamResponse {
@@ -1091,11 +2008,11 @@ fn get_team(team_id: String) -> TeamResponse {
 
 #[query]
 fn get_all_teams() -> Vec<Team> {
-    TEAMS.with(|teams| teams.borrow().iter().map(|(_, team)| team.clone()).collect())
+    TEAMS.with(|teams| teams.borrow().iter().map(|(_, team)| team).collect())
 }
 
 // Player Management
-#[update]
+#[update(guard = "is_operator")]
 fn create_player(
     name: String,
     team_id: String,
@@ -1119,7 +2036,7 @@ fn create_player(
     });
 
     // Add player to team
-    let mut team = TEAMS.with(|teams| teams.borrow().get(&team_id).cloned());
+    let mut team = TEAMS.with(|teams| teams.borrow().get(&team_id));
     if let Some(ref mut t) = team {
         t.players.push(player.clone());
         TEAMS.with(|teams| {
@@ -1136,7 +2053,7 @@ fn create_player(
 
 #[query]
 fn get_player(player_id: String) -> PlayerResponse {
-    let player = PLAYERS.with(|players| players.borrow().get(&player_id).cloned());
+    let player = PLAYERS.with(|players| players.borrow().get(&player_id));
 
     match player {
         Some(p) => PlayerResponse {
@@ -1159,7 +2076,7 @@ fn get_players_by_team(team_id: String) -> Vec<Player> {
             .borrow()
             .iter()
             .filter(|(_, player)| player.team_id == team_id)
-            .map(|(_, player)| player.clone())
+            .map(|(_, player)| player)
             .collect()
     })
 }
@@ -1171,14 +2088,14 @@ fn get_players_by_position(position: PlayerPosition) -> Vec<Player> {
             .borrow()
             .iter()
             .filter(|(_, player)| player.position == position)
-            .map(|(_, player)| player.clone())
+            .map(|(_, player)| player)
             .collect()
     })
 }
 
-#[update]
+#[update(guard = "is_operator")]
 fn update_player_points(player_id: String, points: f64) -> PlayerResponse {
-    let mut player = PLAYERS.with(|players| players.borrow().get(&player_id).cloned());
+    let mut player = PLAYERS.with(|players| players.borrow().get(&player_id));
 
     if let Some(ref mut p) = player {
         p.points = points;
@@ -1202,7 +2119,7 @@ fn update_player_points(player_id: String, points: f64) -> PlayerResponse {
 }
 
 // Match Management
-#[update]
+#[update(guard = "is_operator")]
 fn create_match(
     tournament_id: String,
     team1_id: String,
@@ -1227,7 +2144,7 @@ fn create_match(
 
     // Add match to tournament
     let mut tournament = TOURNAMENTS.with(|tournaments| {
-        tournaments.borrow().get(&tournament_id).cloned()
+        tournaments.borrow().get(&tournament_id)
     });
 
     if let Some(ref mut tourney) = tournament {
@@ -1237,6 +2154,8 @@ fn create_match(
         });
     }
 
+    schedule_lifecycle_timer();
+
     MatchResponse {
         success: true,
         data: Some(match_obj),
@@ -1246,7 +2165,7 @@ fn create_match(
 
 #[query]
 fn get_match(match_id: String) -> MatchResponse {
-    let match_obj = MATCHES.with(|matches| matches.borrow().get(&match_id).cloned());
+    let match_obj = MATCHES.with(|matches| matches.borrow().get(&match_id));
 
     match match_obj {
         Some(m) => MatchResponse {
@@ -1269,22 +2188,36 @@ fn get_matches_by_tournament(tournament_id: String) -> Vec<Match> {
             .borrow()
             .iter()
             .filter(|(_, match_obj)| match_obj.tournament_id == tournament_id)
-            .map(|(_, match_obj)| match_obj.clone())
+            .map(|(_, match_obj)| match_obj)
             .collect()
     })
 }
 
-#[update]
+#[update(guard = "is_operator")]
 fn update_match_status(match_id: String, status: MatchStatus) -> MatchResponse {
-    let mut match_obj = MATCHES.with(|matches| matches.borrow().get(&match_id).cloned());
+    let mut match_obj = MATCHES.with(|matches| matches.borrow().get(&match_id));
 
     if let Some(ref mut m) = match_obj {
         m.status = status;
 
         MATCHES.with(|matches| {
-            matches.borrow_mut().insert(match_id, m.clone());
+            matches.borrow_mut().insert(match_id.clone(), m.clone());
         });
 
+        // Refund every contest that was running on a cancelled match
+        if m.status == MatchStatus::Cancelled {
+            let contests = match_contests(&match_id)
+                .into_iter()
+                .filter(|contest| !matches!(contest.status, ContestStatus::Completed | ContestStatus::Cancelled));
+            for contest in contests {
+                cancel_contest_with_refunds(contest, "The match was cancelled");
+            }
+        }
+
+        refund_unmatched_tickets();
+        settle_season_leagues_of_match(&match_id);
+        schedule_lifecycle_timer();
+
         MatchResponse {
             success: true,
             data: Some(m.clone()),
@@ -1299,17 +2232,19 @@ fn update_match_status(match_id: String, status: MatchStatus) -> MatchResponse {
     }
 }
 
-#[update]
+#[update(guard = "is_score_feeder")]
 fn update_match_score(match_id: String, score: MatchScore) -> MatchResponse {
-    let mut match_obj = MATCHES.with(|matches| matches.borrow().get(&match_id).cloned());
+    let mut match_obj = MATCHES.with(|matches| matches.borrow().get(&match_id));
 
     if let Some(ref mut m) = match_obj {
         m.score = Some(score);
 
         MATCHES.with(|matches| {
-            matches.borrow_mut().insert(match_id, m.clone());
+            matches.borrow_mut().insert(match_id.clone(), m.clone());
         });
 
+        settle_season_leagues_of_match(&match_id);
+
         MatchResponse {
             success: true,
             data: Some(m.clone()),
@@ -1324,11 +2259,12 @@ fn update_match_score(match_id: String, score: MatchScore) -> MatchResponse {
     }
 }
 
-#[update]
+#[update(guard = "is_score_feeder")]
 fn update_match_score_data(match_id: String, score: MatchScore) -> MatchScoreResponse {
     MATCH_SCORES.with(|match_scores| {
         match_scores.borrow_mut().insert(match_id.clone(), score.clone());
     });
+    settle_season_leagues_of_match(&match_id);
     
     MatchScoreResponse {
         success: true,
@@ -1340,7 +2276,7 @@ fn update_match_score_data(match_id: String, score: MatchScore) -> MatchScoreRes
 #[query]
 fn get_match_score(match_id: String) -> MatchScoreResponse {
     let score = MATCH_SCORES.with(|match_scores| {
-        match_scores.borrow().get(&match_id).cloned()
+        match_scores.borrow().get(&match_id)
     });
     
     match score {
@@ -1357,10 +2293,10 @@ fn get_match_score(match_id: String) -> MatchScoreResponse {
     }
 }
 
-#[update]
+#[update(guard = "is_operator")]
 fn add_team_to_tournament(tournament_id: String, team: Team) -> TournamentResponse {
     let mut tournament = TOURNAMENTS.with(|tournaments| {
-        tournaments.borrow().get(&tournament_id).cloned()
+        tournaments.borrow().get(&tournament_id)
     });
 
     if let Some(ref mut tourney) = tournament {
@@ -1387,7 +2323,7 @@ fn add_team_to_tournament(tournament_id: String, team: Team) -> TournamentRespon
 #[query]
 fn get_tournament_teams(tournament_id: String) -> TeamsResponse {
     let tournament = TOURNAMENTS.with(|tournaments| {
-        tournaments.borrow().get(&tournament_id).cloned()
+        tournaments.borrow().get(&tournament_id)
     });
 
     match tournament {
@@ -1406,12 +2342,12 @@ fn get_tournament_teams(tournament_id: String) -> TeamsResponse {
 
 #[query]
 fn get_tournament_count() -> u64 {
-    TOURNAMENTS.with(|tournaments| tournaments.borrow().len() as u64)
+    TOURNAMENTS.with(|tournaments| tournaments.borrow().len())
 }
 
 #[query]
 fn get_match_count() -> u64 {
-    MATCHES.with(|matches| matches.borrow().len() as u64)
+    MATCHES.with(|matches| matches.borrow().len())
 }
 
 #[query]
@@ -1421,7 +2357,7 @@ fn get_live_matches() -> Vec<Match> {
             .borrow()
             .iter()
             .filter(|(_, match_obj)| match_obj.status == MatchStatus::Live)
-            .map(|(_, match_obj)| match_obj.clone())
+            .map(|(_, match_obj)| match_obj)
             .collect()
     })
 }
@@ -1433,18 +2369,71 @@ fn get_upcoming_matches() -> Vec<Match> {
             .borrow()
             .iter()
             .filter(|(_, match_obj)| match_obj.status == MatchStatus::Scheduled)
-            .map(|(_, match_obj)| match_obj.clone())
+            .map(|(_, match_obj)| match_obj)
             .collect()
     })
 }
 
+// Deadline Locking
+fn match_lock_time(match_obj: &Match) -> u64 {
+    let buffer_seconds = LOCK_BUFFER_SECONDS.with(|buffer| *buffer.borrow().get());
+    match_obj
+        .start_time
+        .saturating_sub(buffer_seconds.saturating_mul(1_000_000_000))
+}
+
+// A match is locked once its lock time passes or it has left the Scheduled
+// state. Unknown matches count as locked.
+fn is_match_locked(match_id: &str) -> bool {
+    MATCHES
+        .with(|matches| matches.borrow().get(&match_id.to_string()))
+        .map(|match_obj| {
+            match_obj.status != MatchStatus::Scheduled || time() >= match_lock_time(&match_obj)
+        })
+        .unwrap_or(true)
+}
+
+#[query]
+fn get_lock_time(match_id: String) -> LockTimeResponse {
+    let match_obj = MATCHES.with(|matches| matches.borrow().get(&match_id));
+    
+    match match_obj {
+        Some(m) => LockTimeResponse {
+            success: true,
+            data: Some(match_lock_time(&m)),
+            error: None,
+        },
+        None => LockTimeResponse {
+            success: false,
+            data: None,
+            error: Some("Match not found".to_string()),
+        },
+    }
+}
+
+#[update(guard = "is_admin")]
+fn set_lock_buffer(seconds: u64) -> LockTimeResponse {
+    LOCK_BUFFER_SECONDS.with(|buffer| {
+        buffer.borrow_mut().set(seconds).unwrap();
+    });
+    // Lock times have moved, so matches may now lock sooner or later
+    schedule_lifecycle_timer();
+    
+    LockTimeResponse {
+        success: true,
+        data: Some(seconds),
+        error: None,
+    }
+}
+
 // --- Team Management Section ---
 // (Insert all thread_local! statics and functions from team_management/src/lib.rs here)
 // ...
 
 // --- Contest Section ---
 // Contest Management
-#[update]
+#[update(guard = "is_operator")]
+#[allow(clippy::too_many_arguments)]
 fn create_contest(
     name: String,
     match_id: String,
@@ -1453,7 +2442,49 @@ fn create_contest(
     prize_pool: u64,
     contest_type: ContestType,
     start_time: u64,
+    min_entries: Option<u32>,
+    max_entries_per_user: Option<u32>,
 ) -> ContestResponse {
+    if contest_type == ContestType::HeadToHead && total_spots != HEAD_TO_HEAD_SPOTS {
+        return ContestResponse {
+            success: false,
+            data: None,
+            error: Some(format!("Head-to-head contests have exactly {} spots", HEAD_TO_HEAD_SPOTS)),
+        };
+    }
+    
+    let min_entries = min_entries.unwrap_or(DEFAULT_MIN_ENTRIES.min(total_spots));
+    if min_entries == 0 || min_entries > total_spots {
+        return ContestResponse {
+            success: false,
+            data: None,
+            error: Some("Minimum entries must be between 1 and the number of spots".to_string()),
+        };
+    }
+    
+    let max_entries_per_user = max_entries_per_user.unwrap_or(1);
+    if max_entries_per_user == 0
+        || max_entries_per_user > total_spots
+        || (contest_type == ContestType::HeadToHead && max_entries_per_user != 1)
+    {
+        return ContestResponse {
+            success: false,
+            data: None,
+            error: Some("Max entries per user must be between 1 and the number of spots".to_string()),
+        };
+    }
+    
+    let sport = match get_match_sport(&match_id) {
+        Some(sport) => sport,
+        None => {
+            return ContestResponse {
+                success: false,
+                data: None,
+                error: Some("Match not found".to_string()),
+            };
+        }
+    };
+    
     let contest_id = get_next_id(&NEXT_CONTEST_ID);
     
     let contest = Contest {
@@ -1466,8 +2497,14 @@ fn create_contest(
         prize_pool,
         contest_type,
         status: ContestStatus::Open,
-        created_at: ic_cdk::api::time(),
+        created_at: time(),
         start_time,
+        scoring_rule_set_id: latest_rule_set(&sport).map(|rule_set| rule_set.id),
+        ranking: None,
+        min_entries: Some(min_entries),
+        max_entries_per_user: Some(max_entries_per_user),
+        creator: None,
+        visibility: Some(ContestVisibility::Public),
     };
     
     CONTESTS.with(|contests| {
@@ -1475,8 +2512,11 @@ fn create_contest(
     });
     
     CONTEST_ENTRIES_BY_CONTEST.with(|contest_entries| {
-        contest_entries.borrow_mut().insert(contest_id.to_string(), Vec::new());
+        contest_entries.borrow_mut().insert(contest_id.to_string(), StorableVec::default());
     });
+    add_contest_to_match(&contest.match_id, &contest.id);
+    
+    schedule_lifecycle_timer();
     
     ContestResponse {
         success: true,
@@ -1487,7 +2527,7 @@ fn create_contest(
 
 #[query]
 fn get_contest(contest_id: String) -> ContestResponse {
-    let contest = CONTESTS.with(|contests| contests.borrow().get(&contest_id).cloned());
+    let contest = CONTESTS.with(|contests| contests.borrow().get(&contest_id));
     
     match contest {
         Some(c) => ContestResponse {
@@ -1505,14 +2545,51 @@ fn get_contest(contest_id: String) -> ContestResponse {
 
 #[query]
 fn get_contests_by_match(match_id: String) -> Vec<Contest> {
+    match_contests(&match_id)
+        .into_iter()
+        .filter(|contest| !is_private_contest(contest))
+        .collect()
+}
+
+fn match_contests(match_id: &str) -> Vec<Contest> {
+    let contest_ids = CONTESTS_BY_MATCH
+        .with(|index| index.borrow().get(&match_id.to_string()))
+        .unwrap_or_default();
+    
     CONTESTS.with(|contests| {
+        let contests = contests.borrow();
+        contest_ids.0.iter().filter_map(|contest_id| contests.get(contest_id)).collect()
+    })
+}
+
+fn add_contest_to_match(match_id: &str, contest_id: &str) {
+    CONTESTS_BY_MATCH.with(|index| {
+        let mut index = index.borrow_mut();
+        let mut contest_ids = index.get(&match_id.to_string()).unwrap_or_default();
+        if !contest_ids.0.iter().any(|id| id == contest_id) {
+            contest_ids.0.push(contest_id.to_string());
+            index.insert(match_id.to_string(), contest_ids);
+        }
+    });
+}
+
+// Builds the match index for contests created before it existed.
+fn index_contests_by_match() {
+    if !CONTESTS_BY_MATCH.with(|index| index.borrow().is_empty()) {
+        return;
+    }
+    
+    let contests: Vec<(String, String)> = CONTESTS.with(|contests| {
         contests
             .borrow()
             .iter()
-            .filter(|(_, contest)| contest.match_id == match_id)
-            .map(|(_, contest)| contest.clone())
+            .map(|(contest_id, contest)| (contest.match_id, contest_id))
             .collect()
-    })
+    });
+    
+    for (match_id, contest_id) in contests {
+        add_contest_to_match(&match_id, &contest_id);
+    }
 }
 
 #[query]
@@ -1521,81 +2598,126 @@ fn get_open_contests() -> Vec<Contest> {
         contests
             .borrow()
             .iter()
-            .filter(|(_, contest)| contest.status == ContestStatus::Open)
-            .map(|(_, contest)| contest.clone())
+            .filter(|(_, contest)| contest.status == ContestStatus::Open && !is_private_contest(contest))
+            .map(|(_, contest)| contest)
             .collect()
     })
 }
 
 // Contest Entry Management
 #[update]
-fn join_contest(contest_id: String, fantasy_team_id: String) -> ContestEntryResponse {
+fn join_contest(contest_id: String, fantasy_team_id: String) -> Result<ContestEntry, FantasyError> {
+    // Private leagues can only be entered with their invite code
+    let private = CONTESTS
+        .with(|contests| contests.borrow().get(&contest_id))
+        .map(|contest| is_private_contest(&contest))
+        .unwrap_or(false);
+    if private {
+        return Err(FantasyError::Unauthorized);
+    }
+    
+    enter_contest(contest_id, fantasy_team_id)
+}
+
+fn enter_contest(contest_id: String, fantasy_team_id: String) -> Result<ContestEntry, FantasyError> {
     let caller = caller();
     
-    // Get contest
-    let contest = CONTESTS.with(|contests| contests.borrow().get(&contest_id).cloned());
-    if contest.is_none() {
-        return ContestEntryResponse {
-            success: false,
-            data: None,
-            error: Some("Contest not found".to_string()),
-        };
-    }
-    let contest = contest.unwrap();
+    // Validate everything up front so that no state is touched on failure
+    let contest = CONTESTS
+        .with(|contests| contests.borrow().get(&contest_id))
+        .ok_or(FantasyError::ContestNotFound)?;
     
-    // Check if contest is open
-    if contest.status != ContestStatus::Open {
-        return ContestEntryResponse {
-            success: false,
-            data: None,
-            error: Some("Contest is not open for entries".to_string()),
-        };
+    match contest.status {
+        ContestStatus::Open => {}
+        ContestStatus::Full => return Err(FantasyError::ContestFull),
+        ContestStatus::Live => return Err(FantasyError::ContestAlreadyStarted),
+        ContestStatus::Completed => return Err(FantasyError::ContestCompleted),
+        ContestStatus::Cancelled => return Err(FantasyError::InvalidOperation),
     }
     
-    // Check if contest is full
     if contest.filled_spots >= contest.total_spots {
-        return ContestEntryResponse {
-            success: false,
-            data: None,
-            error: Some("Contest is full".to_string()),
-        };
+        return Err(FantasyError::ContestFull);
+    }
+    
+    if is_match_locked(&contest.match_id) {
+        return Err(FantasyError::ContestAlreadyStarted);
+    }
+    
+    let user = USERS
+        .with(|users| users.borrow().get(&caller))
+        .ok_or(FantasyError::UserNotFound)?;
+    
+    let user_entries = user_contest_entries(&contest_id, &caller);
+    if user_entries.len() as u32 >= contest.max_entries_per_user.unwrap_or(1) {
+        return Err(FantasyError::AlreadyJoined);
+    }
+    
+    if user_entries.iter().any(|entry| entry.team.id == fantasy_team_id) {
+        return Err(FantasyError::InvalidTeam);
     }
     
-    // Check if user already joined this contest
-    let user_contest_ids = USER_CONTESTS.with(|user_contests| {
-        user_contests.borrow().get(&caller).cloned().unwrap_or(vec![])
+    let team = FANTASY_TEAMS
+        .with(|teams| teams.borrow().get(&fantasy_team_id))
+        .ok_or(FantasyError::TeamNotFound)?;
+    
+    let owns_team = USER_FANTASY_TEAMS.with(|user_teams| {
+        user_teams
+            .borrow()
+            .get(&caller)
+            .map(|team_ids| team_ids.0.contains(&fantasy_team_id))
+            .unwrap_or(false)
     });
     
-    if user_contest_ids.contains(&contest_id) {
-        return ContestEntryResponse {
-            success: false,
-            data: None,
-            error: Some("User already joined this contest".to_string()),
-        };
+    if !owns_team {
+        return Err(FantasyError::Unauthorized);
+    }
+    
+    if team.match_id.as_ref() != Some(&contest.match_id) {
+        return Err(FantasyError::InvalidTeam);
     }
     
-    // TODO: Deduct entry fee from user balance (call user_management canister)
-    // TODO: Get fantasy team details (call team_management canister)
+    if user.balance < contest.entry_fee {
+        return Err(FantasyError::InsufficientBalance);
+    }
+    
+    // Charge the entry fee into the contest's escrow
+    post_transfer(
+        LedgerAccount::UserWallet(caller),
+        LedgerAccount::ContestEscrow(contest_id.clone()),
+        contest.entry_fee,
+        TransactionType::ContestEntry,
+    )
+    .map_err(|_| FantasyError::InsufficientBalance)?;
+    
+    if contest.entry_fee > 0 {
+        create_transaction(
+            caller,
+            contest.entry_fee,
+            TransactionType::ContestEntry,
+            TransactionStatus::Completed,
+        );
+    }
     
+    Ok(insert_contest_entry(contest, caller, team))
+}
+
+// Adds an entry for a user whose fee has already been taken and updates the
+// contest's spots and the entry indexes.
+fn insert_contest_entry(contest: Contest, user_id: Principal, team: FantasyTeam) -> ContestEntry {
+    let contest_id = contest.id.clone();
     let entry_id = get_next_id(&NEXT_ENTRY_ID);
     
+    // The entry keeps a frozen copy of the team as it was when joining
     let entry = ContestEntry {
         id: entry_id.to_string(),
         contest_id: contest_id.clone(),
-        user_id: caller,
-        team: FantasyTeam {
-            id: fantasy_team_id.clone(),
-            name: "".to_string(),
-            captain_id: "".to_string(),
-            vice_captain_id: "".to_string(),
-            players: Vec::new(),
-            total_points: 0.0,
-            total_price: 0,
-        },
+        user_id,
+        team,
         points: 0.0,
         rank: None,
         prize: None,
-        created_at: ic_cdk::api::time(),
+        created_at: time(),
+        refunded_at: None,
     };
     
     CONTEST_ENTRIES.with(|entries| {
@@ -1603,7 +2725,7 @@ fn join_contest(contest_id: String, fantasy_team_id: String) -> ContestEntryResp
     });
     
     // Update contest filled spots
-    let mut updated_contest = contest.clone();
+    let mut updated_contest = contest;
     updated_contest.filled_spots += 1;
     if updated_contest.filled_spots >= updated_contest.total_spots {
         updated_contest.status = ContestStatus::Full;
@@ -1614,38 +2736,116 @@ fn join_contest(contest_id: String, fantasy_team_id: String) -> ContestEntryResp
     });
     
     // Update user contests
-    let mut user_contest_ids = user_contest_ids;
-    user_contest_ids.push(contest_id.clone());
-    USER_CONTESTS.with(|user_contests_map| {
-        user_contests_map.borrow_mut().insert(caller, user_contest_ids);
+    let mut user_contest_ids = USER_CONTESTS.with(|user_contests| {
+        user_contests.borrow().get(&user_id).unwrap_or_default().0
     });
+    if !user_contest_ids.contains(&contest_id) {
+        user_contest_ids.push(contest_id.clone());
+        USER_CONTESTS.with(|user_contests_map| {
+            user_contests_map.borrow_mut().insert(user_id, StorableVec(user_contest_ids));
+        });
+    }
     
     // Update contest entries
     let mut contest_entries = CONTEST_ENTRIES_BY_CONTEST.with(|contest_entries| {
-        contest_entries.borrow().get(&contest_id).cloned().unwrap_or(vec![])
+        contest_entries.borrow().get(&contest_id).unwrap_or_default().0
     });
     contest_entries.push(entry_id.to_string());
     CONTEST_ENTRIES_BY_CONTEST.with(|contest_entries_map| {
-        contest_entries_map.borrow_mut().insert(contest_id, contest_entries);
+        contest_entries_map.borrow_mut().insert(contest_id, StorableVec(contest_entries));
     });
     
-    ContestEntryResponse {
-        success: true,
-        data: Some(entry),
-        error: None,
+    entry
+}
+
+#[update]
+fn switch_entry_team(entry_id: String, fantasy_team_id: String) -> Result<ContestEntry, FantasyError> {
+    let caller = caller();
+    
+    let mut entry = CONTEST_ENTRIES
+        .with(|entries| entries.borrow().get(&entry_id))
+        .ok_or(FantasyError::InvalidOperation)?;
+    
+    if entry.user_id != caller {
+        return Err(FantasyError::Unauthorized);
+    }
+    
+    let contest = CONTESTS
+        .with(|contests| contests.borrow().get(&entry.contest_id))
+        .ok_or(FantasyError::ContestNotFound)?;
+    
+    match contest.status {
+        ContestStatus::Open | ContestStatus::Full => {}
+        ContestStatus::Live => return Err(FantasyError::ContestAlreadyStarted),
+        ContestStatus::Completed => return Err(FantasyError::ContestCompleted),
+        ContestStatus::Cancelled => return Err(FantasyError::InvalidOperation),
+    }
+    
+    if is_match_locked(&contest.match_id) {
+        return Err(FantasyError::ContestAlreadyStarted);
+    }
+    
+    let team = FANTASY_TEAMS
+        .with(|teams| teams.borrow().get(&fantasy_team_id))
+        .ok_or(FantasyError::TeamNotFound)?;
+    
+    let owns_team = USER_FANTASY_TEAMS.with(|user_teams| {
+        user_teams
+            .borrow()
+            .get(&caller)
+            .map(|team_ids| team_ids.0.contains(&fantasy_team_id))
+            .unwrap_or(false)
+    });
+    
+    if !owns_team {
+        return Err(FantasyError::Unauthorized);
+    }
+    
+    if team.match_id.as_ref() != Some(&contest.match_id) {
+        return Err(FantasyError::InvalidTeam);
+    }
+    
+    // Each of the user's entries must keep a different team
+    let team_in_use = user_contest_entries(&contest.id, &caller)
+        .iter()
+        .any(|other| other.id != entry.id && other.team.id == fantasy_team_id);
+    if team_in_use {
+        return Err(FantasyError::InvalidTeam);
     }
+    
+    entry.team = team;
+    CONTEST_ENTRIES.with(|entries| {
+        entries.borrow_mut().insert(entry_id, entry.clone());
+    });
+    
+    Ok(entry)
+}
+
+fn user_contest_entries(contest_id: &str, user_id: &Principal) -> Vec<ContestEntry> {
+    let entry_ids = CONTEST_ENTRIES_BY_CONTEST.with(|contest_entries| {
+        contest_entries.borrow().get(&contest_id.to_string()).unwrap_or_default().0
+    });
+    
+    CONTEST_ENTRIES.with(|entries| {
+        let entries = entries.borrow();
+        entry_ids
+            .iter()
+            .filter_map(|entry_id| entries.get(entry_id))
+            .filter(|entry| entry.user_id == *user_id)
+            .collect()
+    })
 }
 
 #[query]
 fn get_contest_entries(contest_id: String) -> Vec<ContestEntry> {
     let entry_ids = CONTEST_ENTRIES_BY_CONTEST.with(|contest_entries| {
-        contest_entries.borrow().get(&contest_id).cloned().unwrap_or(vec![])
+        contest_entries.borrow().get(&contest_id).unwrap_or_default().0
     });
     
     CONTEST_ENTRIES.with(|entries| {
         entry_ids
             .iter()
-            .filter_map(|id| entries.borrow().get(id).cloned())
+            .filter_map(|id| entries.borrow().get(id))
             .collect()
     })
 }
@@ -1654,16 +2854,12 @@ fn get_contest_entries(contest_id: String) -> Vec<ContestEntry> {
 fn get_user_contest_entries(user_id: Option<Principal>) -> ContestEntriesResponse {
     let user_id = user_id.unwrap_or_else(caller);
     
-    let contest_ids = USER_CONTESTS.with(|user_contests| {
-        user_contests.borrow().get(&user_id).cloned().unwrap_or(vec![])
-    });
-    
     let entries = CONTEST_ENTRIES.with(|entries| {
         entries
             .borrow()
             .iter()
             .filter(|(_, entry)| entry.user_id == user_id)
-            .map(|(_, entry)| entry.clone())
+            .map(|(_, entry)| entry)
             .collect()
     });
     
@@ -1674,17 +2870,55 @@ fn get_user_contest_entries(user_id: Option<Principal>) -> ContestEntriesRespons
     }
 }
 
-#[update]
+// Contests only move forward: Open (or Full) -> Live -> Completed, or to
+// Cancelled from any status before Completed.
+fn contest_transition_allowed(from: &ContestStatus, to: &ContestStatus) -> bool {
+    matches!(
+        (from, to),
+        (ContestStatus::Open, ContestStatus::Full)
+            | (ContestStatus::Full, ContestStatus::Open)
+            | (ContestStatus::Open | ContestStatus::Full, ContestStatus::Live)
+            | (ContestStatus::Live, ContestStatus::Completed)
+            | (ContestStatus::Open | ContestStatus::Full | ContestStatus::Live, ContestStatus::Cancelled)
+    )
+}
+
+#[update(guard = "is_operator")]
 fn update_contest_status(contest_id: String, status: ContestStatus) -> ContestResponse {
-    let mut contest = CONTESTS.with(|contests| contests.borrow().get(&contest_id).cloned());
+    match status {
+        // Going live checks the minimum fill, completing pays out the prizes
+        // and cancelling refunds the entrants, so each has its own flow
+        ContestStatus::Live => return start_contest(contest_id),
+        ContestStatus::Cancelled => return cancel_contest(contest_id, "Cancelled by operator".to_string()),
+        ContestStatus::Completed => {
+            return ContestResponse {
+                success: false,
+                data: None,
+                error: Some("Contests are completed by finalizing them".to_string()),
+            };
+        }
+        ContestStatus::Open | ContestStatus::Full => {}
+    }
+    
+    let mut contest = CONTESTS.with(|contests| contests.borrow().get(&contest_id));
     
     if let Some(ref mut c) = contest {
+        if !contest_transition_allowed(&c.status, &status) {
+            return ContestResponse {
+                success: false,
+                data: None,
+                error: Some(format!("Cannot move a contest from {:?} to {:?}", c.status, status)),
+            };
+        }
+        
         c.status = status;
         
         CONTESTS.with(|contests| {
             contests.borrow_mut().insert(contest_id, c.clone());
         });
         
+        schedule_lifecycle_timer();
+        
         ContestResponse {
             success: true,
             data: Some(c.clone()),
@@ -1699,9 +2933,178 @@ fn update_contest_status(contest_id: String, status: ContestStatus) -> ContestRe
     }
 }
 
-#[update]
+#[update(guard = "is_operator")]
+fn cancel_contest(contest_id: String, reason: String) -> ContestResponse {
+    let contest = CONTESTS.with(|contests| contests.borrow().get(&contest_id));
+    
+    match contest {
+        Some(c) if !contest_transition_allowed(&c.status, &ContestStatus::Cancelled) => {
+            ContestResponse {
+                success: false,
+                data: None,
+                error: Some("Contest is already finalized".to_string()),
+            }
+        }
+        Some(c) => ContestResponse {
+            success: true,
+            data: Some(cancel_contest_with_refunds(c, &reason)),
+            error: None,
+        },
+        None => ContestResponse {
+            success: false,
+            data: None,
+            error: Some("Contest not found".to_string()),
+        },
+    }
+}
+
+// Marks the contest cancelled and returns every entry fee to its entrant.
+fn cancel_contest_with_refunds(mut contest: Contest, reason: &str) -> Contest {
+    contest.status = ContestStatus::Cancelled;
+    CONTESTS.with(|contests| {
+        contests.borrow_mut().insert(contest.id.clone(), contest.clone());
+    });
+    
+    refund_contest_entries(&contest, reason);
+    
+    contest
+}
+
+// Returns the entry fee of every entry not refunded yet. An entry whose
+// refund fails is recorded as such and left outstanding for a retry.
+fn refund_contest_entries(contest: &Contest, reason: &str) {
+    for mut entry in get_contest_entries(contest.id.clone()) {
+        if entry.refunded_at.is_some() {
+            continue;
+        }
+        
+        let refunded = post_transfer(
+            LedgerAccount::ContestEscrow(contest.id.clone()),
+            LedgerAccount::UserWallet(entry.user_id),
+            contest.entry_fee,
+            TransactionType::Refund,
+        );
+        
+        if contest.entry_fee > 0 {
+            create_transaction(
+                entry.user_id,
+                contest.entry_fee,
+                TransactionType::Refund,
+                if refunded.is_ok() { TransactionStatus::Completed } else { TransactionStatus::Failed },
+            );
+        }
+        
+        if refunded.is_err() {
+            continue;
+        }
+        
+        entry.refunded_at = Some(time());
+        CONTEST_ENTRIES.with(|entries| {
+            entries.borrow_mut().insert(entry.id.clone(), entry.clone());
+        });
+        
+        create_notification(
+            entry.user_id,
+            "Contest cancelled".to_string(),
+            format!(
+                "{} was cancelled: {}. Your entry fee of {} tokens has been refunded.",
+                contest.name, reason, contest.entry_fee
+            ),
+            NotificationType::ContestCancelled,
+        );
+    }
+}
+
+#[update(guard = "is_admin")]
+fn retry_contest_refunds(contest_id: String) -> ContestResponse {
+    let contest = CONTESTS.with(|contests| contests.borrow().get(&contest_id));
+    
+    match contest {
+        Some(c) if c.status == ContestStatus::Cancelled => {
+            refund_contest_entries(&c, "Refund retried");
+            ContestResponse {
+                success: true,
+                data: Some(c),
+                error: None,
+            }
+        }
+        Some(_) => ContestResponse {
+            success: false,
+            data: None,
+            error: Some("Contest is not cancelled".to_string()),
+        },
+        None => ContestResponse {
+            success: false,
+            data: None,
+            error: Some("Contest not found".to_string()),
+        },
+    }
+}
+
+// Entries a contest needs to go ahead. Head-to-head contests must be full.
+fn required_entries(contest: &Contest) -> u32 {
+    match contest.contest_type {
+        ContestType::HeadToHead => HEAD_TO_HEAD_SPOTS,
+        _ => contest.min_entries.unwrap_or(DEFAULT_MIN_ENTRIES.min(contest.total_spots)),
+    }
+}
+
+// Locks a contest at its start time. Non-guaranteed contests below their
+// minimum fill are cancelled and refunded instead of going live.
+#[update(guard = "is_operator")]
+fn start_contest(contest_id: String) -> ContestResponse {
+    let contest = match CONTESTS.with(|contests| contests.borrow().get(&contest_id)) {
+        Some(c) => c,
+        None => {
+            return ContestResponse {
+                success: false,
+                data: None,
+                error: Some("Contest not found".to_string()),
+            };
+        }
+    };
+    
+    if !contest_transition_allowed(&contest.status, &ContestStatus::Live) {
+        return ContestResponse {
+            success: false,
+            data: None,
+            error: Some("Contest is not waiting to start".to_string()),
+        };
+    }
+    
+    if time() < contest.start_time {
+        return ContestResponse {
+            success: false,
+            data: None,
+            error: Some("Contest start time has not been reached".to_string()),
+        };
+    }
+    
+    ContestResponse {
+        success: true,
+        data: Some(lock_contest(contest)),
+        error: None,
+    }
+}
+
+fn lock_contest(mut contest: Contest) -> Contest {
+    let under_filled = contest.filled_spots < required_entries(&contest);
+    
+    if under_filled && contest.contest_type != ContestType::Guaranteed {
+        return cancel_contest_with_refunds(contest, "Not enough entries joined before the start");
+    }
+    
+    contest.status = ContestStatus::Live;
+    CONTESTS.with(|contests| {
+        contests.borrow_mut().insert(contest.id.clone(), contest.clone());
+    });
+    
+    contest
+}
+
+#[update(guard = "is_score_feeder")]
 fn update_entry_points(entry_id: String, points: f64) -> ContestEntryResponse {
-    let mut entry = CONTEST_ENTRIES.with(|entries| entries.borrow().get(&entry_id).cloned());
+    let mut entry = CONTEST_ENTRIES.with(|entries| entries.borrow().get(&entry_id));
     
     if let Some(ref mut e) = entry {
         e.points = points;
@@ -1724,9 +3127,48 @@ fn update_entry_points(entry_id: String, points: f64) -> ContestEntryResponse {
     }
 }
 
-#[update]
+#[update(guard = "is_operator")]
 fn finalize_contest(contest_id: String) -> ContestEntriesResponse {
-    let entries = get_contest_entries(contest_id.clone());
+    let mut contest = match CONTESTS.with(|contests| contests.borrow().get(&contest_id)) {
+        Some(c) => c,
+        None => {
+            return ContestEntriesResponse {
+                success: false,
+                data: None,
+                error: Some("Contest not found".to_string()),
+            };
+        }
+    };
+    
+    if matches!(contest.status, ContestStatus::Completed | ContestStatus::Cancelled) {
+        return ContestEntriesResponse {
+            success: false,
+            data: None,
+            error: Some("Contest is already finalized".to_string()),
+        };
+    }
+    
+    if !contest_transition_allowed(&contest.status, &ContestStatus::Completed) {
+        return ContestEntriesResponse {
+            success: false,
+            data: None,
+            error: Some("Contest has not started".to_string()),
+        };
+    }
+    
+    let match_settled = MATCHES
+        .with(|matches| matches.borrow().get(&contest.match_id))
+        .is_some_and(|match_obj| is_match_settled(&match_obj));
+    if !match_settled {
+        return ContestEntriesResponse {
+            success: false,
+            data: None,
+            error: Some("Match has not been completed and scored".to_string()),
+        };
+    }
+    
+    // Score every entry from the final stats before ranking
+    let entries = rescore_contest_entries(&contest);
     
     if entries.is_empty() {
         return ContestEntriesResponse {
@@ -1736,21 +3178,28 @@ fn finalize_contest(contest_id: String) -> ContestEntriesResponse {
         };
     }
     
-    // Sort entries by points (descending)
-    let mut sorted_entries = entries.clone();
-    sorted_entries.sort_by(|a, b| b.points.partial_cmp(&a.points).unwrap());
+    // Rank entries into groups of tied entries, then pay out the prize pool
+    let mut ranked_groups = rank_entries(&contest, entries);
+    if let Err(error) = pay_contest_prizes(&contest, &mut ranked_groups) {
+        return ContestEntriesResponse {
+            success: false,
+            data: None,
+            error: Some(error),
+        };
+    }
+    
+    let sorted_entries: Vec<ContestEntry> = ranked_groups.into_iter().flatten().collect();
     
-    // Assign ranks
-    for (index, entry) in sorted_entries.iter_mut().enumerate() {
-        entry.rank = Some((index + 1) as u32);
-        
+    for entry in &sorted_entries {
         CONTEST_ENTRIES.with(|entries| {
             entries.borrow_mut().insert(entry.id.clone(), entry.clone());
         });
     }
     
-    // Update contest status
-    update_contest_status(contest_id, ContestStatus::Completed);
+    contest.status = ContestStatus::Completed;
+    CONTESTS.with(|contests| {
+        contests.borrow_mut().insert(contest_id, contest);
+    });
     
     ContestEntriesResponse {
         success: true,
@@ -1759,719 +3208,6304 @@ fn finalize_contest(contest_id: String) -> ContestEntriesResponse {
     }
 }
 
-#[query]
-fn get_contest_count() -> u64 {
-    CONTESTS.with(|contests| contests.borrow().len() as u64)
-}
-
-#[query]
-fn get_entry_count() -> u64 {
-    CONTEST_ENTRIES.with(|entries| entries.borrow().len() as u64)
-}
-
-// --- Scoring Section ---
-// Scoring Management
-#[update]
-fn update_player_score(
-    player_id: String,
-    match_id: String,
-    stats: PlayerStats,
-) -> PlayerScoreResponse {
-    let score_id = format!("{}:{}", player_id, match_id);
-    
-    let mut player_score = PLAYER_SCORES.with(|scores| {
-        scores.borrow().get(&score_id).cloned()
-    });
-    
-    if player_score.is_none() {
-        player_score = Some(PlayerScore {
-            player_id: player_id.clone(),
-            match_id: match_id.clone(),
-            points: 0.0,
-            stats: PlayerStats {
-                runs: None,
-                wickets: None,
-                catches: None,
-                stumpings: None,
-                run_outs: None,
-                overs: None,
-                maidens: None,
-                economy: None,
-                goals: None,
-                assists: None,
-                clean_sheets: None,
-                saves: None,
-                yellow_cards: None,
-                red_cards: None,
-                points: None,
-                rebounds: None,
-                blocks: None,
-                steals: None,
-                turnovers: None,
-            },
-            updated_at: ic_cdk::api::time(),
-        });
-    }
+#[update(guard = "is_operator")]
+fn set_contest_ranking(contest_id: String, ranking: RankingConfig) -> ContestResponse {
+    let mut contest = CONTESTS.with(|contests| contests.borrow().get(&contest_id));
     
-    if let Some(ref mut score) = player_score {
-        score.stats = stats;
-        score.points = calculate_player_points(&score.stats);
-        score.updated_at = ic_cdk::api::time();
+    if let Some(ref mut c) = contest {
+        if matches!(c.status, ContestStatus::Completed | ContestStatus::Cancelled) {
+            return ContestResponse {
+                success: false,
+                data: None,
+                error: Some("Contest is already finalized".to_string()),
+            };
+        }
         
-        PLAYER_SCORES.with(|scores| {
-            scores.borrow_mut().insert(score_id, score.clone());
+        c.ranking = Some(ranking);
+        
+        CONTESTS.with(|contests| {
+            contests.borrow_mut().insert(contest_id, c.clone());
         });
         
-        PlayerScoreResponse {
+        ContestResponse {
             success: true,
-            data: Some(score.clone()),
+            data: Some(c.clone()),
             error: None,
         }
     } else {
-        PlayerScoreResponse {
+        ContestResponse {
             success: false,
             data: None,
-            error: Some("Failed to create player score".to_string()),
+            error: Some("Contest not found".to_string()),
         }
     }
 }
 
-fn calculate_player_points(stats: &PlayerStats) -> f64 {
-    let mut total_points = 0.0;
-    
-    // Cricket points
-    if let Some(runs) = stats.runs {
-        total_points += runs as f64 * 1.0;
-    }
-    if let Some(wickets) = stats.wickets {
-        total_points += wickets as f64 * 10.0;
-    }
-    if let Some(catches) = stats.catches {
-        total_points += catches as f64 * 8.0;
-    }
-    if let Some(stumpings) = stats.stumpings {
-        total_points += stumpings as f64 * 10.0;
-    }
-    if let Some(run_outs) = stats.run_outs {
-        total_points += run_outs as f64 * 6.0;
-    }
-    if let Some(maidens) = stats.maidens {
-        total_points += maidens as f64 * 4.0;
-    }
-    if let Some(economy) = stats.economy {
-        if economy < 4.0 {
-            total_points += 2.0; // Economy bonus
-        }
+// NaN points would otherwise poison the ordering, so they rank last.
+fn sortable_points(points: f64) -> f64 {
+    if points.is_nan() {
+        f64::NEG_INFINITY
+    } else {
+        points
     }
+}
+
+fn captain_points(entry: &ContestEntry, match_id: &str, rules: &[ScoringRule]) -> f64 {
+    let score_id = format!("{}:{}", entry.team.captain_id, match_id);
+    PLAYER_SCORES
+        .with(|scores| scores.borrow().get(&score_id))
+        .map(|score| calculate_points_with_rules(&score.stats, rules) * CAPTAIN_MULTIPLIER)
+        .map(sortable_points)
+        .unwrap_or(0.0)
+}
+
+// Entry ids come from a counter, so they give a stable order among true ties.
+fn entry_sequence(entry: &ContestEntry) -> u64 {
+    entry.id.parse().unwrap_or(u64::MAX)
+}
+
+struct RankingCandidate {
+    points: f64,
+    captain_points: f64,
+    entry: ContestEntry,
+}
+
+// Sorts entries by points and the contest's tie-breakers and assigns ranks.
+// Returns the entries grouped by position, each group holding entries that
+// remain tied after every tie-breaker.
+fn rank_entries(contest: &Contest, entries: Vec<ContestEntry>) -> Vec<Vec<ContestEntry>> {
+    let config = contest.ranking.clone().unwrap_or_default();
+    let uses_captain_points = config.tie_breakers.contains(&TieBreaker::CaptainPoints);
+    let rules = if uses_captain_points {
+        contest_scoring_rules(contest)
+    } else {
+        Vec::new()
+    };
     
-    // Football points
-    if let Some(goals) = stats.goals {
-        total_points += goals as f64 * 6.0;
-    }
-    if let Some(assists) = stats.assists {
-        total_points += assists as f64 * 3.0;
-    }
-    if let Some(clean_sheets) = stats.clean_sheets {
-        total_points += clean_sheets as f64 * 4.0;
-    }
-    if let Some(saves) = stats.saves {
-        total_points += saves as f64 * 1.0;
-    }
-    if let Some(yellow_cards) = stats.yellow_cards {
-        total_points -= yellow_cards as f64 * 1.0;
-    }
-    if let Some(red_cards) = stats.red_cards {
-        total_points -= red_cards as f64 * 3.0;
-    }
+    let mut candidates: Vec<RankingCandidate> = entries
+        .into_iter()
+        .map(|entry| RankingCandidate {
+            points: sortable_points(entry.points),
+            captain_points: if uses_captain_points {
+                captain_points(&entry, &contest.match_id, &rules)
+            } else {
+                0.0
+            },
+            entry,
+        })
+        .collect();
     
-    // Basketball points
-    if let Some(points) = stats.points {
-        total_points += points as f64 * 1.0;
-    }
-    if let Some(rebounds) = stats.rebounds {
-        total_points += rebounds as f64 * 1.2;
-    }
-    if let Some(assists) = stats.assists {
-        total_points += assists as f64 * 1.5;
-    }
-    if let Some(blocks) = stats.blocks {
-        total_points += blocks as f64 * 2.0;
-    }
-    if let Some(steals) = stats.steals {
-        total_points += steals as f64 * 2.0;
-    }
-    if let Some(turnovers) = stats.turnovers {
-        total_points -= turnovers as f64 * 1.0;
+    let compare = |a: &RankingCandidate, b: &RankingCandidate| {
+        let mut ordering = b.points.total_cmp(&a.points);
+        for tie_breaker in &config.tie_breakers {
+            ordering = ordering.then_with(|| match tie_breaker {
+                TieBreaker::EarliestEntry => a.entry.created_at.cmp(&b.entry.created_at),
+                TieBreaker::CaptainPoints => b.captain_points.total_cmp(&a.captain_points),
+            });
+        }
+        ordering
+    };
+    
+    candidates.sort_by(|a, b| {
+        compare(a, b)
+            .then_with(|| a.entry.created_at.cmp(&b.entry.created_at))
+            .then_with(|| entry_sequence(&a.entry).cmp(&entry_sequence(&b.entry)))
+    });
+    
+    let mut groups: Vec<Vec<ContestEntry>> = Vec::new();
+    
+    for (index, candidate) in candidates.iter().enumerate() {
+        let tied = index > 0 && compare(&candidates[index - 1], candidate).is_eq();
+        let mut entry = candidate.entry.clone();
+        
+        match groups.last_mut() {
+            Some(group) if tied => {
+                entry.rank = group[0].rank;
+                group.push(entry);
+            }
+            _ => {
+                let rank = match config.method {
+                    RankingMethod::Standard => index as u32 + 1,
+                    RankingMethod::Dense => groups.len() as u32 + 1,
+                };
+                entry.rank = Some(rank);
+                groups.push(vec![entry]);
+            }
+        }
     }
     
-    total_points
+    groups
 }
 
 #[query]
-fn get_player_score(player_id: String, match_id: String) -> PlayerScoreResponse {
-    let score_id = format!("{}:{}", player_id, match_id);
+fn get_contest_count() -> u64 {
+    CONTESTS.with(|contests| contests.borrow().len())
+}
+
+#[query]
+fn get_entry_count() -> u64 {
+    CONTEST_ENTRIES.with(|entries| entries.borrow().len())
+}
+
+// --- Leaderboard Section ---
+#[query]
+fn get_contest_leaderboard(contest_id: String, offset: u32, limit: u32) -> LeaderboardResponse {
+    let Some(contest) = CONTESTS.with(|contests| contests.borrow().get(&contest_id)) else {
+        return LeaderboardResponse {
+            success: false,
+            data: None,
+            error: Some("Contest not found".to_string()),
+        };
+    };
     
-    let player_score = PLAYER_SCORES.with(|scores| {
-        scores.borrow().get(&score_id).cloned()
-    });
+    let standings = contest_standings(&contest);
+    let total = standings.len() as u64;
+    let entries = standings
+        .iter()
+        .skip(offset as usize)
+        .take(limit.min(MAX_LEADERBOARD_PAGE_SIZE) as usize)
+        .map(|entry| leaderboard_entry(entry.rank.unwrap_or(0), entry.user_id, entry.points, entry.prize))
+        .collect();
     
-    match player_score {
-        Some(score) => PlayerScoreResponse {
-            success: true,
-            data: Some(score),
-            error: None,
-        },
-        None => PlayerScoreResponse {
+    LeaderboardResponse {
+        success: true,
+        data: Some(LeaderboardPage { entries, total }),
+        error: None,
+    }
+}
+
+// The caller's rows in a contest's standings, one per entry they hold
+#[query]
+fn get_my_rank(contest_id: String) -> LeaderboardResponse {
+    let caller = caller();
+    
+    let Some(contest) = CONTESTS.with(|contests| contests.borrow().get(&contest_id)) else {
+        return LeaderboardResponse {
             success: false,
             data: None,
-            error: Some("Player score not found".to_string()),
-        },
+            error: Some("Contest not found".to_string()),
+        };
+    };
+    
+    let standings = contest_standings(&contest);
+    let total = standings.len() as u64;
+    let entries: Vec<LeaderboardEntry> = standings
+        .iter()
+        .filter(|entry| entry.user_id == caller)
+        .map(|entry| leaderboard_entry(entry.rank.unwrap_or(0), entry.user_id, entry.points, entry.prize))
+        .collect();
+    
+    if entries.is_empty() {
+        return LeaderboardResponse {
+            success: false,
+            data: None,
+            error: Some("You have not joined this contest".to_string()),
+        };
+    }
+    
+    LeaderboardResponse {
+        success: true,
+        data: Some(LeaderboardPage { entries, total }),
+        error: None,
     }
 }
 
+// Totals every user's points and winnings across contests and season leagues,
+// optionally limited to one tournament, and ranks them by the chosen metric.
 #[query]
-fn get_match_scores(match_id: String) -> Vec<PlayerScore> {
-    PLAYER_SCORES.with(|scores| {
-        scores
+fn get_global_leaderboard(
+    tournament_id: Option<String>,
+    metric: LeaderboardMetric,
+    offset: u32,
+    limit: u32,
+) -> LeaderboardResponse {
+    let in_scope = |match_tournament_id: Option<String>| match &tournament_id {
+        Some(id) => match_tournament_id.as_ref() == Some(id),
+        None => true,
+    };
+    
+    let mut totals: BTreeMap<Principal, (f64, u64)> = BTreeMap::new();
+    let mut add = |user_id: Principal, points: f64, prize: u64| {
+        // Entries whose points could not be computed count as zero
+        let points = if points.is_finite() { points } else { 0.0 };
+        let (total_points, total_prize) = totals.entry(user_id).or_insert((0.0, 0));
+        *total_points += points;
+        *total_prize += prize;
+    };
+    
+    let contests: Vec<Contest> = CONTESTS.with(|contests| {
+        contests
             .borrow()
             .iter()
-            .filter(|(_, score)| score.match_id == match_id)
-            .map(|(_, score)| score.clone())
+            .map(|(_, contest)| contest)
+            .filter(|contest| contest.status != ContestStatus::Cancelled)
+            .filter(|contest| {
+                in_scope(
+                    MATCHES
+                        .with(|matches| matches.borrow().get(&contest.match_id))
+                        .map(|match_obj| match_obj.tournament_id),
+                )
+            })
             .collect()
-    })
-}
-
-#[update]
-fn add_scoring_rule(sport: Sport, action: String, points: f64) -> ScoringRuleResponse {
-    let rule = ScoringRule {
-        action: action.clone(),
-        points,
-        sport: sport.clone(),
-    };
+    });
+    
+    for contest in contests {
+        for entry in get_contest_entries(contest.id) {
+            add(entry.user_id, entry.points, entry.prize.unwrap_or(0));
+        }
+    }
     
-    let mut rules = SCORING_RULES.with(|scoring_rules| {
-        scoring_rules.borrow().get(&sport).cloned().unwrap_or(vec![])
+    let leagues: Vec<SeasonLeague> = SEASON_LEAGUES.with(|leagues| {
+        leagues
+            .borrow()
+            .iter()
+            .map(|(_, league)| league)
+            .filter(|league| league.status != SeasonLeagueStatus::Cancelled)
+            .filter(|league| in_scope(Some(league.tournament_id.clone())))
+            .collect()
     });
-    rules.push(rule.clone());
     
-    SCORING_RULES.with(|scoring_rules| {
-        scoring_rules.borrow_mut().insert(sport, rules);
+    for league in leagues {
+        for entry in season_entries(&league.id) {
+            add(entry.user_id, entry.total_points, entry.prize.unwrap_or(0));
+        }
+    }
+    
+    let key = |points: f64, prize: u64| match metric {
+        LeaderboardMetric::Winnings => (prize as f64, points),
+        LeaderboardMetric::Points => (points, prize as f64),
+    };
+    
+    let mut totals: Vec<(Principal, f64, u64)> = totals
+        .into_iter()
+        .map(|(user_id, (points, prize))| (user_id, points, prize))
+        .collect();
+    totals.sort_by(|a, b| {
+        let (a_first, a_second) = key(a.1, a.2);
+        let (b_first, b_second) = key(b.1, b.2);
+        b_first
+            .total_cmp(&a_first)
+            .then_with(|| b_second.total_cmp(&a_second))
+            .then_with(|| a.0.cmp(&b.0))
     });
     
-    ScoringRuleResponse {
+    // Users level on both totals share a rank
+    let mut ranks: Vec<u32> = Vec::with_capacity(totals.len());
+    for (index, (_, points, prize)) in totals.iter().enumerate() {
+        let tied = index > 0 && key(totals[index - 1].1, totals[index - 1].2) == key(*points, *prize);
+        ranks.push(if tied { ranks[index - 1] } else { index as u32 + 1 });
+    }
+    
+    // Usernames are only looked up for the requested page
+    let total = totals.len() as u64;
+    let entries = totals
+        .into_iter()
+        .zip(ranks)
+        .skip(offset as usize)
+        .take(limit.min(MAX_LEADERBOARD_PAGE_SIZE) as usize)
+        .map(|((user_id, points, prize), rank)| leaderboard_entry(rank, user_id, points, Some(prize)))
+        .collect();
+    
+    LeaderboardResponse {
         success: true,
-        data: Some(rule),
+        data: Some(LeaderboardPage { entries, total }),
         error: None,
     }
 }
 
-#[query]
-fn get_scoring_rules(sport: Sport) -> Vec<ScoringRule> {
-    SCORING_RULES.with(|rules| {
-        rules.borrow().get(&sport).cloned().unwrap_or(vec![])
-    })
+// Finished contests keep the ranks they were paid out on; others are ranked
+// live from the current points.
+fn contest_standings(contest: &Contest) -> Vec<ContestEntry> {
+    let entries = get_contest_entries(contest.id.clone());
+    
+    if contest.status == ContestStatus::Completed {
+        let mut entries = entries;
+        entries.sort_by_key(|entry| (entry.rank.unwrap_or(u32::MAX), entry_sequence(entry)));
+        entries
+    } else {
+        rank_entries(contest, entries).into_iter().flatten().collect()
+    }
 }
 
-#[query]
-fn calculate_fantasy_team_points(
-    player_ids: Vec<String>,
-    match_id: String,
-    captain_id: String,
-    vice_captain_id: String,
-) -> f64 {
-    let mut total_points = 0.0;
+fn leaderboard_entry(rank: u32, user_id: Principal, points: f64, prize: Option<u64>) -> LeaderboardEntry {
+    let username = USERS
+        .with(|users| users.borrow().get(&user_id))
+        .map(|user| user.username)
+        .unwrap_or_default();
     
-    for player_id in player_ids {
-        let score = PLAYER_SCORES.with(|scores| {
-            let score_id = format!("{}:{}", player_id, match_id);
-            scores.borrow().get(&score_id).cloned()
-        });
-        
-        if let Some(player_score) = score {
-            let mut points = player_score.points;
-            
-            // Apply captain/vice-captain multipliers
-            if player_id == captain_id {
-                points *= 2.0;
-            } else if player_id == vice_captain_id {
-                points *= 1.5;
-            }
-            
-            total_points += points;
-        }
+    LeaderboardEntry {
+        rank,
+        user_id,
+        username,
+        points,
+        prize,
     }
-    
-    total_points
 }
 
-#[query]
-fn get_all_player_scores() -> Vec<PlayerScore> {
-    PLAYER_SCORES.with(|scores| {
-        scores.borrow().iter().map(|(_, score)| score.clone()).collect()
+// --- Private League Section ---
+fn is_private_contest(contest: &Contest) -> bool {
+    contest.visibility == Some(ContestVisibility::Private)
+}
+
+// Private contests the user created that are still taking entries
+fn open_private_contest_count(creator: Principal) -> usize {
+    CONTESTS.with(|contests| {
+        contests
+            .borrow()
+            .iter()
+            .filter(|(_, contest)| {
+                is_private_contest(contest)
+                    && contest.creator == Some(creator)
+                    && matches!(contest.status, ContestStatus::Open | ContestStatus::Full)
+            })
+            .count()
     })
 }
 
-#[query]
-fn get_score_count() -> u64 {
-    PLAYER_SCORES.with(|scores| scores.borrow().len() as u64)
+// Whether a creator may open another private contest on a match
+fn private_contest_slot_decision(locked: bool, open_contests: usize) -> Result<(), String> {
+    if locked {
+        return Err("Match is locked for new contests".to_string());
+    }
+    if open_contests >= MAX_OPEN_PRIVATE_CONTESTS_PER_USER {
+        return Err(format!(
+            "You cannot have more than {} open private contests",
+            MAX_OPEN_PRIVATE_CONTESTS_PER_USER
+        ));
+    }
+    Ok(())
+}
+
+// Checks that can change while waiting for randomness, so they are repeated
+// once the invite code has been generated
+fn check_private_contest_slot(creator: Principal, match_id: &str) -> Result<(), String> {
+    private_contest_slot_decision(is_match_locked(match_id), open_private_contest_count(creator))
 }
 
-// --- Rewards Section ---
-// Prize Pool Management
 #[update]
-fn create_prize_pool(
-    contest_id: String,
-    total_amount: u64,
+async fn create_private_contest(
+    name: String,
+    match_id: String,
+    entry_fee: u64,
+    total_spots: u32,
     distribution: Vec<PrizeDistribution>,
-) -> PrizePoolResponse {
-    let prize_pool = PrizePool {
-        contest_id: contest_id.clone(),
-        total_amount,
-        distribution,
-        distributed: false,
+) -> PrivateContestResponse {
+    let caller = caller();
+    
+    let validation = if !USERS.with(|users| users.borrow().contains_key(&caller)) {
+        Err("User not found".to_string())
+    } else if entry_fee > MAX_PRIVATE_ENTRY_FEE {
+        Err(format!("Entry fee cannot exceed {} tokens", MAX_PRIVATE_ENTRY_FEE))
+    } else if !(MIN_PRIVATE_SPOTS..=MAX_PRIVATE_SPOTS).contains(&total_spots) {
+        Err(format!(
+            "Private contests must have between {} and {} spots",
+            MIN_PRIVATE_SPOTS, MAX_PRIVATE_SPOTS
+        ))
+    } else if distribution.iter().any(|tier| tier.rank_end.unwrap_or(tier.rank) > total_spots) {
+        Err("Prizes cannot be paid to ranks beyond the number of spots".to_string())
+    } else {
+        Ok(())
+    };
+    
+    // The pool is whatever the entry fees add up to
+    let prize_pool = entry_fee * u64::from(total_spots);
+    let validation = validation.and_then(|_| {
+        let match_obj = MATCHES
+            .with(|matches| matches.borrow().get(&match_id))
+            .ok_or_else(|| "Match not found".to_string())?;
+        check_private_contest_slot(caller, &match_id)?;
+        let sport = get_match_sport(&match_id).ok_or_else(|| "Match sport not found".to_string())?;
+        let distribution = resolve_prize_distribution(prize_pool, distribution)?;
+        Ok((match_obj, sport, distribution))
+    });
+    
+    let (match_obj, sport, distribution) = match validation {
+        Ok(validated) => validated,
+        Err(error) => {
+            return PrivateContestResponse {
+                success: false,
+                data: None,
+                error: Some(error),
+            };
+        }
+    };
+    
+    let random_bytes = match ic_cdk::api::management_canister::main::raw_rand().await {
+        Ok((bytes,)) => bytes,
+        Err((_, message)) => {
+            return PrivateContestResponse {
+                success: false,
+                data: None,
+                error: Some(format!("Failed to generate invite code: {}", message)),
+            };
+        }
+    };
+    
+    if let Err(error) = check_private_contest_slot(caller, &match_id) {
+        return PrivateContestResponse {
+            success: false,
+            data: None,
+            error: Some(error),
+        };
+    }
+    
+    let invite_code = random_bytes
+        .chunks(INVITE_CODE_LENGTH)
+        .filter(|chunk| chunk.len() == INVITE_CODE_LENGTH)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .map(|byte| INVITE_CODE_ALPHABET[*byte as usize % INVITE_CODE_ALPHABET.len()] as char)
+                .collect::<String>()
+        })
+        .find(|code| INVITE_CODES.with(|codes| !codes.borrow().contains_key(code)));
+    
+    let Some(invite_code) = invite_code else {
+        return PrivateContestResponse {
+            success: false,
+            data: None,
+            error: Some("Failed to generate a unique invite code".to_string()),
+        };
+    };
+    
+    let contest_id = get_next_id(&NEXT_CONTEST_ID).to_string();
+    
+    let contest = Contest {
+        id: contest_id.clone(),
+        name,
+        match_id,
+        entry_fee,
+        total_spots,
+        filled_spots: 0,
+        prize_pool,
+        contest_type: ContestType::MultiPlayer,
+        status: ContestStatus::Open,
+        created_at: time(),
+        // Private leagues start with their match
+        start_time: match_obj.start_time,
+        scoring_rule_set_id: latest_rule_set(&sport).map(|rule_set| rule_set.id),
+        ranking: None,
+        min_entries: Some(DEFAULT_MIN_ENTRIES),
+        max_entries_per_user: Some(1),
+        creator: Some(caller),
+        visibility: Some(ContestVisibility::Private),
     };
     
+    CONTESTS.with(|contests| {
+        contests.borrow_mut().insert(contest_id.clone(), contest.clone());
+    });
+    
+    CONTEST_ENTRIES_BY_CONTEST.with(|contest_entries| {
+        contest_entries.borrow_mut().insert(contest_id.clone(), StorableVec::default());
+    });
+    add_contest_to_match(&contest.match_id, &contest_id);
+    
     PRIZE_POOLS.with(|pools| {
-        pools.borrow_mut().insert(contest_id, prize_pool.clone());
+        pools.borrow_mut().insert(
+            contest_id.clone(),
+            PrizePool {
+                contest_id: contest_id.clone(),
+                total_amount: prize_pool,
+                distribution,
+                distributed: false,
+                rake: None,
+            },
+        );
     });
     
-    PrizePoolResponse {
+    INVITE_CODES.with(|codes| {
+        codes.borrow_mut().insert(invite_code.clone(), contest_id);
+    });
+    
+    schedule_lifecycle_timer();
+    
+    PrivateContestResponse {
         success: true,
-        data: Some(prize_pool),
+        data: Some(PrivateContest { contest, invite_code }),
         error: None,
     }
 }
 
+#[update]
+fn join_contest_by_code(invite_code: String, fantasy_team_id: String) -> Result<ContestEntry, FantasyError> {
+    let contest_id = INVITE_CODES
+        .with(|codes| codes.borrow().get(&invite_code.trim().to_uppercase()))
+        .ok_or(FantasyError::ContestNotFound)?;
+    
+    enter_contest(contest_id, fantasy_team_id)
+}
+
+// Lets the creator look up the invite code of their league again
 #[query]
-fn get_prize_pool(contest_id: String) -> PrizePoolResponse {
-    let pool = PRIZE_POOLS.with(|pools| pools.borrow().get(&contest_id).cloned());
+fn get_private_contest(contest_id: String) -> PrivateContestResponse {
+    let caller = caller();
     
-    match pool {
-        Some(p) => PrizePoolResponse {
+    let contest = CONTESTS
+        .with(|contests| contests.borrow().get(&contest_id))
+        .filter(|contest| is_private_contest(contest) && contest.creator == Some(caller));
+    let invite_code = INVITE_CODES.with(|codes| {
+        codes
+            .borrow()
+            .iter()
+            .find(|(_, id)| *id == contest_id)
+            .map(|(code, _)| code)
+    });
+    
+    match (contest, invite_code) {
+        (Some(contest), Some(invite_code)) => PrivateContestResponse {
             success: true,
-            data: Some(p),
+            data: Some(PrivateContest { contest, invite_code }),
             error: None,
         },
-        None => PrizePoolResponse {
+        _ => PrivateContestResponse {
             success: false,
             data: None,
-            error: Some("Prize pool not found".to_string()),
+            error: Some("Private contest not found".to_string()),
         },
     }
 }
 
-// Reward Distribution
 #[update]
-fn distribute_contest_rewards(
-    contest_id: String,
-    winners: Vec<(Principal, u32, u64)>, // (user_id, rank, amount)
-) -> UserRewardsResponse {
-    let mut rewards = Vec::new();
+fn cancel_private_contest(contest_id: String) -> ContestResponse {
+    let caller = caller();
     
-    for (user_id, rank, amount) in winners {
-        let reward_id = get_next_id(&NEXT_REWARD_ID);
-        
-        let user_reward = UserReward {
-            id: reward_id.to_string(),
-            user_id,
-            contest_id: contest_id.clone(),
-            amount,
-            rank,
-            status: RewardStatus::Pending,
-            created_at: ic_cdk::api::time(),
-            claimed_at: None,
-        };
-        
-        USER_REWARDS.with(|rewards_map| {
-            let mut user_rewards = rewards_map.borrow().get(&user_id).cloned().unwrap_or(vec![]);
-            user_rewards.push(user_reward.clone());
-            rewards_map.borrow_mut().insert(user_id, user_rewards);
-        });
-        
-        rewards.push(user_reward);
-    }
-    
-    // Mark prize pool as distributed
-    let mut prize_pool = PRIZE_POOLS.with(|pools| pools.borrow().get(&contest_id).cloned());
-    if let Some(ref mut pool) = prize_pool {
-        pool.distributed = true;
-        PRIZE_POOLS.with(|pools| {
-            pools.borrow_mut().insert(contest_id, pool.clone());
-        });
-    }
-    
-    UserRewardsResponse {
-        success: true,
-        data: Some(rewards),
-        error: None,
-    }
-}
-
-#[update]
-fn claim_reward(reward_id: String) -> UserRewardResponse {
-    let mut reward = find_user_reward(reward_id.clone());
+    let contest = CONTESTS
+        .with(|contests| contests.borrow().get(&contest_id))
+        .filter(is_private_contest);
     
-    if let Some(ref mut r) = reward {
-        if r.status == RewardStatus::Pending {
-            r.status = RewardStatus::Claimed;
-            r.claimed_at = Some(ic_cdk::api::time());
-            
-            update_user_reward(r.clone());
-            
-            // Add balance to user
-            add_balance(r.user_id, r.amount);
-            
-            UserRewardResponse {
-                success: true,
-                data: Some(r.clone()),
-                error: None,
-            }
-        } else {
-            UserRewardResponse {
-                success: false,
-                data: None,
-                error: Some("Reward already claimed or failed".to_string()),
-            }
+    let error = match &contest {
+        None => Some("Private contest not found".to_string()),
+        Some(c) if c.creator != Some(caller) => Some("Only the creator can cancel this contest".to_string()),
+        Some(c) if !matches!(c.status, ContestStatus::Open | ContestStatus::Full) || is_match_locked(&c.match_id) => {
+            Some("Contest has already started".to_string())
         }
-    } else {
-        UserRewardResponse {
+        Some(_) => None,
+    };
+    
+    match (contest, error) {
+        (Some(c), None) => ContestResponse {
+            success: true,
+            data: Some(cancel_contest_with_refunds(c, "Cancelled by the league creator")),
+            error: None,
+        },
+        (_, error) => ContestResponse {
             success: false,
             data: None,
-            error: Some("Reward not found".to_string()),
-        }
+            error,
+        },
     }
 }
 
-fn find_user_reward(reward_id: String) -> Option<UserReward> {
-    USER_REWARDS.with(|rewards| {
-        for (_, user_rewards) in rewards.borrow().iter() {
-            for reward in user_rewards {
-                if reward.id == reward_id {
-                    return Some(reward.clone());
-                }
-            }
+// --- Season League Section ---
+#[update(guard = "is_operator")]
+fn create_season_league(
+    name: String,
+    tournament_id: String,
+    entry_fee: u64,
+    max_members: u32,
+    transfers_per_matchday: u32,
+    distribution: Vec<PrizeDistribution>,
+) -> SeasonLeagueResponse {
+    let tournament = TOURNAMENTS.with(|tournaments| tournaments.borrow().get(&tournament_id));
+    
+    let validated = match tournament {
+        None => Err("Tournament not found".to_string()),
+        Some(t) if matches!(t.status, TournamentStatus::Completed | TournamentStatus::Cancelled) => {
+            Err("Tournament is already finished".to_string())
         }
-        None
-    })
-}
-
-fn update_user_reward(updated_reward: UserReward) {
-    USER_REWARDS.with(|rewards| {
-        for (user_id, user_rewards) in rewards.borrow_mut().iter_mut() {
-            for reward in user_rewards {
-                if reward.id == updated_reward.id {
-                    *reward = updated_reward.clone();
-                    return;
-                }
-            }
+        Some(_) if max_members < MIN_PRIVATE_SPOTS => {
+            Err(format!("Season leagues need at least {} members", MIN_PRIVATE_SPOTS))
         }
-    });
-}
-
-fn create_transaction(
-    user_id: Principal,
-    amount: u64,
-    transaction_type: TransactionType,
-    status: TransactionStatus,
-) {
-    let transaction_id = get_next_id(&NEXT_TRANSACTION_ID);
+        Some(_) if distribution.iter().any(|tier| tier.rank_end.unwrap_or(tier.rank) > max_members) => {
+            Err("Prizes cannot be paid to ranks beyond the number of members".to_string())
+        }
+        Some(t) => resolve_prize_distribution(entry_fee * u64::from(max_members), distribution)
+            .map(|distribution| (t, distribution)),
+    };
     
-    let transaction = RewardTransaction {
-        id: transaction_id.to_string(),
-        user_id,
-        amount,
-        transaction_type,
-        status,
-        created_at: ic_cdk::api::time(),
-        completed_at: None,
+    let (tournament, distribution) = match validated {
+        Ok(validated) => validated,
+        Err(error) => {
+            return SeasonLeagueResponse {
+                success: false,
+                data: None,
+                error: Some(error),
+            };
+        }
     };
     
-    REWARD_HISTORY.with(|history| {
-        history.borrow_mut().insert(transaction_id.to_string(), transaction);
-    });
-}
-
-#[query]
-fn get_user_rewards(user_id: Option<Principal>) -> UserRewardsResponse {
-    let user_id = user_id.unwrap_or_else(caller);
+    // Shares the contest counter so prize pools and rewards never collide
+    let league_id = get_next_id(&NEXT_CONTEST_ID).to_string();
     
-    let rewards = USER_REWARDS.with(|rewards| {
-        rewards.borrow().get(&user_id).cloned().unwrap_or(vec![])
+    let league = SeasonLeague {
+        id: league_id.clone(),
+        name,
+        tournament_id,
+        entry_fee,
+        max_members,
+        member_count: 0,
+        transfers_per_matchday,
+        distribution,
+        scoring_rule_set_id: latest_rule_set(&tournament.sport).map(|rule_set| rule_set.id),
+        status: SeasonLeagueStatus::Open,
+        created_at: time(),
+    };
+    
+    SEASON_LEAGUES.with(|leagues| {
+        leagues.borrow_mut().insert(league_id, league.clone());
     });
     
-    UserRewardsResponse {
+    SeasonLeagueResponse {
         success: true,
-        data: Some(rewards),
+        data: Some(league),
         error: None,
     }
 }
 
 #[query]
-fn get_user_transactions(user_id: Option<Principal>) -> RewardTransactionsResponse {
-    let user_id = user_id.unwrap_or_else(caller);
-    
-    let transactions = REWARD_HISTORY.with(|history| {
-        history
-            .borrow()
-            .iter()
-            .filter(|(_, transaction)| transaction.user_id == user_id)
-            .map(|(_, transaction)| transaction.clone())
-            .collect()
-    });
+fn get_season_league(league_id: String) -> SeasonLeagueResponse {
+    let league = SEASON_LEAGUES.with(|leagues| leagues.borrow().get(&league_id));
     
-    RewardTransactionsResponse {
-        success: true,
-        data: Some(transactions),
-        error: None,
+    match league {
+        Some(l) => SeasonLeagueResponse {
+            success: true,
+            data: Some(l),
+            error: None,
+        },
+        None => SeasonLeagueResponse {
+            success: false,
+            data: None,
+            error: Some("Season league not found".to_string()),
+        },
     }
 }
 
 #[query]
-fn get_pending_rewards(user_id: Option<Principal>) -> UserRewardsResponse {
-    let user_id = user_id.unwrap_or_else(caller);
-    
-    let rewards = USER_REWARDS.with(|rewards| {
-        rewards
+fn get_season_leagues(tournament_id: String) -> Vec<SeasonLeague> {
+    SEASON_LEAGUES.with(|leagues| {
+        leagues
             .borrow()
-            .get(&user_id)
-            .cloned()
-            .unwrap_or(vec![])
-            .into_iter()
-            .filter(|reward| reward.status == RewardStatus::Pending)
+            .iter()
+            .filter(|(_, league)| league.tournament_id == tournament_id)
+            .map(|(_, league)| league)
             .collect()
-    });
-    
-    UserRewardsResponse {
-        success: true,
-        data: Some(rewards),
-        error: None,
-    }
+    })
 }
 
 #[update]
-fn create_bonus_reward(user_id: Principal, amount: u64, reason: String) -> UserRewardResponse {
-    let reward_id = get_next_id(&NEXT_REWARD_ID);
+fn join_season_league(
+    league_id: String,
+    team_name: String,
+    captain_id: String,
+    vice_captain_id: String,
+    player_ids: Vec<String>,
+) -> SeasonEntryResponse {
+    let caller = caller();
+    let now = time();
+    let entry_id = season_entry_key(&league_id, &caller);
     
-    let user_reward = UserReward {
-        id: reward_id.to_string(),
-        user_id,
-        contest_id: "bonus".to_string(),
-        amount,
-        rank: 0,
-        status: RewardStatus::Pending,
-        created_at: ic_cdk::api::time(),
-        claimed_at: None,
+    let league = SEASON_LEAGUES.with(|leagues| leagues.borrow().get(&league_id));
+    let user = USERS.with(|users| users.borrow().get(&caller));
+    
+    let validated = match (league, user) {
+        (None, _) => Err("Season league not found".to_string()),
+        (_, None) => Err("User not found".to_string()),
+        (Some(l), _) if l.status != SeasonLeagueStatus::Open => Err("Season league is closed".to_string()),
+        (Some(l), _) if l.member_count >= l.max_members => Err("Season league is full".to_string()),
+        (Some(l), Some(u)) if u.balance < l.entry_fee => Err("Insufficient balance".to_string()),
+        _ if SEASON_ENTRIES.with(|entries| entries.borrow().contains_key(&entry_id)) => {
+            Err("Already joined this season league".to_string())
+        }
+        (Some(l), Some(_)) => check_season_team(&l.tournament_id, &captain_id, &vice_captain_id, &player_ids)
+            .map(|total_price| (l, total_price)),
     };
     
-    USER_REWARDS.with(|rewards_map| {
-        let mut user_rewards = rewards_map.borrow().get(&user_id).cloned().unwrap_or(vec![]);
-        user_rewards.push(user_reward.clone());
-        rewards_map.borrow_mut().insert(user_id, user_rewards);
+    let (mut league, total_price) = match validated {
+        Ok(validated) => validated,
+        Err(error) => {
+            return SeasonEntryResponse {
+                success: false,
+                data: None,
+                error: Some(error),
+            };
+        }
+    };
+    
+    // Charge the entry fee into the league's escrow
+    if let Err(error) = post_transfer(
+        LedgerAccount::UserWallet(caller),
+        LedgerAccount::ContestEscrow(league_id.clone()),
+        league.entry_fee,
+        TransactionType::ContestEntry,
+    ) {
+        return SeasonEntryResponse {
+            success: false,
+            data: None,
+            error: Some(error),
+        };
+    }
+    
+    if league.entry_fee > 0 {
+        create_transaction(
+            caller,
+            league.entry_fee,
+            TransactionType::ContestEntry,
+            TransactionStatus::Completed,
+        );
+    }
+    
+    let mut entry = SeasonEntry {
+        id: entry_id.clone(),
+        league_id: league_id.clone(),
+        user_id: caller,
+        team: FantasyTeam {
+            id: entry_id.clone(),
+            name: team_name,
+            match_id: None,
+            captain_id: String::new(),
+            vice_captain_id: String::new(),
+            players: Vec::new(),
+            total_points: 0.0,
+            total_price: 0,
+        },
+        matchdays: Vec::new(),
+        total_points: 0.0,
+        transfer_window: None,
+        transfers_used: 0,
+        rank: None,
+        prize: None,
+        joined_at: now,
+        refunded_at: None,
+    };
+    
+    // Matches that locked before joining are recorded with an empty squad
+    freeze_locked_matchdays(&mut entry, &tournament_matches(&league.tournament_id));
+    
+    entry.team.captain_id = captain_id;
+    entry.team.vice_captain_id = vice_captain_id;
+    entry.team.players = player_ids;
+    entry.team.total_price = total_price;
+    
+    SEASON_ENTRIES.with(|entries| {
+        entries.borrow_mut().insert(entry_id, entry.clone());
     });
     
-    // Create transaction record
-    create_transaction(
-        user_id,
-        amount,
-        TransactionType::Bonus,
-        TransactionStatus::Pending,
-    );
+    league.member_count += 1;
+    SEASON_LEAGUES.with(|leagues| {
+        leagues.borrow_mut().insert(league_id, league);
+    });
     
-    UserRewardResponse {
+    SeasonEntryResponse {
         success: true,
-        data: Some(user_reward),
+        data: Some(entry),
         error: None,
     }
 }
 
-#[query]
-fn get_total_rewards_distributed() -> u64 {
-    USER_REWARDS.with(|rewards| {
-        rewards
-            .borrow()
-            .iter()
-            .flat_map(|(_, user_rewards)| user_rewards)
-            .filter(|reward| reward.status == RewardStatus::Claimed)
-            .map(|reward| reward.amount)
-            .sum()
-    })
-}
-
-#[query]
-fn get_reward_count() -> u64 {
-    USER_REWARDS.with(|rewards| {
-        rewards
-            .borrow()
-            .iter()
-            .map(|(_, user_rewards)| user_rewards.len() as u64)
-            .sum()
-    })
-}
-
-#[query]
-fn get_transaction_count() -> u64 {
-    REWARD_HISTORY.with(|history| history.borrow().len() as u64)
-}
-
-// --- Team Management Section ---
-// Fantasy Team Management
+// Changes the squad for the upcoming matches. Every player brought in counts
+// against the league's transfer budget for the next matchday; captaincy
+// changes are free.
 #[update]
-fn create_fantasy_team(
-    name: String,
+fn update_season_team(
+    league_id: String,
     captain_id: String,
     vice_captain_id: String,
     player_ids: Vec<String>,
-) -> FantasyTeamResponse {
+) -> SeasonEntryResponse {
     let caller = caller();
     
-    // Validate team size
-    if player_ids.len() != MAX_TEAM_SIZE as usize {
-        return FantasyTeamResponse {
-            success: false,
-            data: None,
-            error: Some(format!("Team must have exactly {} players", MAX_TEAM_SIZE)),
-        };
-    }
+    let league = SEASON_LEAGUES.with(|leagues| leagues.borrow().get(&league_id));
+    let entry = SEASON_ENTRIES.with(|entries| entries.borrow().get(&season_entry_key(&league_id, &caller)));
     
-    // Validate captain and vice-captain are in the team
-    if !player_ids.contains(&captain_id) || !player_ids.contains(&vice_captain_id) {
-        return FantasyTeamResponse {
+    let validated = match (league, entry) {
+        (None, _) => Err("Season league not found".to_string()),
+        (_, None) => Err("Season entry not found".to_string()),
+        (Some(l), _) if l.status != SeasonLeagueStatus::Open => Err("Season league is closed".to_string()),
+        (Some(l), Some(e)) => check_season_team(&l.tournament_id, &captain_id, &vice_captain_id, &player_ids)
+            .map(|total_price| (l, e, total_price)),
+    };
+    
+    let (league, mut entry, total_price) = match validated {
+        Ok(validated) => validated,
+        Err(error) => {
+            return SeasonEntryResponse {
+                success: false,
+                data: None,
+                error: Some(error),
+            };
+        }
+    };
+    
+    let matches = tournament_matches(&league.tournament_id);
+    let Some(next_matchday) = matches.iter().find(|match_obj| !is_match_locked(&match_obj.id)) else {
+        return SeasonEntryResponse {
             success: false,
             data: None,
-            error: Some("Captain and vice-captain must be in the team".to_string()),
+            error: Some("No upcoming matches left in the tournament".to_string()),
         };
-    }
+    };
     
-    // Calculate total price
-    let mut total_price = 0u64;
-    for player_id in &player_ids {
-        if let Some(player) = PLAYERS.with(|players| players.borrow().get(player_id).cloned()) {
-            total_price += player.price;
-        }
-    }
+    let transfers = player_ids
+        .iter()
+        .filter(|player_id| !entry.team.players.contains(player_id))
+        .count() as u32;
+    let used = if entry.transfer_window.as_ref() == Some(&next_matchday.id) {
+        entry.transfers_used
+    } else {
+        0
+    };
     
-    // Validate team price
-    if total_price > MAX_TEAM_PRICE || total_price < MIN_TEAM_PRICE {
-        return FantasyTeamResponse {
+    if used + transfers > league.transfers_per_matchday {
+        return SeasonEntryResponse {
             success: false,
             data: None,
             error: Some(format!(
-                "Team price must be between {} and {} tokens",
-                MIN_TEAM_PRICE, MAX_TEAM_PRICE
+                "Only {} transfers left for the next matchday",
+                league.transfers_per_matchday.saturating_sub(used)
             )),
         };
     }
     
-    let team_id = get_next_id(&NEXT_FANTASY_TEAM_ID);
+    // Matches that already locked keep the squad they were played with
+    freeze_locked_matchdays(&mut entry, &matches);
     
-    let fantasy_team = FantasyTeam {
-        id: team_id.to_string(),
-        name,
-        captain_id,
-        vice_captain_id,
-        players: player_ids,
-        total_points: 0.0,
-        total_price,
-    };
+    entry.team.captain_id = captain_id;
+    entry.team.vice_captain_id = vice_captain_id;
+    entry.team.players = player_ids;
+    entry.team.total_price = total_price;
+    entry.transfer_window = Some(next_matchday.id.clone());
+    entry.transfers_used = used + transfers;
     
-    FANTASY_TEAMS.with(|teams| {
-        teams.borrow_mut().insert(team_id.to_string(), fantasy_team.clone());
+    SEASON_ENTRIES.with(|entries| {
+        entries.borrow_mut().insert(entry.id.clone(), entry.clone());
     });
     
-    // Add team to user's teams
-    let mut user_teams = USER_FANTASY_TEAMS.with(|user_teams| {
-        user_teams.borrow().get(&caller).cloned().unwrap_or(vec![])
-    });
-    user_teams.push(team_id.to_string());
-    USER_FANTASY_TEAMS.with(|user_teams_map| {
-        user_teams_map.borrow_mut().insert(caller, user_teams);
-    });
-    
-    FantasyTeamResponse {
+    SeasonEntryResponse {
         success: true,
-        data: Some(fantasy_team),
+        data: Some(entry),
         error: None,
     }
 }
 
 #[query]
-fn get_user_fantasy_teams(user_id: Option<Principal>) -> FantasyTeamsResponse {
-    let user_id = user_id.unwrap_or_else(caller);
+fn get_season_leaderboard(league_id: String) -> SeasonEntriesResponse {
+    if SEASON_LEAGUES.with(|leagues| !leagues.borrow().contains_key(&league_id)) {
+        return SeasonEntriesResponse {
+            success: false,
+            data: None,
+            error: Some("Season league not found".to_string()),
+        };
+    }
     
-    let team_ids = USER_FANTASY_TEAMS.with(|user_teams| {
-        user_teams.borrow().get(&user_id).cloned().unwrap_or(vec![])
-    });
+    let standings = rank_season_entries(season_entries(&league_id))
+        .into_iter()
+        .flatten()
+        .collect();
     
-    let teams = FANTASY_TEAMS.with(|teams| {
-        team_ids
+    SeasonEntriesResponse {
+        success: true,
+        data: Some(standings),
+        error: None,
+    }
+}
+
+fn season_entry_key(league_id: &str, user_id: &Principal) -> String {
+    format!("{}:{}", league_id, user_id)
+}
+
+fn season_entries(league_id: &str) -> Vec<SeasonEntry> {
+    let prefix = format!("{}:", league_id);
+    
+    SEASON_ENTRIES.with(|entries| {
+        entries
+            .borrow()
+            .range(prefix.clone()..)
+            .take_while(|(entry_id, _)| entry_id.starts_with(&prefix))
+            .map(|(_, entry)| entry)
+            .collect()
+    })
+}
+
+fn open_season_leagues(tournament_id: &str) -> Vec<SeasonLeague> {
+    SEASON_LEAGUES.with(|leagues| {
+        leagues
+            .borrow()
+            .iter()
+            .filter(|(_, league)| league.tournament_id == tournament_id && league.status == SeasonLeagueStatus::Open)
+            .map(|(_, league)| league)
+            .collect()
+    })
+}
+
+// The tournament's matches in the order they are played, cancelled ones left out
+fn tournament_matches(tournament_id: &str) -> Vec<Match> {
+    let mut matches: Vec<Match> = MATCHES.with(|matches| {
+        matches
+            .borrow()
             .iter()
-            .filter_map(|id| teams.borrow().get(id).cloned())
+            .filter(|(_, match_obj)| match_obj.tournament_id == tournament_id && match_obj.status != MatchStatus::Cancelled)
+            .map(|(_, match_obj)| match_obj)
             .collect()
     });
+    matches.sort_by_key(|match_obj| match_obj.start_time);
+    matches
+}
+
+// Season squads may be picked from any side taking part in the tournament
+fn check_season_team(
+    tournament_id: &str,
+    captain_id: &str,
+    vice_captain_id: &str,
+    player_ids: &[String],
+) -> Result<u64, String> {
+    let tournament = TOURNAMENTS
+        .with(|tournaments| tournaments.borrow().get(&tournament_id.to_string()))
+        .ok_or_else(|| "Tournament not found".to_string())?;
+    let rules = composition_rules_for(&tournament.sport)?;
     
-    FantasyTeamsResponse {
-        success: true,
-        data: Some(teams),
-        error: None,
+    let mut eligible_team_ids: Vec<String> = tournament.teams.into_iter().map(|team| team.id).collect();
+    for match_obj in tournament_matches(tournament_id) {
+        for team_id in [match_obj.team1_id, match_obj.team2_id] {
+            if !eligible_team_ids.contains(&team_id) {
+                eligible_team_ids.push(team_id);
+            }
+        }
     }
+    
+    validate_fantasy_team(&eligible_team_ids, &rules, captain_id, vice_captain_id, player_ids).map_err(|violations| {
+        violations
+            .into_iter()
+            .map(|violation| violation.message)
+            .collect::<Vec<_>>()
+            .join("; ")
+    })
 }
 
-#[query]
-fn get_fantasy_team(team_id: String) -> FantasyTeamResponse {
-    let team = FANTASY_TEAMS.with(|teams| teams.borrow().get(&team_id).cloned());
+// Records the current squad against every match that has locked since the
+// squad last changed, so later transfers only affect upcoming matches.
+fn freeze_locked_matchdays(entry: &mut SeasonEntry, matches: &[Match]) {
+    for match_obj in matches {
+        let frozen = entry.matchdays.iter().any(|matchday| matchday.match_id == match_obj.id);
+        if !frozen && is_match_locked(&match_obj.id) {
+            entry.matchdays.push(SeasonMatchday {
+                match_id: match_obj.id.clone(),
+                team: entry.team.clone(),
+                points: 0.0,
+            });
+        }
+    }
+}
+
+fn season_scoring_rules(league: &SeasonLeague) -> Vec<ScoringRule> {
+    league
+        .scoring_rule_set_id
+        .as_ref()
+        .and_then(|rule_set_id| SCORING_RULE_SETS.with(|rule_sets| rule_sets.borrow().get(rule_set_id)))
+        .map(|rule_set| rule_set.rules)
+        .or_else(|| {
+            TOURNAMENTS
+                .with(|tournaments| tournaments.borrow().get(&league.tournament_id))
+                .map(|tournament| get_scoring_rules(tournament.sport))
+        })
+        .unwrap_or_default()
+}
+
+// Scores every matchday of every member from the latest stats.
+fn rescore_season_league(league: &SeasonLeague) -> Vec<SeasonEntry> {
+    let rules = season_scoring_rules(league);
+    let matches = tournament_matches(&league.tournament_id);
+    let mut rescored = Vec::new();
     
-    match team {
-        Some(t) => FantasyTeamResponse {
+    for mut entry in season_entries(&league.id) {
+        freeze_locked_matchdays(&mut entry, &matches);
+        
+        for matchday in entry.matchdays.iter_mut() {
+            matchday.points = calculate_team_points_with_rules(&matchday.team, &matchday.match_id, &rules);
+            matchday.team.total_points = matchday.points;
+        }
+        entry.total_points = entry.matchdays.iter().map(|matchday| matchday.points).sum();
+        entry.team.total_points = entry.total_points;
+        
+        SEASON_ENTRIES.with(|entries| {
+            entries.borrow_mut().insert(entry.id.clone(), entry.clone());
+        });
+        
+        rescored.push(entry);
+    }
+    
+    rescored
+}
+
+// Orders members by season points, earliest joiner first among equals, and
+// groups members on equal points so they share a rank.
+fn rank_season_entries(mut entries: Vec<SeasonEntry>) -> Vec<Vec<SeasonEntry>> {
+    entries.sort_by(|a, b| {
+        sortable_points(b.total_points)
+            .total_cmp(&sortable_points(a.total_points))
+            .then_with(|| a.joined_at.cmp(&b.joined_at))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+    
+    let mut groups: Vec<Vec<SeasonEntry>> = Vec::new();
+    
+    for (index, mut entry) in entries.into_iter().enumerate() {
+        match groups.last_mut() {
+            Some(group) if sortable_points(group[0].total_points) == sortable_points(entry.total_points) => {
+                entry.rank = group[0].rank;
+                group.push(entry);
+            }
+            _ => {
+                entry.rank = Some(index as u32 + 1);
+                groups.push(vec![entry]);
+            }
+        }
+    }
+    
+    groups
+}
+
+// Settles or cancels the open season leagues of a tournament that has finished.
+// Leagues that cannot be settled yet stay open and are tried again as the
+// tournament's remaining matches are completed and scored.
+fn close_season_leagues(tournament_id: &str, status: &TournamentStatus) {
+    for league in open_season_leagues(tournament_id) {
+        match status {
+            TournamentStatus::Completed => {
+                let _ = settle_season_league(league);
+            }
+            TournamentStatus::Cancelled => cancel_season_league(league),
+            _ => {}
+        }
+    }
+}
+
+// Retries the settlement of a finished tournament's leagues once one of its
+// matches changes.
+fn settle_season_leagues_of_match(match_id: &str) {
+    let tournament = MATCHES
+        .with(|matches| matches.borrow().get(&match_id.to_string()))
+        .and_then(|match_obj| TOURNAMENTS.with(|tournaments| tournaments.borrow().get(&match_obj.tournament_id)));
+    
+    if let Some(tournament) = tournament {
+        if tournament.status == TournamentStatus::Completed {
+            close_season_leagues(&tournament.id, &tournament.status);
+        }
+    }
+}
+
+// Pays the collected entry fees out over the final standings. Nothing moves
+// and the league stays open until every match of the tournament is completed
+// and scored and the prize tiers resolve against what was collected.
+fn settle_season_league(mut league: SeasonLeague) -> Result<SeasonLeague, String> {
+    if !tournament_matches(&league.tournament_id).iter().all(is_match_settled) {
+        return Err("Not every match of the tournament has been completed and scored".to_string());
+    }
+    
+    let escrow = LedgerAccount::ContestEscrow(league.id.clone());
+    let collected = u64::try_from(account_balance(&escrow)).unwrap_or(0);
+    let rake = rake_on(collected);
+    let payable = collected - rake;
+    // The stored tier amounts assume a full league, so they are never paid as they are
+    let distribution = resolve_prize_distribution(payable, league.distribution.clone())?;
+    let rake = take_rake(&league.id, rake);
+    let mut pool = PrizePool {
+        contest_id: league.id.clone(),
+        total_amount: payable,
+        distribution,
+        distributed: false,
+        rake: Some(rake),
+    };
+    
+    let mut groups = rank_season_entries(rescore_season_league(&league));
+    let mut unpaid = false;
+    let group_sizes: Vec<usize> = groups.iter().map(|group| group.len()).collect();
+    let shares = split_prizes_by_group(&pool, &group_sizes);
+    
+    for (group, group_shares) in groups.iter_mut().zip(shares) {
+        for (entry, amount) in group.iter_mut().zip(group_shares) {
+            if amount > 0 {
+                if credit_prize(entry.user_id, &league.id, entry.rank.unwrap_or(0), amount) {
+                    entry.prize = Some(amount);
+                } else {
+                    unpaid = true;
+                }
+            }
+            
+            SEASON_ENTRIES.with(|entries| {
+                entries.borrow_mut().insert(entry.id.clone(), entry.clone());
+            });
+        }
+    }
+    
+    pool.distributed = true;
+    PRIZE_POOLS.with(|pools| {
+        pools.borrow_mut().insert(league.id.clone(), pool);
+    });
+    
+    // Prizes that failed to pay stay in escrow
+    if !unpaid {
+        settle_escrow(&league.id);
+    }
+    
+    league.status = SeasonLeagueStatus::Completed;
+    SEASON_LEAGUES.with(|leagues| {
+        leagues.borrow_mut().insert(league.id.clone(), league.clone());
+    });
+    
+    Ok(league)
+}
+
+// Returns every member's entry fee when the tournament is called off.
+fn cancel_season_league(mut league: SeasonLeague) {
+    league.status = SeasonLeagueStatus::Cancelled;
+    SEASON_LEAGUES.with(|leagues| {
+        leagues.borrow_mut().insert(league.id.clone(), league.clone());
+    });
+    
+    refund_season_entries(&league);
+}
+
+// Same as `refund_contest_entries`, for season league members.
+fn refund_season_entries(league: &SeasonLeague) {
+    for mut entry in season_entries(&league.id) {
+        if entry.refunded_at.is_some() {
+            continue;
+        }
+        
+        let refunded = post_transfer(
+            LedgerAccount::ContestEscrow(league.id.clone()),
+            LedgerAccount::UserWallet(entry.user_id),
+            league.entry_fee,
+            TransactionType::Refund,
+        );
+        
+        if league.entry_fee > 0 {
+            create_transaction(
+                entry.user_id,
+                league.entry_fee,
+                TransactionType::Refund,
+                if refunded.is_ok() { TransactionStatus::Completed } else { TransactionStatus::Failed },
+            );
+        }
+        
+        if refunded.is_err() {
+            continue;
+        }
+        
+        entry.refunded_at = Some(time());
+        SEASON_ENTRIES.with(|entries| {
+            entries.borrow_mut().insert(entry.id.clone(), entry.clone());
+        });
+        
+        create_notification(
+            entry.user_id,
+            "Season league cancelled".to_string(),
+            format!("{} was cancelled and your entry fee has been refunded", league.name),
+            NotificationType::ContestCancelled,
+        );
+    }
+}
+
+#[update(guard = "is_admin")]
+fn retry_season_league_refunds(league_id: String) -> SeasonLeagueResponse {
+    let league = SEASON_LEAGUES.with(|leagues| leagues.borrow().get(&league_id));
+    
+    match league {
+        Some(l) if l.status == SeasonLeagueStatus::Cancelled => {
+            refund_season_entries(&l);
+            SeasonLeagueResponse {
+                success: true,
+                data: Some(l),
+                error: None,
+            }
+        }
+        Some(_) => SeasonLeagueResponse {
+            success: false,
+            data: None,
+            error: Some("Season league is not cancelled".to_string()),
+        },
+        None => SeasonLeagueResponse {
+            success: false,
+            data: None,
+            error: Some("Season league not found".to_string()),
+        },
+    }
+}
+
+// Settles a league of a completed tournament that was left open, reporting
+// why when it still cannot be settled.
+#[update(guard = "is_admin")]
+fn retry_season_league_settlement(league_id: String) -> SeasonLeagueResponse {
+    let league = SEASON_LEAGUES.with(|leagues| leagues.borrow().get(&league_id));
+    let tournament_completed = |league: &SeasonLeague| {
+        TOURNAMENTS
+            .with(|tournaments| tournaments.borrow().get(&league.tournament_id))
+            .is_some_and(|tournament| tournament.status == TournamentStatus::Completed)
+    };
+    
+    let settled = match league {
+        Some(l) if l.status == SeasonLeagueStatus::Open && tournament_completed(&l) => settle_season_league(l),
+        Some(_) => Err("Season league is not awaiting settlement".to_string()),
+        None => Err("Season league not found".to_string()),
+    };
+    
+    match settled {
+        Ok(l) => SeasonLeagueResponse {
             success: true,
-            data: Some(t),
+            data: Some(l),
             error: None,
         },
-        None => FantasyTeamResponse {
+        Err(error) => SeasonLeagueResponse {
             success: false,
             data: None,
-            error: Some("Fantasy team not found".to_string()),
+            error: Some(error),
         },
     }
 }
 
+// --- Matchmaking Section ---
+// Queues the caller for a head-to-head on a match at the given stake. The
+// stake is taken straight away; when another user is already waiting at the
+// same stake the two are paired into a new two-spot contest.
 #[update]
-fn update_player_playing_status(player_id: String, is_playing: bool) -> PlayerResponse {
-    let mut player = PLAYERS.with(|players| players.borrow().get(&player_id).cloned());
+fn queue_head_to_head(match_id: String, fantasy_team_id: String, stake: u64) -> Result<MatchmakingOutcome, FantasyError> {
+    let caller = caller();
+    let now = time();
     
-    if let Some(ref mut p) = player {
-        p.is_playing = is_playing;
+    // Validate everything up front so that no state is touched on failure
+    if get_match_sport(&match_id).is_none() {
+        return Err(FantasyError::InvalidOperation);
+    }
+    
+    if is_match_locked(&match_id) {
+        return Err(FantasyError::ContestAlreadyStarted);
+    }
+    
+    let user = USERS
+        .with(|users| users.borrow().get(&caller))
+        .ok_or(FantasyError::UserNotFound)?;
+    
+    let team = FANTASY_TEAMS
+        .with(|teams| teams.borrow().get(&fantasy_team_id))
+        .ok_or(FantasyError::TeamNotFound)?;
+    
+    let owns_team = USER_FANTASY_TEAMS.with(|user_teams| {
+        user_teams
+            .borrow()
+            .get(&caller)
+            .map(|team_ids| team_ids.0.contains(&fantasy_team_id))
+            .unwrap_or(false)
+    });
+    
+    if !owns_team {
+        return Err(FantasyError::Unauthorized);
+    }
+    
+    if team.match_id.as_ref() != Some(&match_id) {
+        return Err(FantasyError::InvalidTeam);
+    }
+    
+    let ticket_key = matchmaking_key(&match_id, stake, &caller);
+    if MATCHMAKING_QUEUE.with(|queue| queue.borrow().contains_key(&ticket_key)) {
+        return Err(FantasyError::AlreadyJoined);
+    }
+    
+    if user.balance < stake {
+        return Err(FantasyError::InsufficientBalance);
+    }
+    
+    // Longest-waiting opponent at the same stake
+    let prefix = format!("{}:{}:", match_id, stake);
+    let opponent = MATCHMAKING_QUEUE.with(|queue| {
+        queue
+            .borrow()
+            .range(prefix.clone()..)
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .filter(|(_, ticket)| ticket.user_id != caller)
+            .min_by_key(|(_, ticket)| ticket.created_at)
+    });
+    
+    let Some((opponent_key, opponent_ticket)) = opponent else {
+        // Hold the stake while waiting for an opponent
+        post_transfer(
+            LedgerAccount::UserWallet(caller),
+            LedgerAccount::MatchmakingEscrow(match_id.clone()),
+            stake,
+            TransactionType::ContestEntry,
+        )
+        .map_err(|_| FantasyError::InsufficientBalance)?;
         
-        PLAYERS.with(|players| {
-            players.borrow_mut().insert(player_id, p.clone());
+        if stake > 0 {
+            create_transaction(
+                caller,
+                stake,
+                TransactionType::ContestEntry,
+                TransactionStatus::Completed,
+            );
+        }
+        
+        let ticket = MatchmakingTicket {
+            user_id: caller,
+            match_id,
+            fantasy_team_id,
+            stake,
+            created_at: now,
+            refund_failed: None,
+        };
+        MATCHMAKING_QUEUE.with(|queue| {
+            queue.borrow_mut().insert(ticket_key, ticket.clone());
+        });
+        schedule_lifecycle_timer();
+        return Ok(MatchmakingOutcome::Queued(ticket));
+    };
+    
+    // Teams cannot be deleted, so the opponent's team is still there
+    let opponent_team = FANTASY_TEAMS
+        .with(|teams| teams.borrow().get(&opponent_ticket.fantasy_team_id))
+        .ok_or(FantasyError::SystemError)?;
+    
+    // The contest is created before any tokens move, so a failure here
+    // leaves both users exactly as they were
+    let start_time = MATCHES
+        .with(|matches| matches.borrow().get(&match_id))
+        .map(|match_obj| match_obj.start_time)
+        .unwrap_or(now);
+    let contest = create_contest(
+        "Head-to-head".to_string(),
+        match_id,
+        stake,
+        HEAD_TO_HEAD_SPOTS,
+        stake * u64::from(HEAD_TO_HEAD_SPOTS),
+        ContestType::HeadToHead,
+        start_time,
+        None,
+        None,
+    )
+    .data
+    .ok_or(FantasyError::SystemError)?;
+    
+    // The caller pays straight into the contest's escrow and the opponent's
+    // held stake follows it there
+    let escrow = LedgerAccount::ContestEscrow(contest.id.clone());
+    if post_transfer(LedgerAccount::UserWallet(caller), escrow.clone(), stake, TransactionType::ContestEntry).is_err() {
+        discard_contest(&contest.id);
+        return Err(FantasyError::InsufficientBalance);
+    }
+    
+    if post_transfer(
+        LedgerAccount::MatchmakingEscrow(contest.match_id.clone()),
+        escrow.clone(),
+        stake,
+        TransactionType::ContestEntry,
+    )
+    .is_err()
+    {
+        let _ = post_transfer(escrow, LedgerAccount::UserWallet(caller), stake, TransactionType::Refund);
+        discard_contest(&contest.id);
+        return Err(FantasyError::SystemError);
+    }
+    
+    if stake > 0 {
+        create_transaction(
+            caller,
+            stake,
+            TransactionType::ContestEntry,
+            TransactionStatus::Completed,
+        );
+    }
+    
+    MATCHMAKING_QUEUE.with(|queue| {
+        queue.borrow_mut().remove(&opponent_key);
+    });
+    
+    insert_contest_entry(contest.clone(), opponent_ticket.user_id, opponent_team);
+    
+    let contest = CONTESTS
+        .with(|contests| contests.borrow().get(&contest.id))
+        .ok_or(FantasyError::SystemError)?;
+    let entry = insert_contest_entry(contest, caller, team);
+    
+    create_notification(
+        opponent_ticket.user_id,
+        "Opponent found".to_string(),
+        format!("You have been matched in a head-to-head for {} tokens", stake),
+        NotificationType::ContestReminder,
+    );
+    
+    Ok(MatchmakingOutcome::Matched(entry))
+}
+
+// Removes a head-to-head contest that was created but never funded.
+fn discard_contest(contest_id: &str) {
+    let removed = CONTESTS.with(|contests| contests.borrow_mut().remove(&contest_id.to_string()));
+    if let Some(contest) = removed {
+        CONTESTS_BY_MATCH.with(|index| {
+            let mut index = index.borrow_mut();
+            if let Some(mut contest_ids) = index.get(&contest.match_id) {
+                contest_ids.0.retain(|id| id != contest_id);
+                index.insert(contest.match_id, contest_ids);
+            }
         });
+    }
+    CONTEST_ENTRIES_BY_CONTEST.with(|contest_entries| {
+        contest_entries.borrow_mut().remove(&contest_id.to_string());
+    });
+}
+
+#[query]
+fn get_my_matchmaking_tickets() -> Vec<MatchmakingTicket> {
+    let caller = caller();
+    
+    MATCHMAKING_QUEUE.with(|queue| {
+        queue
+            .borrow()
+            .iter()
+            .filter(|(_, ticket)| ticket.user_id == caller)
+            .map(|(_, ticket)| ticket)
+            .collect()
+    })
+}
+
+fn matchmaking_key(match_id: &str, stake: u64, user_id: &Principal) -> String {
+    format!("{}:{}:{}", match_id, stake, user_id)
+}
+
+// Returns the stake of every queued user whose match has locked without an
+// opponent turning up.
+fn refund_unmatched_tickets() {
+    let expired: Vec<(String, MatchmakingTicket)> = MATCHMAKING_QUEUE.with(|queue| {
+        queue
+            .borrow()
+            .iter()
+            .filter(|(_, ticket)| is_match_locked(&ticket.match_id))
+            .collect()
+    });
+    
+    for (key, mut ticket) in expired {
+        let refunded = post_transfer(
+            LedgerAccount::MatchmakingEscrow(ticket.match_id.clone()),
+            LedgerAccount::UserWallet(ticket.user_id),
+            ticket.stake,
+            TransactionType::Refund,
+        );
         
-        PlayerResponse {
-            success: true,
-            data: Some(p.clone()),
-            error: None,
+        // A failed refund is recorded once; the ticket stays queued and the
+        // refund is retried on the next pass
+        if refunded.is_err() {
+            if ticket.refund_failed.is_none() && ticket.stake > 0 {
+                create_transaction(ticket.user_id, ticket.stake, TransactionType::Refund, TransactionStatus::Failed);
+            }
+            ticket.refund_failed = Some(true);
+            MATCHMAKING_QUEUE.with(|queue| {
+                queue.borrow_mut().insert(key, ticket);
+            });
+            continue;
         }
-    } else {
-        PlayerResponse {
-            success: false,
-            data: None,
-            error: Some("Player not found".to_string()),
+        
+        MATCHMAKING_QUEUE.with(|queue| {
+            queue.borrow_mut().remove(&key);
+        });
+        
+        if ticket.stake > 0 {
+            create_transaction(
+                ticket.user_id,
+                ticket.stake,
+                TransactionType::Refund,
+                TransactionStatus::Completed,
+            );
         }
+        
+        create_notification(
+            ticket.user_id,
+            "No opponent found".to_string(),
+            format!(
+                "No head-to-head opponent was found before the match locked. Your stake of {} tokens has been refunded.",
+                ticket.stake
+            ),
+            NotificationType::ContestCancelled,
+        );
     }
 }
 
-#[query]
-fn get_all_players() -> Vec<Player> {
-    PLAYERS.with(|players| {
-        players.borrow().iter().map(|(_, player)| player.clone()).collect()
-    })
+// --- Lifecycle Scheduler Section ---
+// A single one-shot timer is kept armed for the earliest pending transition.
+// Each tick applies every transition that is due and re-arms the timer.
+fn schedule_lifecycle_timer() {
+    LIFECYCLE_TIMER.with(|timer| {
+        if let Some(timer_id) = timer.borrow_mut().take() {
+            clear_timer(timer_id);
+        }
+    });
+    
+    if let Some(next_time) = next_lifecycle_event_time() {
+        let delay = next_time.saturating_sub(time());
+        let timer_id = set_timer(Duration::from_nanos(delay), run_lifecycle_tick);
+        LIFECYCLE_TIMER.with(|timer| *timer.borrow_mut() = Some(timer_id));
+    }
 }
 
-fn get_next_id(id_ref: &'static LocalKey<RefCell<Option<StableCell<u64, Memory>>>>) -> u64 {
-    id_ref.with(|id| {
-        let mut id = id.borrow_mut();
-        let stable_cell = id.as_mut().unwrap();
-        let current_id = *stable_cell.get();
-        stable_cell.set(current_id + 1).unwrap();
-        current_id
-    })
-} 
\ No newline at end of file
+fn run_lifecycle_tick() {
+    LIFECYCLE_TIMER.with(|timer| *timer.borrow_mut() = None);
+    apply_due_lifecycle_transitions(time());
+    schedule_lifecycle_timer();
+}
+
+fn next_lifecycle_event_time() -> Option<u64> {
+    let contest_times = CONTESTS.with(|contests| {
+        contests
+            .borrow()
+            .iter()
+            .filter(|(_, contest)| matches!(contest.status, ContestStatus::Open | ContestStatus::Full))
+            .map(|(_, contest)| contest.start_time)
+            .min()
+    });
+    
+    let match_times = MATCHES.with(|matches| {
+        matches
+            .borrow()
+            .iter()
+            .filter(|(_, match_obj)| match_obj.status == MatchStatus::Scheduled)
+            .map(|(_, match_obj)| match_obj.start_time)
+            .min()
+    });
+    
+    let tournament_times = TOURNAMENTS.with(|tournaments| {
+        tournaments
+            .borrow()
+            .iter()
+            .filter_map(|(_, tournament)| match tournament.status {
+                TournamentStatus::Upcoming => Some(tournament.start_time),
+                TournamentStatus::Live => Some(tournament.end_time),
+                _ => None,
+            })
+            .min()
+    });
+    
+    // Queued head-to-head users are refunded once their match locks. Tickets
+    // whose refund already failed are not scheduled again, as their lock time
+    // has passed and would keep the timer firing.
+    let queue_times = MATCHMAKING_QUEUE.with(|queue| {
+        queue
+            .borrow()
+            .iter()
+            .filter(|(_, ticket)| ticket.refund_failed.is_none())
+            .filter_map(|(_, ticket)| MATCHES.with(|matches| matches.borrow().get(&ticket.match_id)))
+            .map(|match_obj| match_lock_time(&match_obj))
+            .min()
+    });
+    
+    [contest_times, match_times, tournament_times, queue_times]
+        .into_iter()
+        .flatten()
+        .min()
+}
+
+fn apply_due_lifecycle_transitions(now: u64) {
+    // Matches go live at their start time
+    let due_matches: Vec<Match> = MATCHES.with(|matches| {
+        matches
+            .borrow()
+            .iter()
+            .filter(|(_, match_obj)| match_obj.status == MatchStatus::Scheduled && match_obj.start_time <= now)
+            .map(|(_, match_obj)| match_obj)
+            .collect()
+    });
+    
+    for mut match_obj in due_matches {
+        match_obj.status = MatchStatus::Live;
+        MATCHES.with(|matches| {
+            matches.borrow_mut().insert(match_obj.id.clone(), match_obj);
+        });
+    }
+    
+    refund_unmatched_tickets();
+    
+    // Contests lock (or are cancelled when under-filled) at their start time
+    let due_contests: Vec<Contest> = CONTESTS.with(|contests| {
+        contests
+            .borrow()
+            .iter()
+            .filter(|(_, contest)| {
+                matches!(contest.status, ContestStatus::Open | ContestStatus::Full) && contest.start_time <= now
+            })
+            .map(|(_, contest)| contest)
+            .collect()
+    });
+    
+    for contest in due_contests {
+        lock_contest(contest);
+    }
+    
+    // Tournaments follow their start and end times
+    let due_tournaments: Vec<(Tournament, TournamentStatus)> = TOURNAMENTS.with(|tournaments| {
+        tournaments
+            .borrow()
+            .iter()
+            .filter_map(|(_, tournament)| {
+                let next_status = match tournament.status {
+                    TournamentStatus::Upcoming if tournament.end_time <= now => TournamentStatus::Completed,
+                    TournamentStatus::Upcoming if tournament.start_time <= now => TournamentStatus::Live,
+                    TournamentStatus::Live if tournament.end_time <= now => TournamentStatus::Completed,
+                    _ => return None,
+                };
+                Some((tournament, next_status))
+            })
+            .collect()
+    });
+    
+    for (mut tournament, status) in due_tournaments {
+        tournament.status = status;
+        close_season_leagues(&tournament.id, &tournament.status);
+        TOURNAMENTS.with(|tournaments| {
+            tournaments.borrow_mut().insert(tournament.id.clone(), tournament);
+        });
+    }
+}
+
+// --- Scoring Section ---
+// Scoring Management
+#[update(guard = "is_score_feeder")]
+fn update_player_score(
+    player_id: String,
+    match_id: String,
+    stats: PlayerStats,
+) -> PlayerScoreResponse {
+    let sport = match get_match_sport(&match_id) {
+        Some(sport) => sport,
+        None => {
+            return PlayerScoreResponse {
+                success: false,
+                data: None,
+                error: Some("Match not found".to_string()),
+            };
+        }
+    };
+    
+    let score = store_player_score(&player_id, &match_id, stats, &sport);
+    
+    // Propagate the new stats to live contest entries
+    recalculate_match_entries(&match_id);
+    
+    PlayerScoreResponse {
+        success: true,
+        data: Some(score),
+        error: None,
+    }
+}
+
+// Applies a batch of stat updates for one match and rescores its entries
+// once, rather than once per player as `update_player_score` does.
+#[update(guard = "is_score_feeder")]
+fn update_player_scores(match_id: String, updates: Vec<PlayerStatsUpdate>) -> PlayerScoresResponse {
+    let sport = match get_match_sport(&match_id) {
+        Some(sport) => sport,
+        None => {
+            return PlayerScoresResponse {
+                success: false,
+                data: None,
+                error: Some("Match not found".to_string()),
+            };
+        }
+    };
+    
+    let scores: Vec<PlayerScore> = updates
+        .into_iter()
+        .map(|update| store_player_score(&update.player_id, &match_id, update.stats, &sport))
+        .collect();
+    
+    if !scores.is_empty() {
+        recalculate_match_entries(&match_id);
+    }
+    
+    PlayerScoresResponse {
+        success: true,
+        data: Some(scores),
+        error: None,
+    }
+}
+
+// Stores a player's latest stats for a match and refreshes their season total.
+fn store_player_score(player_id: &str, match_id: &str, stats: PlayerStats, sport: &Sport) -> PlayerScore {
+    let score_id = format!("{}:{}", player_id, match_id);
+    
+    let mut score = PLAYER_SCORES
+        .with(|scores| scores.borrow().get(&score_id))
+        .unwrap_or_else(|| PlayerScore {
+            player_id: player_id.to_string(),
+            match_id: match_id.to_string(),
+            points: 0.0,
+            stats: PlayerStats::default(),
+            updated_at: time(),
+        });
+    
+    score.points = calculate_player_points(&stats, sport);
+    score.stats = stats;
+    score.updated_at = time();
+    
+    PLAYER_SCORES.with(|scores| {
+        scores.borrow_mut().insert(score_id, score.clone());
+    });
+    
+    refresh_player_total_points(player_id);
+    
+    score
+}
+
+// A match's results are final once it is completed and its score recorded.
+fn is_match_settled(match_obj: &Match) -> bool {
+    match_obj.status == MatchStatus::Completed
+        && (match_obj.score.is_some()
+            || MATCH_SCORES.with(|match_scores| match_scores.borrow().contains_key(&match_obj.id)))
+}
+
+fn get_match_sport(match_id: &str) -> Option<Sport> {
+    let match_obj = MATCHES.with(|matches| matches.borrow().get(&match_id.to_string()))?;
+    TOURNAMENTS
+        .with(|tournaments| tournaments.borrow().get(&match_obj.tournament_id))
+        .map(|tournament| tournament.sport)
+}
+
+// Maps a scoring rule action to the stat it is evaluated against.
+fn stat_value(stats: &PlayerStats, action: &str) -> Option<f64> {
+    match action {
+        // Cricket
+        "run" => stats.runs.map(f64::from),
+        "wicket" => stats.wickets.map(f64::from),
+        "catch" => stats.catches.map(f64::from),
+        "stumping" => stats.stumpings.map(f64::from),
+        "run_out" => stats.run_outs.map(f64::from),
+        "over" => stats.overs,
+        "maiden_over" => stats.maidens.map(f64::from),
+        "economy" => stats.economy,
+        // Football
+        "goal" => stats.goals.map(f64::from),
+        "assist" => stats.assists.map(f64::from),
+        "clean_sheet" => stats.clean_sheets.map(f64::from),
+        "save" => stats.saves.map(f64::from),
+        "yellow_card" => stats.yellow_cards.map(f64::from),
+        "red_card" => stats.red_cards.map(f64::from),
+        // Basketball
+        "point" => stats.points.map(f64::from),
+        "rebound" => stats.rebounds.map(f64::from),
+        "block" => stats.blocks.map(f64::from),
+        "steal" => stats.steals.map(f64::from),
+        "turnover" => stats.turnovers.map(f64::from),
+        _ => None,
+    }
+}
+
+fn evaluate_rule(rule: &ScoringRule, stats: &PlayerStats) -> f64 {
+    let value = match stat_value(stats, &rule.action) {
+        Some(value) => value,
+        None => return 0.0,
+    };
+    
+    match &rule.condition {
+        None => value * rule.points,
+        Some(condition) => {
+            let satisfied = match condition.comparison {
+                Comparison::LessThan => value < condition.threshold,
+                Comparison::LessThanOrEqual => value <= condition.threshold,
+                Comparison::GreaterThan => value > condition.threshold,
+                Comparison::GreaterThanOrEqual => value >= condition.threshold,
+            };
+            if satisfied {
+                rule.points
+            } else {
+                0.0
+            }
+        }
+    }
+}
+
+fn calculate_player_points(stats: &PlayerStats, sport: &Sport) -> f64 {
+    calculate_points_with_rules(stats, &get_scoring_rules(sport.clone()))
+}
+
+fn calculate_points_with_rules(stats: &PlayerStats, rules: &[ScoringRule]) -> f64 {
+    rules.iter().map(|rule| evaluate_rule(rule, stats)).sum()
+}
+
+#[query]
+fn get_player_score(player_id: String, match_id: String) -> PlayerScoreResponse {
+    let score_id = format!("{}:{}", player_id, match_id);
+    
+    let player_score = PLAYER_SCORES.with(|scores| {
+        scores.borrow().get(&score_id)
+    });
+    
+    match player_score {
+        Some(score) => PlayerScoreResponse {
+            success: true,
+            data: Some(score),
+            error: None,
+        },
+        None => PlayerScoreResponse {
+            success: false,
+            data: None,
+            error: Some("Player score not found".to_string()),
+        },
+    }
+}
+
+#[query]
+fn get_match_scores(match_id: String) -> Vec<PlayerScore> {
+    PLAYER_SCORES.with(|scores| {
+        scores
+            .borrow()
+            .iter()
+            .filter(|(_, score)| score.match_id == match_id)
+            .map(|(_, score)| score)
+            .collect()
+    })
+}
+
+#[update(guard = "is_admin")]
+fn add_scoring_rule(
+    sport: Sport,
+    action: String,
+    points: f64,
+    condition: Option<RuleCondition>,
+) -> ScoringRuleResponse {
+    if !SCORING_ACTIONS.contains(&action.as_str()) {
+        return ScoringRuleResponse {
+            success: false,
+            data: None,
+            error: Some(format!("Unknown scoring action: {}", action)),
+        };
+    }
+    
+    let rule = ScoringRule {
+        action,
+        points,
+        sport: sport.clone(),
+        condition,
+    };
+    
+    // Rule sets are immutable, so adding a rule publishes a new version
+    let mut rules = get_scoring_rules(sport.clone());
+    rules.push(rule.clone());
+    publish_rule_set(sport, rules);
+    
+    ScoringRuleResponse {
+        success: true,
+        data: Some(rule),
+        error: None,
+    }
+}
+
+#[update(guard = "is_admin")]
+fn publish_scoring_rule_set(sport: Sport, rules: Vec<ScoringRule>) -> ScoringRuleSetResponse {
+    if let Some(rule) = rules.iter().find(|rule| rule.sport != sport) {
+        return ScoringRuleSetResponse {
+            success: false,
+            data: None,
+            error: Some(format!("Rule '{}' is not a {:?} rule", rule.action, sport)),
+        };
+    }
+    
+    if let Some(rule) = rules.iter().find(|rule| !SCORING_ACTIONS.contains(&rule.action.as_str())) {
+        return ScoringRuleSetResponse {
+            success: false,
+            data: None,
+            error: Some(format!("Unknown scoring action: {}", rule.action)),
+        };
+    }
+    
+    ScoringRuleSetResponse {
+        success: true,
+        data: Some(publish_rule_set(sport, rules)),
+        error: None,
+    }
+}
+
+// Stores the rules as the next version for the sport and makes them the live rules.
+fn publish_rule_set(sport: Sport, rules: Vec<ScoringRule>) -> ScoringRuleSet {
+    let version = latest_rule_set(&sport).map_or(1, |rule_set| rule_set.version + 1);
+    
+    let rule_set = ScoringRuleSet {
+        id: format!("{:?}-v{}", sport, version),
+        sport: sport.clone(),
+        version,
+        rules: rules.clone(),
+        created_at: time(),
+    };
+    
+    SCORING_RULE_SETS.with(|rule_sets| {
+        rule_sets.borrow_mut().insert(rule_set.id.clone(), rule_set.clone());
+    });
+    
+    SCORING_RULES.with(|scoring_rules| {
+        scoring_rules.borrow_mut().insert(sport, StorableVec(rules));
+    });
+    
+    rule_set
+}
+
+fn latest_rule_set(sport: &Sport) -> Option<ScoringRuleSet> {
+    SCORING_RULE_SETS.with(|rule_sets| {
+        rule_sets
+            .borrow()
+            .iter()
+            .filter(|(_, rule_set)| rule_set.sport == *sport)
+            .max_by_key(|(_, rule_set)| rule_set.version)
+            .map(|(_, rule_set)| rule_set)
+    })
+}
+
+#[query]
+fn get_scoring_rule_set(rule_set_id: String) -> ScoringRuleSetResponse {
+    let rule_set = SCORING_RULE_SETS.with(|rule_sets| rule_sets.borrow().get(&rule_set_id));
+    
+    match rule_set {
+        Some(rule_set) => ScoringRuleSetResponse {
+            success: true,
+            data: Some(rule_set),
+            error: None,
+        },
+        None => ScoringRuleSetResponse {
+            success: false,
+            data: None,
+            error: Some("Scoring rule set not found".to_string()),
+        },
+    }
+}
+
+#[query]
+fn get_scoring_rule_sets(sport: Sport) -> Vec<ScoringRuleSet> {
+    let mut rule_sets: Vec<ScoringRuleSet> = SCORING_RULE_SETS.with(|rule_sets| {
+        rule_sets
+            .borrow()
+            .iter()
+            .filter(|(_, rule_set)| rule_set.sport == sport)
+            .map(|(_, rule_set)| rule_set)
+            .collect()
+    });
+    rule_sets.sort_by_key(|rule_set| rule_set.version);
+    rule_sets
+}
+
+#[query]
+fn get_scoring_rules(sport: Sport) -> Vec<ScoringRule> {
+    SCORING_RULES.with(|rules| {
+        rules.borrow().get(&sport).unwrap_or_default().0
+    })
+}
+
+#[query]
+fn calculate_fantasy_team_points(
+    player_ids: Vec<String>,
+    match_id: String,
+    captain_id: String,
+    vice_captain_id: String,
+) -> f64 {
+    let mut total_points = 0.0;
+    
+    for player_id in player_ids {
+        let score = PLAYER_SCORES.with(|scores| {
+            let score_id = format!("{}:{}", player_id, match_id);
+            scores.borrow().get(&score_id)
+        });
+        
+        if let Some(player_score) = score {
+            let mut points = player_score.points;
+            
+            // Apply captain/vice-captain multipliers
+            if player_id == captain_id {
+                points *= CAPTAIN_MULTIPLIER;
+            } else if player_id == vice_captain_id {
+                points *= VICE_CAPTAIN_MULTIPLIER;
+            }
+            
+            total_points += points;
+        }
+    }
+    
+    total_points
+}
+
+// Sets a player's cumulative points to the sum of their scores across all matches.
+fn refresh_player_total_points(player_id: &str) {
+    let prefix = format!("{}:", player_id);
+    
+    let total_points: f64 = PLAYER_SCORES.with(|scores| {
+        scores
+            .borrow()
+            .range(prefix.clone()..)
+            .take_while(|(score_id, _)| score_id.starts_with(&prefix))
+            .map(|(_, score)| score.points)
+            .sum()
+    });
+    
+    PLAYERS.with(|players| {
+        let mut players = players.borrow_mut();
+        if let Some(mut player) = players.get(&player_id.to_string()) {
+            player.points = total_points;
+            players.insert(player_id.to_string(), player);
+        }
+    });
+}
+
+// Recomputes the points of every entry in the unfinished contests of a match.
+fn recalculate_match_entries(match_id: &str) {
+    let contests = match_contests(match_id)
+        .into_iter()
+        .filter(|contest| !matches!(contest.status, ContestStatus::Completed | ContestStatus::Cancelled));
+    
+    for contest in contests {
+        rescore_contest_entries(&contest);
+    }
+    
+    if let Some(match_obj) = MATCHES.with(|matches| matches.borrow().get(&match_id.to_string())) {
+        for league in open_season_leagues(&match_obj.tournament_id) {
+            rescore_season_league(&league);
+        }
+    }
+}
+
+// Rules a contest is scored with: its pinned rule set, or the live rules of the
+// match's sport for contests created before rule sets existed.
+fn contest_scoring_rules(contest: &Contest) -> Vec<ScoringRule> {
+    contest
+        .scoring_rule_set_id
+        .as_ref()
+        .and_then(|rule_set_id| SCORING_RULE_SETS.with(|rule_sets| rule_sets.borrow().get(rule_set_id)))
+        .map(|rule_set| rule_set.rules)
+        .or_else(|| get_match_sport(&contest.match_id).map(get_scoring_rules))
+        .unwrap_or_default()
+}
+
+fn calculate_team_points_with_rules(team: &FantasyTeam, match_id: &str, rules: &[ScoringRule]) -> f64 {
+    let mut total_points = 0.0;
+    
+    for player_id in &team.players {
+        let score = PLAYER_SCORES.with(|scores| {
+            let score_id = format!("{}:{}", player_id, match_id);
+            scores.borrow().get(&score_id)
+        });
+        
+        if let Some(player_score) = score {
+            let mut points = calculate_points_with_rules(&player_score.stats, rules);
+            
+            // Apply captain/vice-captain multipliers
+            if *player_id == team.captain_id {
+                points *= CAPTAIN_MULTIPLIER;
+            } else if *player_id == team.vice_captain_id {
+                points *= VICE_CAPTAIN_MULTIPLIER;
+            }
+            
+            total_points += points;
+        }
+    }
+    
+    total_points
+}
+
+// Scores every entry of a contest from the latest stats using the contest's
+// pinned rules, without storing the result.
+fn score_contest_entries(contest: &Contest) -> Vec<ContestEntry> {
+    let rules = contest_scoring_rules(contest);
+    
+    get_contest_entries(contest.id.clone())
+        .into_iter()
+        .map(|mut entry| {
+            let points = calculate_team_points_with_rules(&entry.team, &contest.match_id, &rules);
+            entry.points = points;
+            entry.team.total_points = points;
+            entry
+        })
+        .collect()
+}
+
+// Scores and stores every entry of a contest. Points live on the entry only:
+// a team can enter contests scored under different rule sets, so the stored
+// team is left untouched.
+fn rescore_contest_entries(contest: &Contest) -> Vec<ContestEntry> {
+    let rescored = score_contest_entries(contest);
+    
+    CONTEST_ENTRIES.with(|entries| {
+        let mut entries = entries.borrow_mut();
+        for entry in &rescored {
+            entries.insert(entry.id.clone(), entry.clone());
+        }
+    });
+    
+    rescored
+}
+
+#[update(guard = "is_score_feeder")]
+fn rescore_contest(contest_id: String) -> ContestEntriesResponse {
+    let contest = CONTESTS.with(|contests| contests.borrow().get(&contest_id));
+    
+    match contest {
+        // Settled contests keep the points they were paid out on
+        Some(c) if !matches!(c.status, ContestStatus::Open | ContestStatus::Full | ContestStatus::Live) => {
+            ContestEntriesResponse {
+                success: false,
+                data: None,
+                error: Some("Contest is already finalized".to_string()),
+            }
+        }
+        Some(c) => ContestEntriesResponse {
+            success: true,
+            data: Some(rescore_contest_entries(&c)),
+            error: None,
+        },
+        None => ContestEntriesResponse {
+            success: false,
+            data: None,
+            error: Some("Contest not found".to_string()),
+        },
+    }
+}
+
+// Recomputes a finalized contest's points and ranks from its pinned rule set
+// so the stored results can be checked. Nothing is stored or paid out.
+#[query]
+fn recompute_contest_results(contest_id: String) -> ContestEntriesResponse {
+    match CONTESTS.with(|contests| contests.borrow().get(&contest_id)) {
+        Some(c) if c.status == ContestStatus::Completed => ContestEntriesResponse {
+            success: true,
+            data: Some(rank_entries(&c, score_contest_entries(&c)).into_iter().flatten().collect()),
+            error: None,
+        },
+        Some(_) => ContestEntriesResponse {
+            success: false,
+            data: None,
+            error: Some("Contest has not been finalized".to_string()),
+        },
+        None => ContestEntriesResponse {
+            success: false,
+            data: None,
+            error: Some("Contest not found".to_string()),
+        },
+    }
+}
+
+#[query]
+fn get_all_player_scores() -> Vec<PlayerScore> {
+    PLAYER_SCORES.with(|scores| {
+        scores.borrow().iter().map(|(_, score)| score).collect()
+    })
+}
+
+#[query]
+fn get_score_count() -> u64 {
+    PLAYER_SCORES.with(|scores| scores.borrow().len())
+}
+
+// --- Ledger Section ---
+// Every token movement is a single entry taking an amount out of one account
+// and into another, so the balances of all accounts always add up to zero.
+// User wallets may not be overdrawn; escrow, house and promo accounts may run
+// a deficit, which shows up in their balances and the audit.
+fn post_transfer(
+    from: LedgerAccount,
+    to: LedgerAccount,
+    amount: u64,
+    transaction_type: TransactionType,
+) -> Result<(), String> {
+    if amount == 0 {
+        return Ok(());
+    }
+    
+    if from == to {
+        return Err("Cannot transfer to the same account".to_string());
+    }
+    
+    for account in [&from, &to] {
+        if let LedgerAccount::UserWallet(user_id) = account {
+            if USERS.with(|users| !users.borrow().contains_key(user_id)) {
+                return Err("User not found".to_string());
+            }
+        }
+    }
+    
+    if matches!(from, LedgerAccount::UserWallet(_)) && account_balance(&from) < i128::from(amount) {
+        return Err("Insufficient balance".to_string());
+    }
+    
+    LEDGER.with(|ledger| {
+        let mut ledger = ledger.borrow_mut();
+        let id = ledger.len();
+        ledger.insert(
+            id,
+            LedgerEntry {
+                id,
+                from: from.clone(),
+                to: to.clone(),
+                amount,
+                transaction_type,
+                created_at: time(),
+            },
+        );
+    });
+    
+    adjust_account_balance(&from, -i128::from(amount));
+    adjust_account_balance(&to, i128::from(amount));
+    
+    Ok(())
+}
+
+// Updates the cached balance of an account, mirroring wallets onto the user's profile.
+fn adjust_account_balance(account: &LedgerAccount, delta: i128) {
+    let balance = account_balance(account) + delta;
+    
+    ACCOUNT_BALANCES.with(|balances| {
+        balances.borrow_mut().insert(
+            account.clone(),
+            AccountBalance {
+                account: account.clone(),
+                balance,
+            },
+        );
+    });
+    
+    if let LedgerAccount::UserWallet(user_id) = account {
+        USERS.with(|users| {
+            let mut users = users.borrow_mut();
+            if let Some(mut profile) = users.get(user_id) {
+                profile.balance = u64::try_from(balance).unwrap_or(0);
+                profile.updated_at = time();
+                users.insert(*user_id, profile);
+            }
+        });
+    }
+}
+
+fn account_balance(account: &LedgerAccount) -> i128 {
+    ACCOUNT_BALANCES
+        .with(|balances| balances.borrow().get(account))
+        .map(|balance| balance.balance)
+        .unwrap_or(0)
+}
+
+// Empties an escrow account once its contest is settled: anything left over is
+// house revenue and any deficit is covered by the house.
+fn settle_escrow(escrow_id: &str) {
+    let escrow = LedgerAccount::ContestEscrow(escrow_id.to_string());
+    let balance = account_balance(&escrow);
+    
+    // Neither direction can fail as only wallets are checked for funds
+    if balance > 0 {
+        let _ = post_transfer(escrow, LedgerAccount::House, balance as u64, TransactionType::HouseRevenue);
+    } else if balance < 0 {
+        let _ = post_transfer(LedgerAccount::House, escrow, balance.unsigned_abs() as u64, TransactionType::HouseShortfall);
+    }
+}
+
+// Moves the platform's share of an escrow to the house, returning the amount.
+fn take_rake(escrow_id: &str, rake: u64) -> u64 {
+    let escrow = LedgerAccount::ContestEscrow(escrow_id.to_string());
+    
+    match post_transfer(escrow, LedgerAccount::House, rake, TransactionType::Rake) {
+        Ok(()) => rake,
+        Err(_) => 0,
+    }
+}
+
+// The platform's share of the given escrowed amount.
+fn rake_on(amount: u64) -> u64 {
+    let percentage = PLATFORM_RAKE_PERCENTAGE.with(|rake| *rake.borrow().get());
+    (amount as f64 * percentage / 100.0).floor() as u64
+}
+
+#[query]
+fn get_platform_rake() -> f64 {
+    PLATFORM_RAKE_PERCENTAGE.with(|rake| *rake.borrow().get())
+}
+
+#[update(guard = "is_admin")]
+fn set_platform_rake(percentage: f64) -> PlatformRakeResponse {
+    if !percentage.is_finite() || !(0.0..=MAX_RAKE_PERCENTAGE).contains(&percentage) {
+        return PlatformRakeResponse {
+            success: false,
+            data: None,
+            error: Some(format!("Rake must be between 0% and {}%", MAX_RAKE_PERCENTAGE)),
+        };
+    }
+    
+    PLATFORM_RAKE_PERCENTAGE.with(|rake| {
+        rake.borrow_mut().set(percentage).unwrap();
+    });
+    
+    PlatformRakeResponse {
+        success: true,
+        data: Some(percentage),
+        error: None,
+    }
+}
+
+// Lists every contest's escrow next to the prize pool it has to pay.
+#[query(guard = "is_admin")]
+fn get_contest_escrows() -> Vec<ContestEscrowSummary> {
+    let contests: Vec<Contest> = CONTESTS.with(|contests| {
+        contests.borrow().iter().map(|(_, contest)| contest).collect()
+    });
+    
+    contests
+        .into_iter()
+        .map(|contest| {
+            let pool = PRIZE_POOLS.with(|pools| pools.borrow().get(&contest.id));
+            let prize_pool = pool.as_ref().map(|p| p.total_amount).unwrap_or(contest.prize_pool);
+            let escrow_balance = account_balance(&LedgerAccount::ContestEscrow(contest.id.clone()));
+            let paid_out = pool.as_ref().map(|p| p.distributed).unwrap_or(false)
+                || contest.status == ContestStatus::Cancelled;
+            let shortfall = if paid_out {
+                0
+            } else {
+                u64::try_from(i128::from(prize_pool) - escrow_balance).unwrap_or(0)
+            };
+            
+            ContestEscrowSummary {
+                contest_id: contest.id.clone(),
+                status: contest.status,
+                advertised_pool: contest.prize_pool,
+                prize_pool,
+                collected: contest.entry_fee * contest.filled_spots as u64,
+                escrow_balance,
+                rake: pool.and_then(|p| p.rake),
+                shortfall,
+            }
+        })
+        .collect()
+}
+
+// Wallet balances from before the ledger are opened against the house.
+fn open_ledger_wallets() {
+    let unopened: Vec<(Principal, u64)> = USERS.with(|users| {
+        users
+            .borrow()
+            .iter()
+            .filter(|(user_id, _)| {
+                ACCOUNT_BALANCES.with(|balances| {
+                    !balances.borrow().contains_key(&LedgerAccount::UserWallet(*user_id))
+                })
+            })
+            .map(|(user_id, profile)| (user_id, profile.balance))
+            .collect()
+    });
+    
+    for (user_id, balance) in unopened {
+        // The profile already shows the balance; replaying it through the
+        // ledger leaves the same figure behind.
+        USERS.with(|users| {
+            let mut users = users.borrow_mut();
+            if let Some(mut profile) = users.get(&user_id) {
+                profile.balance = 0;
+                users.insert(user_id, profile);
+            }
+        });
+        let _ = post_transfer(
+            LedgerAccount::House,
+            LedgerAccount::UserWallet(user_id),
+            balance,
+            TransactionType::Deposit,
+        );
+    }
+}
+
+// Users can see their own wallet; every other account is admin-only.
+#[query]
+fn get_account_balance(account: LedgerAccount) -> Result<i128, FantasyError> {
+    if account != LedgerAccount::UserWallet(caller()) && is_admin().is_err() {
+        return Err(FantasyError::Unauthorized);
+    }
+    
+    Ok(account_balance(&account))
+}
+
+#[query(guard = "is_admin")]
+fn get_ledger_entries(offset: u64, limit: u64) -> Vec<LedgerEntry> {
+    LEDGER.with(|ledger| {
+        ledger
+            .borrow()
+            .range(offset..)
+            .take(limit.min(u64::from(MAX_LEADERBOARD_PAGE_SIZE)) as usize)
+            .map(|(_, entry)| entry)
+            .collect()
+    })
+}
+
+// Replays the whole ledger and checks it against the cached balances and the
+// wallet balances shown on user profiles.
+#[query(guard = "is_admin")]
+fn audit_ledger() -> LedgerAudit {
+    let mut replayed: BTreeMap<LedgerAccount, i128> = BTreeMap::new();
+    
+    let entry_count = LEDGER.with(|ledger| {
+        let ledger = ledger.borrow();
+        for (_, entry) in ledger.iter() {
+            *replayed.entry(entry.from).or_insert(0) -= i128::from(entry.amount);
+            *replayed.entry(entry.to).or_insert(0) += i128::from(entry.amount);
+        }
+        ledger.len()
+    });
+    
+    let cached: Vec<AccountBalance> = ACCOUNT_BALANCES.with(|balances| {
+        balances.borrow().iter().map(|(_, balance)| balance).collect()
+    });
+    
+    let mut mismatched_accounts: Vec<LedgerAccount> = cached
+        .iter()
+        .filter(|balance| replayed.get(&balance.account).copied().unwrap_or(0) != balance.balance)
+        .map(|balance| balance.account.clone())
+        .collect();
+    
+    for account in replayed.keys() {
+        let cached_missing = !cached.iter().any(|balance| balance.account == *account);
+        let profile_differs = match account {
+            LedgerAccount::UserWallet(user_id) => USERS
+                .with(|users| users.borrow().get(user_id))
+                .map(|profile| i128::from(profile.balance) != replayed[account])
+                .unwrap_or(true),
+            _ => false,
+        };
+        if (cached_missing || profile_differs) && !mismatched_accounts.contains(account) {
+            mismatched_accounts.push(account.clone());
+        }
+    }
+    
+    let total_balance: i128 = cached.iter().map(|balance| balance.balance).sum();
+    
+    LedgerAudit {
+        entry_count,
+        account_count: cached.len() as u64,
+        total_balance,
+        balanced: total_balance == 0 && mismatched_accounts.is_empty(),
+        mismatched_accounts,
+    }
+}
+
+// --- Token Section ---
+// Real value moves in and out through an ICRC-1 ledger canister. Deposits
+// pull tokens the user has approved with `icrc2_transfer_from`; withdrawals
+// push them out with `icrc1_transfer`. Both are booked against the treasury
+// account and tracked as a reward transaction from Pending to Completed or Failed.
+fn token_ledger() -> Result<Principal, String> {
+    TOKEN_LEDGER
+        .with(|ledger| *ledger.borrow().get())
+        .ok_or_else(|| "Token ledger is not configured".to_string())
+}
+
+#[query]
+fn get_token_ledger() -> Option<Principal> {
+    TOKEN_LEDGER.with(|ledger| *ledger.borrow().get())
+}
+
+#[update(guard = "is_admin")]
+fn set_token_ledger(ledger_id: Principal) -> Option<Principal> {
+    TOKEN_LEDGER.with(|ledger| {
+        ledger.borrow_mut().set(Some(ledger_id)).unwrap();
+    });
+    
+    Some(ledger_id)
+}
+
+// Every deposit is recorded as pending before the ledger is called, with a
+// fixed `created_at_time` and memo. If the outcome of the call is lost, the
+// same transfer is sent again by `resume_deposit`; the ledger deduplicates it
+// within its transaction window, so the deposit is credited exactly once.
+#[update]
+async fn deposit_tokens(amount: u64, from_subaccount: Option<Vec<u8>>) -> RewardTransactionResponse {
+    let caller = caller();
+    
+    let checked = token_ledger().and_then(|ledger_id| {
+        if amount == 0 {
+            return Err("Amount must be greater than zero".to_string());
+        }
+        if USERS.with(|users| !users.borrow().contains_key(&caller)) {
+            return Err("User not found".to_string());
+        }
+        if PENDING_DEPOSITS.with(|pending| pending.borrow().contains_key(&caller)) {
+            return Err("A previous deposit is still pending; call resume_deposit to settle it".to_string());
+        }
+        Ok(ledger_id)
+    });
+    
+    let ledger_id = match checked {
+        Ok(ledger_id) => ledger_id,
+        Err(error) => return transaction_failure(error),
+    };
+    
+    let transaction = create_transaction(caller, amount, TransactionType::Deposit, TransactionStatus::Pending);
+    let pending = PendingDeposit {
+        transaction_id: transaction.id,
+        amount,
+        created_at_time: transaction.created_at,
+        source: DepositSource::Allowance { from_subaccount },
+    };
+    PENDING_DEPOSITS.with(|deposits| {
+        deposits.borrow_mut().insert(caller, pending.clone());
+    });
+    
+    let outcome = send_deposit(ledger_id, caller, &pending).await;
+    settle_deposit(caller, &pending, outcome)
+}
+
+// Settles a deposit left pending by an earlier call.
+#[update]
+async fn resume_deposit() -> RewardTransactionResponse {
+    let caller = caller();
+    
+    let ledger_id = match token_ledger() {
+        Ok(ledger_id) => ledger_id,
+        Err(error) => return transaction_failure(error),
+    };
+    
+    let Some(pending) = PENDING_DEPOSITS.with(|deposits| deposits.borrow().get(&caller)) else {
+        return transaction_failure("No pending deposit".to_string());
+    };
+    
+    let outcome = resolve_pending_deposit(ledger_id, caller, &pending).await;
+    settle_deposit(caller, &pending, outcome)
+}
+
+// What the ledger made of a transfer we sent.
+#[derive(Debug, PartialEq)]
+enum LedgerOutcome {
+    Executed,
+    Rejected(String),
+    Unknown(String), // the call failed, so the transfer may or may not have happened
+    TooOld,          // outside the deduplication window, so a resend says nothing about the first send
+}
+
+async fn send_deposit(ledger_id: Principal, user_id: Principal, pending: &PendingDeposit) -> LedgerOutcome {
+    let memo = Some(pending.transaction_id.clone().into_bytes());
+    let to = Account {
+        owner: ic_cdk::api::id(),
+        subaccount: None,
+    };
+    
+    match &pending.source {
+        DepositSource::Allowance { from_subaccount } => {
+            let args = TransferFromArgs {
+                spender_subaccount: None,
+                from: Account {
+                    owner: user_id,
+                    subaccount: from_subaccount.clone(),
+                },
+                to,
+                amount: Nat::from(pending.amount),
+                fee: None,
+                memo,
+                created_at_time: Some(pending.created_at_time),
+            };
+            
+            let result: CallResult<(Result<Nat, TransferFromError>,)> =
+                ic_cdk::call(ledger_id, "icrc2_transfer_from", (args,)).await;
+            
+            match result {
+                Ok((Ok(_),)) | Ok((Err(TransferFromError::Duplicate { .. }),)) => LedgerOutcome::Executed,
+                Ok((Err(TransferFromError::TooOld),)) => LedgerOutcome::TooOld,
+                Ok((Err(error),)) => LedgerOutcome::Rejected(format!("Deposit rejected by the token ledger: {:?}", error)),
+                Err((_, message)) => LedgerOutcome::Unknown(format!("Failed to call the token ledger: {}", message)),
+            }
+        }
+        DepositSource::Subaccount { fee, .. } => {
+            let args = TransferArg {
+                from_subaccount: Some(deposit_subaccount(&user_id)),
+                to,
+                fee: Some(Nat::from(*fee)),
+                created_at_time: Some(pending.created_at_time),
+                memo,
+                amount: Nat::from(pending.amount),
+            };
+            
+            let result: CallResult<(Result<Nat, TransferError>,)> =
+                ic_cdk::call(ledger_id, "icrc1_transfer", (args,)).await;
+            
+            match result {
+                Ok((Ok(_),)) | Ok((Err(TransferError::Duplicate { .. }),)) => LedgerOutcome::Executed,
+                Ok((Err(TransferError::TooOld),)) => LedgerOutcome::TooOld,
+                Ok((Err(error),)) => LedgerOutcome::Rejected(format!("Deposit sweep rejected by the token ledger: {:?}", error)),
+                Err((_, message)) => LedgerOutcome::Unknown(format!("Failed to call the token ledger: {}", message)),
+            }
+        }
+    }
+}
+
+// Credits or fails a pending deposit once its outcome is known. Only the call
+// that still finds the deposit pending settles it, so it is credited at most once.
+fn settle_deposit(user_id: Principal, pending: &PendingDeposit, outcome: LedgerOutcome) -> RewardTransactionResponse {
+    let received = match outcome {
+        LedgerOutcome::Executed => Ok(()),
+        LedgerOutcome::Rejected(error) => Err(error),
+        LedgerOutcome::Unknown(error) => {
+            return transaction_failure(format!("{}. The deposit is still pending; call resume_deposit to settle it", error));
+        }
+        LedgerOutcome::TooOld => {
+            return transaction_failure("The deposit is too old to resend and is pending review".to_string());
+        }
+    };
+    
+    let already_settled = PENDING_DEPOSITS.with(|deposits| {
+        let mut deposits = deposits.borrow_mut();
+        match deposits.get(&user_id) {
+            Some(current) if current.transaction_id == pending.transaction_id => {
+                deposits.remove(&user_id);
+                false
+            }
+            _ => true,
+        }
+    });
+    
+    if already_settled {
+        return transaction_failure("Deposit has already been settled".to_string());
+    }
+    
+    match received {
+        Ok(()) => match post_transfer(
+            LedgerAccount::Treasury,
+            LedgerAccount::UserWallet(user_id),
+            pending.amount,
+            TransactionType::Deposit,
+        ) {
+            Ok(()) => finish_transaction(&pending.transaction_id, TransactionStatus::Completed),
+            Err(error) => {
+                finish_transaction(&pending.transaction_id, TransactionStatus::Failed);
+                transaction_failure(format!("Tokens were received but could not be credited: {}", error))
+            }
+        },
+        Err(error) => {
+            finish_transaction(&pending.transaction_id, TransactionStatus::Failed);
+            transaction_failure(error)
+        }
+    }
+}
+
+// Settles a deposit that could not be resolved automatically, once an admin
+// has checked the token ledger for it.
+#[update(guard = "is_admin")]
+fn review_pending_deposit(user_id: Principal, received: bool) -> RewardTransactionResponse {
+    let Some(pending) = PENDING_DEPOSITS.with(|deposits| deposits.borrow().get(&user_id)) else {
+        return transaction_failure("No pending deposit".to_string());
+    };
+    
+    let outcome = if received {
+        LedgerOutcome::Executed
+    } else {
+        LedgerOutcome::Rejected("The deposit was not received by the token ledger".to_string())
+    };
+    settle_deposit(user_id, &pending, outcome)
+}
+
+async fn ledger_fee(ledger_id: Principal) -> Result<u64, String> {
+    let result: CallResult<(Nat,)> = ic_cdk::call(ledger_id, "icrc1_fee", ()).await;
+    
+    match result {
+        Ok((fee,)) => u64::try_from(fee.0).map_err(|_| "Ledger fee is out of range".to_string()),
+        Err((_, message)) => Err(format!("Failed to call the token ledger: {}", message)),
+    }
+}
+
+// Deposit Subaccounts
+// Every user can also deposit by transferring tokens to their own subaccount
+// of the canister, then calling `notify_deposit` to have them swept in.
+fn deposit_subaccount(user_id: &Principal) -> Vec<u8> {
+    // Length-prefixed principal bytes, zero padded to 32 bytes
+    let bytes = user_id.as_slice();
+    let mut subaccount = vec![0u8; 32];
+    subaccount[0] = bytes.len() as u8;
+    subaccount[1..=bytes.len()].copy_from_slice(bytes);
+    subaccount
+}
+
+// Profiles created before deposit subaccounts existed get theirs on upgrade.
+fn assign_deposit_subaccounts() {
+    let unassigned: Vec<UserProfile> = USERS.with(|users| {
+        users
+            .borrow()
+            .iter()
+            .filter(|(_, profile)| profile.deposit_subaccount.is_none())
+            .map(|(_, profile)| profile)
+            .collect()
+    });
+    
+    for mut profile in unassigned {
+        profile.deposit_subaccount = Some(deposit_subaccount(&profile.id));
+        USERS.with(|users| {
+            users.borrow_mut().insert(profile.id, profile);
+        });
+    }
+}
+
+#[query]
+fn get_deposit_account() -> Account {
+    Account {
+        owner: ic_cdk::api::id(),
+        subaccount: Some(deposit_subaccount(&caller())),
+    }
+}
+
+// Sweeps whatever has arrived on the caller's deposit subaccount into the
+// canister's main account and credits it. A sweep left pending by an earlier
+// call is settled instead of starting a new one, see `resolve_pending_deposit`,
+// so repeated notifications never credit the same tokens twice.
+#[update]
+async fn notify_deposit() -> RewardTransactionResponse {
+    let caller = caller();
+    
+    let ledger_id = match token_ledger() {
+        Ok(ledger_id) => ledger_id,
+        Err(error) => return transaction_failure(error),
+    };
+    
+    if USERS.with(|users| !users.borrow().contains_key(&caller)) {
+        return transaction_failure("User not found".to_string());
+    }
+    
+    if let Some(pending) = PENDING_DEPOSITS.with(|deposits| deposits.borrow().get(&caller)) {
+        let outcome = resolve_pending_deposit(ledger_id, caller, &pending).await;
+        return settle_deposit(caller, &pending, outcome);
+    }
+    
+    let balance = match subaccount_balance(ledger_id, caller).await {
+        Ok(balance) => balance,
+        Err(error) => return transaction_failure(error),
+    };
+    
+    let fee = match ledger_fee(ledger_id).await {
+        Ok(fee) => fee,
+        Err(error) => return transaction_failure(error),
+    };
+    
+    // The sweep's ledger fee comes out of the deposit
+    if balance <= fee {
+        return transaction_failure("No new deposit to credit".to_string());
+    }
+    
+    // Another notification may have started a sweep while this one waited
+    if PENDING_DEPOSITS.with(|deposits| deposits.borrow().contains_key(&caller)) {
+        return transaction_failure("A deposit is already being processed".to_string());
+    }
+    
+    let amount = balance - fee;
+    let transaction = create_transaction(caller, amount, TransactionType::Deposit, TransactionStatus::Pending);
+    let pending = PendingDeposit {
+        transaction_id: transaction.id,
+        amount,
+        created_at_time: transaction.created_at,
+        source: DepositSource::Subaccount {
+            balance_before: balance,
+            fee,
+        },
+    };
+    PENDING_DEPOSITS.with(|deposits| {
+        deposits.borrow_mut().insert(caller, pending.clone());
+    });
+    
+    let outcome = send_deposit(ledger_id, caller, &pending).await;
+    settle_deposit(caller, &pending, outcome)
+}
+
+// Works out what happened to a deposit left pending. Only the canister can
+// move tokens out of a deposit subaccount, so a balance below the one the
+// sweep was based on means it went through. Otherwise the same transfer is
+// sent again and the ledger deduplicates it. Once the deduplication window has
+// passed the ledger's block log is searched for the first transfer instead.
+async fn resolve_pending_deposit(ledger_id: Principal, user_id: Principal, pending: &PendingDeposit) -> LedgerOutcome {
+    if let DepositSource::Subaccount { balance_before, .. } = pending.source {
+        match subaccount_balance(ledger_id, user_id).await {
+            Ok(balance) if balance < balance_before => return LedgerOutcome::Executed,
+            Ok(_) => {}
+            Err(error) => return LedgerOutcome::Unknown(error),
+        }
+    }
+    
+    match send_deposit(ledger_id, user_id, pending).await {
+        LedgerOutcome::TooOld => {
+            let memo = pending.transaction_id.as_bytes();
+            let found = find_ledger_transfer(ledger_id, memo, pending.created_at_time, pending.amount).await;
+            logged_transfer_outcome(LedgerOutcome::TooOld, found)
+        }
+        outcome => outcome,
+    }
+}
+
+// Combines the ledger's answer to a resend with a search of its log for the
+// first send. A transfer found in the log went through; one that was not
+// found keeps the resend's answer, which for a transfer too old to resend
+// leaves it for an admin to review. A failed search can be tried again.
+fn logged_transfer_outcome(resent: LedgerOutcome, found: Result<bool, String>) -> LedgerOutcome {
+    match found {
+        Ok(true) => LedgerOutcome::Executed,
+        Ok(false) => resent,
+        Err(error) => LedgerOutcome::Unknown(error),
+    }
+}
+
+// Searches the token ledger's block log backwards from its tip for a transfer
+// with the given memo, created_at_time and amount. The search stops at blocks
+// older than the transfer, at archived blocks or after `MAX_LEDGER_LOG_PAGES`.
+async fn find_ledger_transfer(ledger_id: Principal, memo: &[u8], created_at_time: u64, amount: u64) -> Result<bool, String> {
+    let oldest = created_at_time.saturating_sub(LEDGER_TIME_DRIFT_NANOS);
+    let mut end = ledger_blocks(ledger_id, 0, 0).await?.log_length;
+    
+    for _ in 0..MAX_LEDGER_LOG_PAGES {
+        let end_index = nat_to_u64(&end).ok_or_else(|| "Ledger log length is out of range".to_string())?;
+        if end_index == 0 {
+            return Ok(false);
+        }
+        let start = end_index.saturating_sub(LEDGER_LOG_PAGE_SIZE);
+        let page = ledger_blocks(ledger_id, start, end_index - start).await?;
+        
+        if page.blocks.iter().any(|block| block_matches_transfer(&block.block, memo, created_at_time, amount)) {
+            return Ok(true);
+        }
+        if page.blocks.iter().any(|block| block_field_u64(&block.block, &["ts"]).is_some_and(|ts| ts < oldest)) {
+            return Ok(false);
+        }
+        
+        end = match page.blocks.into_iter().map(|block| block.id).min() {
+            Some(lowest) => lowest,
+            None => return Err("Older ledger blocks have been archived".to_string()),
+        };
+    }
+    
+    Err("Transfer not found in the most recent ledger blocks".to_string())
+}
+
+async fn ledger_blocks(ledger_id: Principal, start: u64, length: u64) -> Result<GetBlocksResult, String> {
+    let args = vec![GetBlocksArgs {
+        start: Nat::from(start),
+        length: Nat::from(length),
+    }];
+    let result: CallResult<(GetBlocksResult,)> = ic_cdk::call(ledger_id, "icrc3_get_blocks", (args,)).await;
+    
+    result
+        .map(|(blocks,)| blocks)
+        .map_err(|(_, message)| format!("Failed to read the token ledger's blocks: {}", message))
+}
+
+// Whether an ICRC-3 block records the transfer, matched on the fields the
+// canister set when sending it.
+fn block_matches_transfer(block: &Icrc3Value, memo: &[u8], created_at_time: u64, amount: u64) -> bool {
+    block_field(block, &["tx", "memo"]) == Some(&Icrc3Value::Blob(memo.to_vec()))
+        && block_field_u64(block, &["tx", "ts"]) == Some(created_at_time)
+        && block_field_u64(block, &["tx", "amt"]) == Some(amount)
+}
+
+fn block_field<'a>(value: &'a Icrc3Value, path: &[&str]) -> Option<&'a Icrc3Value> {
+    path.iter().try_fold(value, |value, name| match value {
+        Icrc3Value::Map(fields) => fields.iter().find(|(key, _)| key == name).map(|(_, value)| value),
+        _ => None,
+    })
+}
+
+fn block_field_u64(value: &Icrc3Value, path: &[&str]) -> Option<u64> {
+    match block_field(value, path)? {
+        Icrc3Value::Nat(nat) => nat_to_u64(nat),
+        _ => None,
+    }
+}
+
+fn nat_to_u64(nat: &Nat) -> Option<u64> {
+    u64::try_from(nat.0.clone()).ok()
+}
+
+async fn subaccount_balance(ledger_id: Principal, user_id: Principal) -> Result<u64, String> {
+    let deposit_account = Account {
+        owner: ic_cdk::api::id(),
+        subaccount: Some(deposit_subaccount(&user_id)),
+    };
+    
+    let result: CallResult<(Nat,)> = ic_cdk::call(ledger_id, "icrc1_balance_of", (deposit_account,)).await;
+    
+    match result {
+        Ok((balance,)) => u64::try_from(balance.0).map_err(|_| "Deposit balance is out of range".to_string()),
+        Err((_, message)) => Err(format!("Failed to call the token ledger: {}", message)),
+    }
+}
+
+// Withdrawals
+// Only KYC-verified users can withdraw, within per-transaction and rolling
+// daily limits. Funds are held in the pending withdrawals account from the
+// moment of the request; requests at or above the approval threshold wait
+// there until an admin approves or rejects them.
+#[update]
+async fn request_withdrawal(amount: u64, destination: Account) -> WithdrawalResponse {
+    let caller = caller();
+    
+    let status = match check_withdrawal(caller, amount) {
+        Ok(status) => status,
+        Err(error) => return withdrawal_failure(error),
+    };
+    
+    // Hold the funds before calling out so they cannot be spent twice
+    if let Err(error) = post_transfer(
+        LedgerAccount::UserWallet(caller),
+        LedgerAccount::PendingWithdrawals,
+        amount,
+        TransactionType::Withdrawal,
+    ) {
+        return withdrawal_failure(error);
+    }
+    
+    let transaction = create_transaction(caller, amount, TransactionType::Withdrawal, TransactionStatus::Pending);
+    
+    let request = WithdrawalRequest {
+        id: transaction.id,
+        user_id: caller,
+        amount,
+        destination,
+        status,
+        created_at: transaction.created_at,
+        decided_by: None,
+        completed_at: None,
+        error: None,
+        sent_at: None,
+        fee: None,
+    };
+    save_withdrawal(&request);
+    
+    if request.status == WithdrawalStatus::PendingApproval {
+        return WithdrawalResponse {
+            success: true,
+            data: Some(request),
+            error: None,
+        };
+    }
+    
+    process_withdrawal(request).await
+}
+
+fn check_withdrawal(user_id: Principal, amount: u64) -> Result<WithdrawalStatus, String> {
+    token_ledger()?;
+    
+    let user = USERS
+        .with(|users| users.borrow().get(&user_id))
+        .ok_or_else(|| "User not found".to_string())?;
+    
+    // Everything requested in the last 24 hours that was not turned down
+    let window_start = time().saturating_sub(WITHDRAWAL_LIMIT_WINDOW_NANOS);
+    let withdrawn: u64 = WITHDRAWALS.with(|withdrawals| {
+        withdrawals
+            .borrow()
+            .iter()
+            .filter(|(_, request)| {
+                request.user_id == user_id
+                    && request.created_at >= window_start
+                    && !matches!(request.status, WithdrawalStatus::Rejected | WithdrawalStatus::Failed)
+            })
+            .map(|(_, request)| request.amount)
+            .sum()
+    });
+    
+    withdrawal_decision(&user.kyc_status, amount, withdrawn, &withdrawal_limits())
+}
+
+// Applies the KYC gate and the limits to a withdrawal, given what the user
+// has already withdrawn within the daily window. Returns whether the request
+// can be processed straight away or has to wait for an admin.
+fn withdrawal_decision(
+    kyc_status: &KYCStatus,
+    amount: u64,
+    withdrawn: u64,
+    limits: &WithdrawalLimits,
+) -> Result<WithdrawalStatus, String> {
+    if !matches!(kyc_status, KYCStatus::Verified) {
+        return Err("KYC verification is required to withdraw".to_string());
+    }
+    
+    if amount == 0 {
+        return Err("Amount must be greater than zero".to_string());
+    }
+    
+    if amount > limits.per_transaction {
+        return Err(format!("Withdrawals are limited to {} tokens per transaction", limits.per_transaction));
+    }
+    
+    if withdrawn.saturating_add(amount) > limits.per_day {
+        return Err(format!(
+            "Withdrawals are limited to {} tokens per day, {} remaining",
+            limits.per_day,
+            limits.per_day.saturating_sub(withdrawn)
+        ));
+    }
+    
+    if amount >= limits.approval_threshold {
+        Ok(WithdrawalStatus::PendingApproval)
+    } else {
+        Ok(WithdrawalStatus::Processing)
+    }
+}
+
+// Sends held funds to their destination. The created_at_time and fee are
+// stored on the request before the first call, so a resend by
+// `resume_withdrawal` is the same transfer and the ledger deduplicates it.
+// Funds only go back to the user when the ledger definitely did not send them.
+async fn process_withdrawal(mut request: WithdrawalRequest) -> WithdrawalResponse {
+    let resend = request.sent_at.is_some();
+    let ledger_id = match token_ledger() {
+        Ok(ledger_id) => ledger_id,
+        Err(error) if resend => return settle_withdrawal(request, LedgerOutcome::Unknown(error)),
+        Err(error) => return settle_withdrawal(request, LedgerOutcome::Rejected(error)),
+    };
+    
+    if !resend {
+        // Nothing has been sent yet, so failing here returns the funds
+        let fee = match ledger_fee(ledger_id).await {
+            Ok(fee) if request.amount > fee => fee,
+            Ok(fee) => {
+                let error = format!("Amount must be greater than the ledger fee of {}", fee);
+                return settle_withdrawal(request, LedgerOutcome::Rejected(error));
+            }
+            Err(error) => return settle_withdrawal(request, LedgerOutcome::Rejected(error)),
+        };
+        request.fee = Some(fee);
+        request.sent_at = Some(time());
+        save_withdrawal(&request);
+    }
+    
+    let outcome = match send_withdrawal(ledger_id, &request).await {
+        // A resend can be turned down even though the first send went through,
+        // so the ledger's log decides
+        outcome @ (LedgerOutcome::Rejected(_) | LedgerOutcome::TooOld) if resend => {
+            let memo = request.id.as_bytes();
+            let sent_at = request.sent_at.unwrap_or_default();
+            let sent_amount = request.amount - request.fee.unwrap_or_default();
+            let found = find_ledger_transfer(ledger_id, memo, sent_at, sent_amount).await;
+            logged_transfer_outcome(outcome, found)
+        }
+        outcome => outcome,
+    };
+    
+    settle_withdrawal(request, outcome)
+}
+
+// Sends the transfer recorded on the request; the ledger fee comes out of the
+// withdrawn amount.
+async fn send_withdrawal(ledger_id: Principal, request: &WithdrawalRequest) -> LedgerOutcome {
+    let fee = request.fee.unwrap_or_default();
+    let args = TransferArg {
+        from_subaccount: None,
+        to: request.destination.clone(),
+        fee: Some(Nat::from(fee)),
+        created_at_time: request.sent_at,
+        memo: Some(request.id.clone().into_bytes()),
+        amount: Nat::from(request.amount - fee),
+    };
+    
+    let result: CallResult<(Result<Nat, TransferError>,)> =
+        ic_cdk::call(ledger_id, "icrc1_transfer", (args,)).await;
+    
+    match result {
+        Ok((Ok(_),)) | Ok((Err(TransferError::Duplicate { .. }),)) => LedgerOutcome::Executed,
+        Ok((Err(TransferError::TooOld),)) => LedgerOutcome::TooOld,
+        // Returned before the ledger looks for duplicates, so it says nothing either way
+        Ok((Err(TransferError::TemporarilyUnavailable),)) => {
+            LedgerOutcome::Unknown("The token ledger is temporarily unavailable".to_string())
+        }
+        Ok((Err(error),)) => LedgerOutcome::Rejected(format!("Withdrawal rejected by the token ledger: {:?}", error)),
+        Err((_, message)) => LedgerOutcome::Unknown(format!("Failed to call the token ledger: {}", message)),
+    }
+}
+
+// Completes or fails a withdrawal once the ledger's answer is definite, and
+// otherwise leaves it processing. Only the call that still finds the request
+// processing settles it, so the held funds move once.
+fn settle_withdrawal(mut request: WithdrawalRequest, outcome: LedgerOutcome) -> WithdrawalResponse {
+    let sent = match outcome {
+        LedgerOutcome::Executed => Ok(()),
+        LedgerOutcome::Rejected(error) => Err(error),
+        LedgerOutcome::Unknown(error) => {
+            return WithdrawalResponse {
+                success: false,
+                data: Some(request),
+                error: Some(format!("{}. The withdrawal is still processing; call resume_withdrawal to settle it", error)),
+            };
+        }
+        LedgerOutcome::TooOld => {
+            return WithdrawalResponse {
+                success: false,
+                data: Some(request),
+                error: Some("The withdrawal is too old to resend and is pending review".to_string()),
+            };
+        }
+    };
+    
+    let still_processing = WITHDRAWALS
+        .with(|withdrawals| withdrawals.borrow().get(&request.id))
+        .is_some_and(|stored| stored.status == WithdrawalStatus::Processing);
+    if !still_processing {
+        return withdrawal_failure("Withdrawal has already been settled".to_string());
+    }
+    
+    match sent {
+        Ok(()) => {
+            let _ = post_transfer(
+                LedgerAccount::PendingWithdrawals,
+                LedgerAccount::Treasury,
+                request.amount,
+                TransactionType::Withdrawal,
+            );
+            finish_transaction(&request.id, TransactionStatus::Completed);
+            request.status = WithdrawalStatus::Completed;
+        }
+        Err(error) => {
+            release_withdrawal(&request);
+            finish_transaction(&request.id, TransactionStatus::Failed);
+            request.status = WithdrawalStatus::Failed;
+            request.error = Some(error);
+        }
+    }
+    
+    request.completed_at = Some(time());
+    save_withdrawal(&request);
+    
+    WithdrawalResponse {
+        success: request.status == WithdrawalStatus::Completed,
+        error: request.error.clone(),
+        data: Some(request),
+    }
+}
+
+fn sent_withdrawal(withdrawal_id: &str) -> Result<WithdrawalRequest, String> {
+    let request = WITHDRAWALS
+        .with(|withdrawals| withdrawals.borrow().get(&withdrawal_id.to_string()))
+        .ok_or_else(|| "Withdrawal not found".to_string())?;
+    
+    if request.status != WithdrawalStatus::Processing || request.sent_at.is_none() {
+        return Err("Withdrawal is not awaiting a ledger outcome".to_string());
+    }
+    
+    Ok(request)
+}
+
+// Sends a withdrawal whose outcome was lost again, with the same arguments.
+#[update]
+async fn resume_withdrawal(withdrawal_id: String) -> WithdrawalResponse {
+    let request = match sent_withdrawal(&withdrawal_id) {
+        Ok(request) => request,
+        Err(error) => return withdrawal_failure(error),
+    };
+    
+    if request.user_id != caller() && is_admin().is_err() {
+        return withdrawal_failure("Unauthorized".to_string());
+    }
+    
+    process_withdrawal(request).await
+}
+
+// Settles a withdrawal that could not be resolved automatically, once an
+// admin has checked the token ledger for it.
+#[update(guard = "is_admin")]
+fn review_withdrawal(withdrawal_id: String, sent: bool) -> WithdrawalResponse {
+    let request = match sent_withdrawal(&withdrawal_id) {
+        Ok(request) => request,
+        Err(error) => return withdrawal_failure(error),
+    };
+    
+    let outcome = if sent {
+        LedgerOutcome::Executed
+    } else {
+        LedgerOutcome::Rejected("The withdrawal was not sent by the token ledger".to_string())
+    };
+    settle_withdrawal(request, outcome)
+}
+
+// Returns held funds to the user's wallet.
+fn release_withdrawal(request: &WithdrawalRequest) {
+    let _ = post_transfer(
+        LedgerAccount::PendingWithdrawals,
+        LedgerAccount::UserWallet(request.user_id),
+        request.amount,
+        TransactionType::Refund,
+    );
+    create_transaction(
+        request.user_id,
+        request.amount,
+        TransactionType::Refund,
+        TransactionStatus::Completed,
+    );
+}
+
+fn save_withdrawal(request: &WithdrawalRequest) {
+    WITHDRAWALS.with(|withdrawals| {
+        withdrawals.borrow_mut().insert(request.id.clone(), request.clone());
+    });
+}
+
+fn awaiting_approval(withdrawal_id: &str) -> Result<WithdrawalRequest, String> {
+    let request = WITHDRAWALS
+        .with(|withdrawals| withdrawals.borrow().get(&withdrawal_id.to_string()))
+        .ok_or_else(|| "Withdrawal not found".to_string())?;
+    
+    if request.status != WithdrawalStatus::PendingApproval {
+        return Err("Withdrawal is not awaiting approval".to_string());
+    }
+    
+    Ok(request)
+}
+
+#[update(guard = "is_admin")]
+async fn approve_withdrawal(withdrawal_id: String) -> WithdrawalResponse {
+    let mut request = match awaiting_approval(&withdrawal_id) {
+        Ok(request) => request,
+        Err(error) => return withdrawal_failure(error),
+    };
+    
+    // Marked before calling out so the request cannot be approved twice
+    request.status = WithdrawalStatus::Processing;
+    request.decided_by = Some(caller());
+    save_withdrawal(&request);
+    
+    let response = process_withdrawal(request).await;
+    
+    // A withdrawal still processing has no outcome to report yet
+    if let Some(request) = &response.data {
+        let notice = match request.status {
+            WithdrawalStatus::Completed => Some((
+                "Withdrawal approved",
+                format!("Your withdrawal of {} tokens has been sent", request.amount),
+            )),
+            WithdrawalStatus::Failed => Some((
+                "Withdrawal failed",
+                format!("Your withdrawal of {} tokens failed and has been returned to your balance", request.amount),
+            )),
+            _ => None,
+        };
+        if let Some((title, message)) = notice {
+            create_notification(request.user_id, title.to_string(), message, NotificationType::SystemUpdate);
+        }
+    }
+    
+    response
+}
+
+#[update(guard = "is_admin")]
+fn reject_withdrawal(withdrawal_id: String, reason: String) -> WithdrawalResponse {
+    let mut request = match awaiting_approval(&withdrawal_id) {
+        Ok(request) => request,
+        Err(error) => return withdrawal_failure(error),
+    };
+    
+    release_withdrawal(&request);
+    finish_transaction(&request.id, TransactionStatus::Cancelled);
+    
+    request.status = WithdrawalStatus::Rejected;
+    request.decided_by = Some(caller());
+    request.completed_at = Some(time());
+    request.error = Some(reason.clone());
+    save_withdrawal(&request);
+    
+    create_notification(
+        request.user_id,
+        "Withdrawal rejected".to_string(),
+        format!("Your withdrawal of {} tokens was rejected: {}", request.amount, reason),
+        NotificationType::SystemUpdate,
+    );
+    
+    WithdrawalResponse {
+        success: true,
+        data: Some(request),
+        error: None,
+    }
+}
+
+#[query(guard = "is_admin")]
+fn get_pending_withdrawals() -> Vec<WithdrawalRequest> {
+    WITHDRAWALS.with(|withdrawals| {
+        withdrawals
+            .borrow()
+            .iter()
+            .filter(|(_, request)| request.status == WithdrawalStatus::PendingApproval)
+            .map(|(_, request)| request)
+            .collect()
+    })
+}
+
+#[query]
+fn get_my_withdrawals() -> Vec<WithdrawalRequest> {
+    let caller = caller();
+    
+    WITHDRAWALS.with(|withdrawals| {
+        withdrawals
+            .borrow()
+            .iter()
+            .filter(|(_, request)| request.user_id == caller)
+            .map(|(_, request)| request)
+            .collect()
+    })
+}
+
+fn withdrawal_limits() -> WithdrawalLimits {
+    WITHDRAWAL_LIMITS.with(|limits| limits.borrow().get().clone())
+}
+
+#[query]
+fn get_withdrawal_limits() -> WithdrawalLimits {
+    withdrawal_limits()
+}
+
+#[update(guard = "is_admin")]
+fn set_withdrawal_limits(limits: WithdrawalLimits) -> WithdrawalLimitsResponse {
+    if limits.per_transaction == 0 || limits.per_day < limits.per_transaction || limits.approval_threshold == 0 {
+        return WithdrawalLimitsResponse {
+            success: false,
+            data: None,
+            error: Some("Limits must be positive and the daily limit at least the per-transaction limit".to_string()),
+        };
+    }
+    
+    WITHDRAWAL_LIMITS.with(|cell| {
+        cell.borrow_mut().set(limits.clone()).unwrap();
+    });
+    
+    WithdrawalLimitsResponse {
+        success: true,
+        data: Some(limits),
+        error: None,
+    }
+}
+
+fn withdrawal_failure(error: String) -> WithdrawalResponse {
+    WithdrawalResponse {
+        success: false,
+        data: None,
+        error: Some(error),
+    }
+}
+
+// Moves a pending transaction to its final status.
+fn finish_transaction(transaction_id: &str, status: TransactionStatus) -> RewardTransactionResponse {
+    let transaction = REWARD_HISTORY.with(|history| {
+        let mut history = history.borrow_mut();
+        let mut transaction = history.get(&transaction_id.to_string())?;
+        transaction.status = status;
+        transaction.completed_at = Some(time());
+        history.insert(transaction_id.to_string(), transaction.clone());
+        Some(transaction)
+    });
+    
+    match transaction {
+        Some(t) => RewardTransactionResponse {
+            success: t.status == TransactionStatus::Completed,
+            data: Some(t),
+            error: None,
+        },
+        None => transaction_failure("Transaction not found".to_string()),
+    }
+}
+
+fn transaction_failure(error: String) -> RewardTransactionResponse {
+    RewardTransactionResponse {
+        success: false,
+        data: None,
+        error: Some(error),
+    }
+}
+
+// --- Rewards Section ---
+// Prize Pool Management
+#[update(guard = "is_admin")]
+fn create_prize_pool(
+    contest_id: String,
+    total_amount: u64,
+    distribution: Vec<PrizeDistribution>,
+) -> PrizePoolResponse {
+    if CONTESTS.with(|contests| !contests.borrow().contains_key(&contest_id)) {
+        return PrizePoolResponse {
+            success: false,
+            data: None,
+            error: Some("Contest not found".to_string()),
+        };
+    }
+    
+    let already_distributed = PRIZE_POOLS.with(|pools| {
+        pools.borrow().get(&contest_id).map(|pool| pool.distributed).unwrap_or(false)
+    });
+    
+    if already_distributed {
+        return PrizePoolResponse {
+            success: false,
+            data: None,
+            error: Some("Prize pool already distributed".to_string()),
+        };
+    }
+    
+    let contest_type = CONTESTS.with(|contests| contests.borrow().get(&contest_id).map(|c| c.contest_type));
+    let single_winner = distribution.len() == 1
+        && distribution[0].rank == 1
+        && distribution[0].rank_end.unwrap_or(1) == 1;
+    
+    if contest_type == Some(ContestType::WinnerTakesAll) && !single_winner {
+        return PrizePoolResponse {
+            success: false,
+            data: None,
+            error: Some("Winner-takes-all contests pay rank 1 only".to_string()),
+        };
+    }
+    
+    let distribution = match resolve_prize_distribution(total_amount, distribution) {
+        Ok(distribution) => distribution,
+        Err(error) => {
+            return PrizePoolResponse {
+                success: false,
+                data: None,
+                error: Some(error),
+            };
+        }
+    };
+    
+    let prize_pool = PrizePool {
+        contest_id: contest_id.clone(),
+        total_amount,
+        distribution,
+        distributed: false,
+        rake: None,
+    };
+    
+    PRIZE_POOLS.with(|pools| {
+        pools.borrow_mut().insert(contest_id, prize_pool.clone());
+    });
+    
+    PrizePoolResponse {
+        success: true,
+        data: Some(prize_pool),
+        error: None,
+    }
+}
+
+#[query]
+fn get_prize_pool(contest_id: String) -> PrizePoolResponse {
+    let pool = PRIZE_POOLS.with(|pools| pools.borrow().get(&contest_id));
+    
+    match pool {
+        Some(p) => PrizePoolResponse {
+            success: true,
+            data: Some(p),
+            error: None,
+        },
+        None => PrizePoolResponse {
+            success: false,
+            data: None,
+            error: Some("Prize pool not found".to_string()),
+        },
+    }
+}
+
+// Validates the prize tiers and fills in each tier's per-rank amount.
+fn resolve_prize_distribution(
+    total_amount: u64,
+    mut distribution: Vec<PrizeDistribution>,
+) -> Result<Vec<PrizeDistribution>, String> {
+    if distribution.is_empty() {
+        return Err("Prize distribution is empty".to_string());
+    }
+    
+    distribution.sort_by_key(|tier| tier.rank);
+    
+    let mut next_rank = 1;
+    let mut total_percentage = 0.0;
+    
+    for tier in distribution.iter_mut() {
+        let rank_end = tier.rank_end.unwrap_or(tier.rank);
+        
+        if tier.rank < next_rank || rank_end < tier.rank {
+            return Err(format!("Invalid or overlapping prize tier starting at rank {}", tier.rank));
+        }
+        if !tier.percentage.is_finite() || tier.percentage <= 0.0 {
+            return Err(format!("Invalid percentage for prize tier starting at rank {}", tier.rank));
+        }
+        
+        let rank_count = (rank_end - tier.rank + 1) as f64;
+        total_percentage += tier.percentage * rank_count;
+        tier.amount = (total_amount as f64 * tier.percentage / 100.0).floor() as u64;
+        next_rank = rank_end + 1;
+    }
+    
+    if (total_percentage - 100.0).abs() > 1e-6 {
+        return Err(format!("Prize percentages add up to {}%, expected 100%", total_percentage));
+    }
+    
+    if prize_pool_allocated(&distribution) > total_amount {
+        return Err("Prize amounts exceed the pool".to_string());
+    }
+    
+    Ok(distribution)
+}
+
+fn prize_pool_allocated(distribution: &[PrizeDistribution]) -> u64 {
+    distribution
+        .iter()
+        .map(|tier| tier.amount * (tier.rank_end.unwrap_or(tier.rank) - tier.rank + 1) as u64)
+        .sum()
+}
+
+fn prize_for_rank(pool: &PrizePool, rank: u32) -> u64 {
+    pool.distribution
+        .iter()
+        .find(|tier| rank >= tier.rank && rank <= tier.rank_end.unwrap_or(tier.rank))
+        .map(|tier| tier.amount)
+        .unwrap_or(0)
+}
+
+// Contests without an explicit prize pool pay their whole advertised pool to rank 1.
+fn default_prize_pool(contest: &Contest) -> PrizePool {
+    PrizePool {
+        contest_id: contest.id.clone(),
+        total_amount: contest.prize_pool,
+        distribution: vec![PrizeDistribution {
+            rank: 1,
+            rank_end: None,
+            percentage: 100.0,
+            amount: contest.prize_pool,
+        }],
+        distributed: false,
+        rake: None,
+    }
+}
+
+// Pays every ranked entry its share of the contest's prize pool, see
+// `split_prizes_by_group` for how ties and rounding are handled. Prizes for
+// ranks without an entry are left in escrow and settled to the house.
+fn pay_contest_prizes(contest: &Contest, ranked_groups: &mut [Vec<ContestEntry>]) -> Result<(), String> {
+    let mut pool = PRIZE_POOLS
+        .with(|pools| pools.borrow().get(&contest.id))
+        .unwrap_or_else(|| default_prize_pool(contest));
+    
+    if pool.distributed {
+        return Ok(());
+    }
+    
+    let escrow = LedgerAccount::ContestEscrow(contest.id.clone());
+    let collected = u64::try_from(account_balance(&escrow)).unwrap_or(0);
+    let mut rake = rake_on(collected);
+    let mut shortfall = 0;
+    
+    if contest.contest_type == ContestType::Guaranteed {
+        // The advertised pool is paid in full and the house covers any
+        // shortfall. The rake only comes out of entry fees raised above the
+        // pool, so the house never tops up its own cut.
+        shortfall = pool.total_amount.saturating_sub(collected);
+        rake = rake.min(collected.saturating_sub(pool.total_amount));
+    } else {
+        // Other contests shrink their pool in proportion to the spots filled,
+        // and never pay out more than the escrow holds after the rake
+        let available = collected - rake;
+        let mut payable = pool.total_amount;
+        if contest.filled_spots < contest.total_spots {
+            payable = (u128::from(payable) * u128::from(contest.filled_spots) / u128::from(contest.total_spots)) as u64;
+        }
+        payable = payable.min(available);
+        
+        if payable < pool.total_amount {
+            pool.distribution = resolve_prize_distribution(payable, pool.distribution.clone())?;
+            pool.total_amount = payable;
+        }
+    }
+    
+    pool.rake = Some(take_rake(&contest.id, rake));
+    if post_transfer(LedgerAccount::House, escrow, shortfall, TransactionType::HouseShortfall).is_err() {
+        return Err("The house could not cover the prize pool shortfall".to_string());
+    }
+    
+    let group_sizes: Vec<usize> = ranked_groups.iter().map(|group| group.len()).collect();
+    let shares = split_prizes_by_group(&pool, &group_sizes);
+    let mut unpaid = false;
+    
+    for (group, group_shares) in ranked_groups.iter_mut().zip(shares) {
+        for (entry, amount) in group.iter_mut().zip(group_shares) {
+            let rank = entry.rank.unwrap_or(0);
+            
+            if amount > 0 && !award_contest_prize(entry, rank, amount) {
+                unpaid = true;
+            }
+        }
+    }
+    
+    pool.distributed = true;
+    PRIZE_POOLS.with(|pools| {
+        pools.borrow_mut().insert(contest.id.clone(), pool);
+    });
+    
+    // Prizes that failed to pay stay in escrow
+    if !unpaid {
+        settle_escrow(&contest.id);
+    }
+    
+    Ok(())
+}
+
+// Works out what each member of each ranked group is paid. Groups are given
+// in finishing order; tied members split the combined prizes of the positions
+// they occupy and tokens that do not divide evenly go one each to the earliest
+// members. Rounding dust left over from the percentage split goes to position 1.
+fn split_prizes_by_group(pool: &PrizePool, group_sizes: &[usize]) -> Vec<Vec<u64>> {
+    let dust = pool.total_amount.saturating_sub(prize_pool_allocated(&pool.distribution));
+    let mut next_position = 1u32;
+    
+    group_sizes
+        .iter()
+        .map(|&size| {
+            let positions = next_position..next_position + size as u32;
+            next_position = positions.end;
+            
+            let combined: u64 = positions
+                .map(|position| {
+                    let amount = prize_for_rank(pool, position);
+                    if position == 1 {
+                        amount + dust
+                    } else {
+                        amount
+                    }
+                })
+                .sum();
+            
+            let share = combined / size as u64;
+            let leftover = (combined % size as u64) as usize;
+            
+            (0..size)
+                .map(|index| if index < leftover { share + 1 } else { share })
+                .collect()
+        })
+        .collect()
+}
+
+// Credits a contest prize straight to the winner's balance and records it.
+// Returns whether the prize was paid.
+fn award_contest_prize(entry: &mut ContestEntry, rank: u32, amount: u64) -> bool {
+    let paid = credit_prize(entry.user_id, &entry.contest_id, rank, amount);
+    if paid {
+        entry.prize = Some(amount);
+    }
+    paid
+}
+
+fn credit_prize(user_id: Principal, contest_id: &str, rank: u32, amount: u64) -> bool {
+    let now = time();
+    let reward_id = get_next_id(&NEXT_REWARD_ID);
+    
+    let paid = post_transfer(
+        LedgerAccount::ContestEscrow(contest_id.to_string()),
+        LedgerAccount::UserWallet(user_id),
+        amount,
+        TransactionType::ContestWin,
+    )
+    .is_ok();
+    
+    let transaction = create_transaction(
+        user_id,
+        amount,
+        TransactionType::ContestWin,
+        if paid { TransactionStatus::Completed } else { TransactionStatus::Failed },
+    );
+    
+    let user_reward = UserReward {
+        id: reward_id.to_string(),
+        user_id,
+        contest_id: contest_id.to_string(),
+        amount,
+        rank,
+        status: if paid { RewardStatus::Claimed } else { RewardStatus::Failed },
+        created_at: now,
+        claimed_at: if paid { Some(now) } else { None },
+        transaction_id: Some(transaction.id),
+    };
+    
+    USER_REWARDS.with(|rewards_map| {
+        let mut user_rewards = rewards_map.borrow().get(&user_id).unwrap_or_default().0;
+        user_rewards.push(user_reward);
+        rewards_map.borrow_mut().insert(user_id, StorableVec(user_rewards));
+    });
+    
+    if !paid {
+        return false;
+    }
+    
+    create_notification(
+        user_id,
+        "Prize won".to_string(),
+        format!("You finished rank {} and won {} tokens", rank, amount),
+        NotificationType::PrizeWon,
+    );
+    
+    true
+}
+
+#[update]
+fn claim_reward(reward_id: String) -> UserRewardResponse {
+    let mut reward = find_user_reward(reward_id.clone());
+    
+    if let Some(ref mut r) = reward {
+        if r.status == RewardStatus::Pending {
+            // Bonuses are paid from the promo pool
+            let paid = post_transfer(
+                LedgerAccount::Promo,
+                LedgerAccount::UserWallet(r.user_id),
+                r.amount,
+                TransactionType::Bonus,
+            );
+            
+            let status = if paid.is_ok() { TransactionStatus::Completed } else { TransactionStatus::Failed };
+            if let Some(transaction_id) = &r.transaction_id {
+                finish_transaction(transaction_id, status);
+            }
+            
+            if let Err(error) = paid {
+                r.status = RewardStatus::Failed;
+                update_user_reward(r.clone());
+                return UserRewardResponse {
+                    success: false,
+                    data: Some(r.clone()),
+                    error: Some(error),
+                };
+            }
+            
+            r.status = RewardStatus::Claimed;
+            r.claimed_at = Some(time());
+            update_user_reward(r.clone());
+            
+            UserRewardResponse {
+                success: true,
+                data: Some(r.clone()),
+                error: None,
+            }
+        } else {
+            UserRewardResponse {
+                success: false,
+                data: None,
+                error: Some("Reward already claimed or failed".to_string()),
+            }
+        }
+    } else {
+        UserRewardResponse {
+            success: false,
+            data: None,
+            error: Some("Reward not found".to_string()),
+        }
+    }
+}
+
+// Pays out a reward whose credit failed: prizes from the escrow they were won
+// in, bonuses from the promo pool. Once a contest or league has no failed
+// prizes left, its escrow is settled.
+#[update(guard = "is_admin")]
+fn retry_reward(reward_id: String) -> UserRewardResponse {
+    let Some(mut reward) = find_user_reward(reward_id) else {
+        return UserRewardResponse {
+            success: false,
+            data: None,
+            error: Some("Reward not found".to_string()),
+        };
+    };
+    
+    if reward.status != RewardStatus::Failed {
+        return UserRewardResponse {
+            success: false,
+            data: None,
+            error: Some("Only failed rewards can be retried".to_string()),
+        };
+    }
+    
+    let is_bonus = reward.contest_id == "bonus";
+    let (source, transaction_type) = if is_bonus {
+        (LedgerAccount::Promo, TransactionType::Bonus)
+    } else {
+        (LedgerAccount::ContestEscrow(reward.contest_id.clone()), TransactionType::ContestWin)
+    };
+    
+    if let Err(error) = post_transfer(source, LedgerAccount::UserWallet(reward.user_id), reward.amount, transaction_type) {
+        return UserRewardResponse {
+            success: false,
+            data: Some(reward),
+            error: Some(error),
+        };
+    }
+    
+    reward.status = RewardStatus::Claimed;
+    reward.claimed_at = Some(time());
+    update_user_reward(reward.clone());
+    if let Some(transaction_id) = &reward.transaction_id {
+        finish_transaction(transaction_id, TransactionStatus::Completed);
+    }
+    
+    if !is_bonus {
+        record_retried_prize(&reward);
+        
+        let failed_remaining = USER_REWARDS.with(|rewards| {
+            rewards
+                .borrow()
+                .iter()
+                .flat_map(|(_, user_rewards)| user_rewards.0)
+                .any(|other| other.contest_id == reward.contest_id && other.status == RewardStatus::Failed)
+        });
+        if !failed_remaining {
+            settle_escrow(&reward.contest_id);
+        }
+        
+        create_notification(
+            reward.user_id,
+            "Prize won".to_string(),
+            format!("You finished rank {} and won {} tokens", reward.rank, reward.amount),
+            NotificationType::PrizeWon,
+        );
+    }
+    
+    UserRewardResponse {
+        success: true,
+        data: Some(reward),
+        error: None,
+    }
+}
+
+// Marks the prize of a retried reward on the contest or season entry it was won with.
+fn record_retried_prize(reward: &UserReward) {
+    let season_entry_id = season_entry_key(&reward.contest_id, &reward.user_id);
+    let season_entry = SEASON_ENTRIES.with(|entries| entries.borrow().get(&season_entry_id));
+    
+    if let Some(mut entry) = season_entry {
+        entry.prize = Some(reward.amount);
+        SEASON_ENTRIES.with(|entries| {
+            entries.borrow_mut().insert(season_entry_id, entry);
+        });
+        return;
+    }
+    
+    let contest_entry = get_contest_entries(reward.contest_id.clone())
+        .into_iter()
+        .find(|entry| entry.user_id == reward.user_id && entry.rank == Some(reward.rank) && entry.prize.is_none());
+    
+    if let Some(mut entry) = contest_entry {
+        entry.prize = Some(reward.amount);
+        CONTEST_ENTRIES.with(|entries| {
+            entries.borrow_mut().insert(entry.id.clone(), entry);
+        });
+    }
+}
+
+fn find_user_reward(reward_id: String) -> Option<UserReward> {
+    USER_REWARDS.with(|rewards| {
+        rewards
+            .borrow()
+            .iter()
+            .flat_map(|(_, user_rewards)| user_rewards.0)
+            .find(|reward| reward.id == reward_id)
+    })
+}
+
+fn update_user_reward(updated_reward: UserReward) {
+    USER_REWARDS.with(|rewards| {
+        let mut rewards = rewards.borrow_mut();
+        let mut user_rewards = rewards.get(&updated_reward.user_id).unwrap_or_default();
+        if let Some(reward) = user_rewards.0.iter_mut().find(|reward| reward.id == updated_reward.id) {
+            *reward = updated_reward.clone();
+            rewards.insert(updated_reward.user_id, user_rewards);
+        }
+    });
+}
+
+fn create_transaction(
+    user_id: Principal,
+    amount: u64,
+    transaction_type: TransactionType,
+    status: TransactionStatus,
+) -> RewardTransaction {
+    let transaction_id = get_next_id(&NEXT_TRANSACTION_ID);
+    let now = time();
+    
+    let completed_at = match status {
+        TransactionStatus::Completed => Some(now),
+        _ => None,
+    };
+    
+    let transaction = RewardTransaction {
+        id: transaction_id.to_string(),
+        user_id,
+        amount,
+        transaction_type,
+        status,
+        created_at: now,
+        completed_at,
+    };
+    
+    REWARD_HISTORY.with(|history| {
+        history.borrow_mut().insert(transaction_id.to_string(), transaction.clone());
+    });
+    
+    transaction
+}
+
+#[query]
+fn get_user_rewards(user_id: Option<Principal>) -> UserRewardsResponse {
+    let user_id = user_id.unwrap_or_else(caller);
+    
+    let rewards = USER_REWARDS.with(|rewards| {
+        rewards.borrow().get(&user_id).unwrap_or_default().0
+    });
+    
+    UserRewardsResponse {
+        success: true,
+        data: Some(rewards),
+        error: None,
+    }
+}
+
+#[query]
+fn get_user_transactions(user_id: Option<Principal>) -> RewardTransactionsResponse {
+    let user_id = user_id.unwrap_or_else(caller);
+    
+    let transactions = REWARD_HISTORY.with(|history| {
+        history
+            .borrow()
+            .iter()
+            .filter(|(_, transaction)| transaction.user_id == user_id)
+            .map(|(_, transaction)| transaction)
+            .collect()
+    });
+    
+    RewardTransactionsResponse {
+        success: true,
+        data: Some(transactions),
+        error: None,
+    }
+}
+
+#[query]
+fn get_pending_rewards(user_id: Option<Principal>) -> UserRewardsResponse {
+    let user_id = user_id.unwrap_or_else(caller);
+    
+    let rewards = USER_REWARDS.with(|rewards| {
+        rewards
+            .borrow()
+            .get(&user_id)
+                        .unwrap_or_default().0
+            .into_iter()
+            .filter(|reward| reward.status == RewardStatus::Pending)
+            .collect()
+    });
+    
+    UserRewardsResponse {
+        success: true,
+        data: Some(rewards),
+        error: None,
+    }
+}
+
+#[update(guard = "is_admin")]
+fn create_bonus_reward(user_id: Principal, amount: u64, _reason: String) -> UserRewardResponse {
+    let reward_id = get_next_id(&NEXT_REWARD_ID);
+    
+    // Create transaction record, completed when the bonus is claimed
+    let transaction = create_transaction(
+        user_id,
+        amount,
+        TransactionType::Bonus,
+        TransactionStatus::Pending,
+    );
+    
+    let user_reward = UserReward {
+        id: reward_id.to_string(),
+        user_id,
+        contest_id: "bonus".to_string(),
+        amount,
+        rank: 0,
+        status: RewardStatus::Pending,
+        created_at: time(),
+        claimed_at: None,
+        transaction_id: Some(transaction.id),
+    };
+    
+    USER_REWARDS.with(|rewards_map| {
+        let mut user_rewards = rewards_map.borrow().get(&user_id).unwrap_or_default().0;
+        user_rewards.push(user_reward.clone());
+        rewards_map.borrow_mut().insert(user_id, StorableVec(user_rewards));
+    });
+    
+    UserRewardResponse {
+        success: true,
+        data: Some(user_reward),
+        error: None,
+    }
+}
+
+#[query]
+fn get_total_rewards_distributed() -> u64 {
+    USER_REWARDS.with(|rewards| {
+        rewards
+            .borrow()
+            .iter()
+            .flat_map(|(_, user_rewards)| user_rewards.0)
+            .filter(|reward| reward.status == RewardStatus::Claimed)
+            .map(|reward| reward.amount)
+            .sum()
+    })
+}
+
+#[query]
+fn get_reward_count() -> u64 {
+    USER_REWARDS.with(|rewards| {
+        rewards
+            .borrow()
+            .iter()
+            .map(|(_, user_rewards)| user_rewards.0.len() as u64)
+            .sum()
+    })
+}
+
+#[query]
+fn get_transaction_count() -> u64 {
+    REWARD_HISTORY.with(|history| history.borrow().len())
+}
+
+// --- Team Management Section ---
+// Fantasy Team Management
+#[update(guard = "is_admin")]
+fn set_team_composition_rules(rules: TeamCompositionRules) -> TeamCompositionRulesResponse {
+    let invalid = if rules.min_squad_size == 0 || rules.min_squad_size > rules.max_squad_size {
+        Some("Squad size range is invalid".to_string())
+    } else if rules.position_limits.iter().any(|limit| limit.min > limit.max) {
+        Some("Position limits must have min not greater than max".to_string())
+    } else if rules.max_players_per_team == 0 {
+        Some("Max players per team must be at least 1".to_string())
+    } else if rules.min_credits > rules.max_credits {
+        Some("Credit budget range is invalid".to_string())
+    } else {
+        None
+    };
+    
+    if let Some(error) = invalid {
+        return TeamCompositionRulesResponse {
+            success: false,
+            data: None,
+            error: Some(error),
+        };
+    }
+    
+    TEAM_COMPOSITION_RULES.with(|composition_rules| {
+        composition_rules.borrow_mut().insert(rules.sport.clone(), rules.clone());
+    });
+    
+    TeamCompositionRulesResponse {
+        success: true,
+        data: Some(rules),
+        error: None,
+    }
+}
+
+#[query]
+fn get_team_composition_rules(sport: Sport) -> TeamCompositionRulesResponse {
+    let rules = TEAM_COMPOSITION_RULES.with(|rules| rules.borrow().get(&sport));
+    
+    match rules {
+        Some(rules) => TeamCompositionRulesResponse {
+            success: true,
+            data: Some(rules),
+            error: None,
+        },
+        None => TeamCompositionRulesResponse {
+            success: false,
+            data: None,
+            error: Some("Team composition rules not found".to_string()),
+        },
+    }
+}
+
+// Looks up the sides a squad for the match may be picked from and the
+// composition rules of its sport, refusing matches that are already locked.
+fn team_selection_context(match_id: &str) -> Result<(Vec<String>, TeamCompositionRules), String> {
+    let match_obj = MATCHES
+        .with(|matches| matches.borrow().get(&match_id.to_string()))
+        .ok_or_else(|| "Match not found".to_string())?;
+    
+    if is_match_locked(match_id) {
+        return Err("Match is locked for team changes".to_string());
+    }
+    
+    let sport = get_match_sport(match_id).ok_or_else(|| "Match sport not found".to_string())?;
+    let rules = composition_rules_for(&sport)?;
+    
+    Ok((vec![match_obj.team1_id, match_obj.team2_id], rules))
+}
+
+fn composition_rules_for(sport: &Sport) -> Result<TeamCompositionRules, String> {
+    TEAM_COMPOSITION_RULES
+        .with(|rules| rules.borrow().get(sport))
+        .ok_or_else(|| "Team composition rules not found".to_string())
+}
+
+// Checks a squad against the composition rules and returns the total price of
+// the players, or every rule the squad breaks.
+fn validate_fantasy_team(
+    eligible_team_ids: &[String],
+    rules: &TeamCompositionRules,
+    captain_id: &str,
+    vice_captain_id: &str,
+    player_ids: &[String],
+) -> Result<u64, Vec<TeamRuleViolation>> {
+    let mut violations = Vec::new();
+    let mut violation = |rule: CompositionRule, message: String| {
+        violations.push(TeamRuleViolation { rule, message });
+    };
+    
+    let squad_size = player_ids.len() as u32;
+    if squad_size < rules.min_squad_size || squad_size > rules.max_squad_size {
+        let expected = if rules.min_squad_size == rules.max_squad_size {
+            rules.min_squad_size.to_string()
+        } else {
+            format!("{}-{}", rules.min_squad_size, rules.max_squad_size)
+        };
+        violation(
+            CompositionRule::SquadSize,
+            format!("Team must have {} players, got {}", expected, squad_size),
+        );
+    }
+    
+    let mut seen = HashSet::new();
+    let mut unique_players = Vec::new();
+    for player_id in player_ids {
+        if seen.insert(player_id.as_str()) {
+            unique_players.push(player_id);
+        } else {
+            violation(
+                CompositionRule::DuplicatePlayer,
+                format!("Player {} is selected more than once", player_id),
+            );
+        }
+    }
+    
+    // Validate captain and vice-captain are in the team
+    if !player_ids.iter().any(|id| id == captain_id) || !player_ids.iter().any(|id| id == vice_captain_id) {
+        violation(
+            CompositionRule::Captaincy,
+            "Captain and vice-captain must be in the team".to_string(),
+        );
+    } else if captain_id == vice_captain_id && player_ids.len() > 1 {
+        violation(
+            CompositionRule::Captaincy,
+            "Captain and vice-captain must be different players".to_string(),
+        );
+    }
+    
+    // Every player must exist and play for one of the two sides
+    let mut total_price = 0u64;
+    let mut position_counts: Vec<(PlayerPosition, u32)> = Vec::new();
+    let mut team_counts: Vec<(String, u32)> = Vec::new();
+    for player_id in unique_players {
+        let Some(player) = PLAYERS.with(|players| players.borrow().get(player_id)) else {
+            violation(
+                CompositionRule::UnknownPlayer,
+                format!("Player {} not found", player_id),
+            );
+            continue;
+        };
+        
+        if !eligible_team_ids.contains(&player.team_id) {
+            violation(
+                CompositionRule::PlayerNotInMatch,
+                format!("Player {} does not play for an eligible team", player_id),
+            );
+        }
+        
+        total_price += player.price;
+        match position_counts.iter_mut().find(|(position, _)| *position == player.position) {
+            Some((_, count)) => *count += 1,
+            None => position_counts.push((player.position, 1)),
+        }
+        match team_counts.iter_mut().find(|(team_id, _)| *team_id == player.team_id) {
+            Some((_, count)) => *count += 1,
+            None => team_counts.push((player.team_id, 1)),
+        }
+    }
+    
+    for limit in &rules.position_limits {
+        let count = position_counts
+            .iter()
+            .find(|(position, _)| *position == limit.position)
+            .map(|(_, count)| *count)
+            .unwrap_or(0);
+        if count < limit.min || count > limit.max {
+            violation(
+                CompositionRule::PositionLimit(limit.position.clone()),
+                format!(
+                    "Team must have {}-{} {:?} players, got {}",
+                    limit.min, limit.max, limit.position, count
+                ),
+            );
+        }
+    }
+    
+    for (team_id, count) in &team_counts {
+        if *count > rules.max_players_per_team {
+            violation(
+                CompositionRule::MaxPlayersPerTeam,
+                format!(
+                    "At most {} players can be picked from team {}, got {}",
+                    rules.max_players_per_team, team_id, count
+                ),
+            );
+        }
+    }
+    
+    // Validate team price
+    if !(rules.min_credits..=rules.max_credits).contains(&total_price) {
+        violation(
+            CompositionRule::CreditBudget,
+            format!(
+                "Team price must be between {} and {} tokens, got {}",
+                rules.min_credits, rules.max_credits, total_price
+            ),
+        );
+    }
+    
+    if violations.is_empty() {
+        Ok(total_price)
+    } else {
+        Err(violations)
+    }
+}
+
+#[update]
+fn create_fantasy_team(
+    name: String,
+    match_id: String,
+    captain_id: String,
+    vice_captain_id: String,
+    player_ids: Vec<String>,
+) -> FantasyTeamResponse {
+    let caller = caller();
+    
+    let total_price = match check_fantasy_team(&match_id, &captain_id, &vice_captain_id, &player_ids) {
+        Ok(total_price) => total_price,
+        Err((error, violations)) => return fantasy_team_failure(error, violations),
+    };
+    
+    let fantasy_team = FantasyTeam {
+        id: get_next_id(&NEXT_FANTASY_TEAM_ID).to_string(),
+        name,
+        match_id: Some(match_id),
+        captain_id,
+        vice_captain_id,
+        players: player_ids,
+        total_points: 0.0,
+        total_price,
+    };
+    
+    insert_user_fantasy_team(caller, &fantasy_team);
+    
+    FantasyTeamResponse {
+        success: true,
+        data: Some(fantasy_team),
+        error: None,
+        violations: None,
+    }
+}
+
+#[update]
+fn update_fantasy_team(
+    team_id: String,
+    name: String,
+    captain_id: String,
+    vice_captain_id: String,
+    player_ids: Vec<String>,
+) -> FantasyTeamResponse {
+    let caller = caller();
+    
+    let mut fantasy_team = match owned_fantasy_team(&caller, &team_id) {
+        Ok(team) => team,
+        Err(error) => return fantasy_team_failure(error, None),
+    };
+    
+    let match_id = fantasy_team.match_id.clone().unwrap_or_default();
+    let total_price = match check_fantasy_team(&match_id, &captain_id, &vice_captain_id, &player_ids) {
+        Ok(total_price) => total_price,
+        Err((error, violations)) => return fantasy_team_failure(error, violations),
+    };
+    
+    fantasy_team.name = name;
+    fantasy_team.captain_id = captain_id;
+    fantasy_team.vice_captain_id = vice_captain_id;
+    fantasy_team.players = player_ids;
+    fantasy_team.total_price = total_price;
+    
+    FANTASY_TEAMS.with(|teams| {
+        teams.borrow_mut().insert(team_id.clone(), fantasy_team.clone());
+    });
+    
+    // Entries in contests that haven't locked yet follow the edited team
+    let contest_ids = USER_CONTESTS.with(|user_contests| {
+        user_contests.borrow().get(&caller).unwrap_or_default().0
+    });
+    for contest_id in contest_ids {
+        let unlocked = CONTESTS
+            .with(|contests| contests.borrow().get(&contest_id))
+            .map(|contest| {
+                matches!(contest.status, ContestStatus::Open | ContestStatus::Full)
+                    && !is_match_locked(&contest.match_id)
+            })
+            .unwrap_or(false);
+        if !unlocked {
+            continue;
+        }
+        
+        let entry_ids = CONTEST_ENTRIES_BY_CONTEST.with(|entries| {
+            entries.borrow().get(&contest_id).unwrap_or_default().0
+        });
+        CONTEST_ENTRIES.with(|entries| {
+            let mut entries = entries.borrow_mut();
+            for entry_id in entry_ids {
+                if let Some(mut entry) = entries.get(&entry_id) {
+                    if entry.user_id == caller && entry.team.id == team_id {
+                        entry.team = fantasy_team.clone();
+                        entries.insert(entry_id, entry);
+                    }
+                }
+            }
+        });
+    }
+    
+    FantasyTeamResponse {
+        success: true,
+        data: Some(fantasy_team),
+        error: None,
+        violations: None,
+    }
+}
+
+#[update]
+fn clone_fantasy_team(team_id: String, name: String) -> FantasyTeamResponse {
+    let caller = caller();
+    
+    let source = match owned_fantasy_team(&caller, &team_id) {
+        Ok(team) => team,
+        Err(error) => return fantasy_team_failure(error, None),
+    };
+    
+    // The rules or the deadline may have changed since the source was created
+    let match_id = source.match_id.clone().unwrap_or_default();
+    let total_price = match check_fantasy_team(&match_id, &source.captain_id, &source.vice_captain_id, &source.players) {
+        Ok(total_price) => total_price,
+        Err((error, violations)) => return fantasy_team_failure(error, violations),
+    };
+    
+    let fantasy_team = FantasyTeam {
+        id: get_next_id(&NEXT_FANTASY_TEAM_ID).to_string(),
+        name,
+        match_id: source.match_id,
+        captain_id: source.captain_id,
+        vice_captain_id: source.vice_captain_id,
+        players: source.players,
+        total_points: 0.0,
+        total_price,
+    };
+    
+    insert_user_fantasy_team(caller, &fantasy_team);
+    
+    FantasyTeamResponse {
+        success: true,
+        data: Some(fantasy_team),
+        error: None,
+        violations: None,
+    }
+}
+
+fn owned_fantasy_team(owner: &Principal, team_id: &str) -> Result<FantasyTeam, String> {
+    let team = FANTASY_TEAMS
+        .with(|teams| teams.borrow().get(&team_id.to_string()))
+        .ok_or_else(|| "Fantasy team not found".to_string())?;
+    
+    let owns_team = USER_FANTASY_TEAMS.with(|user_teams| {
+        user_teams
+            .borrow()
+            .get(owner)
+            .map(|team_ids| team_ids.0.iter().any(|id| id == team_id))
+            .unwrap_or(false)
+    });
+    
+    if !owns_team {
+        return Err("Unauthorized".to_string());
+    }
+    
+    Ok(team)
+}
+
+// Runs the deadline and composition checks for a squad, returning the total
+// price or the error and any rule violations to report back.
+fn check_fantasy_team(
+    match_id: &str,
+    captain_id: &str,
+    vice_captain_id: &str,
+    player_ids: &[String],
+) -> Result<u64, (String, Option<Vec<TeamRuleViolation>>)> {
+    let (eligible_team_ids, rules) = team_selection_context(match_id).map_err(|error| (error, None))?;
+    
+    validate_fantasy_team(&eligible_team_ids, &rules, captain_id, vice_captain_id, player_ids).map_err(|violations| {
+        (
+            "Team violates the composition rules".to_string(),
+            Some(violations),
+        )
+    })
+}
+
+fn fantasy_team_failure(error: String, violations: Option<Vec<TeamRuleViolation>>) -> FantasyTeamResponse {
+    FantasyTeamResponse {
+        success: false,
+        data: None,
+        error: Some(error),
+        violations,
+    }
+}
+
+fn insert_user_fantasy_team(owner: Principal, fantasy_team: &FantasyTeam) {
+    FANTASY_TEAMS.with(|teams| {
+        teams.borrow_mut().insert(fantasy_team.id.clone(), fantasy_team.clone());
+    });
+    
+    // Add team to user's teams
+    let mut user_teams = USER_FANTASY_TEAMS.with(|user_teams| {
+        user_teams.borrow().get(&owner).unwrap_or_default().0
+    });
+    user_teams.push(fantasy_team.id.clone());
+    USER_FANTASY_TEAMS.with(|user_teams_map| {
+        user_teams_map.borrow_mut().insert(owner, StorableVec(user_teams));
+    });
+}
+
+#[query]
+fn get_user_fantasy_teams(user_id: Option<Principal>) -> FantasyTeamsResponse {
+    let user_id = user_id.unwrap_or_else(caller);
+    
+    let team_ids = USER_FANTASY_TEAMS.with(|user_teams| {
+        user_teams.borrow().get(&user_id).unwrap_or_default().0
+    });
+    
+    let teams = FANTASY_TEAMS.with(|teams| {
+        team_ids
+            .iter()
+            .filter_map(|id| teams.borrow().get(id))
+            .collect()
+    });
+    
+    FantasyTeamsResponse {
+        success: true,
+        data: Some(teams),
+        error: None,
+    }
+}
+
+#[query]
+fn get_fantasy_team(team_id: String) -> FantasyTeamResponse {
+    let team = FANTASY_TEAMS.with(|teams| teams.borrow().get(&team_id));
+    
+    match team {
+        Some(t) => FantasyTeamResponse {
+            success: true,
+            data: Some(t),
+            error: None,
+            violations: None,
+        },
+        None => FantasyTeamResponse {
+            success: false,
+            data: None,
+            error: Some("Fantasy team not found".to_string()),
+            violations: None,
+        },
+    }
+}
+
+#[update(guard = "is_operator")]
+fn update_player_playing_status(player_id: String, is_playing: bool) -> PlayerResponse {
+    let mut player = PLAYERS.with(|players| players.borrow().get(&player_id));
+    
+    if let Some(ref mut p) = player {
+        p.is_playing = is_playing;
+        
+        PLAYERS.with(|players| {
+            players.borrow_mut().insert(player_id, p.clone());
+        });
+        
+        PlayerResponse {
+            success: true,
+            data: Some(p.clone()),
+            error: None,
+        }
+    } else {
+        PlayerResponse {
+            success: false,
+            data: None,
+            error: Some("Player not found".to_string()),
+        }
+    }
+}
+
+#[query]
+fn get_all_players() -> Vec<Player> {
+    PLAYERS.with(|players| {
+        players.borrow().iter().map(|(_, player)| player).collect()
+    })
+}
+
+fn get_next_id(id_ref: &'static IdCounter) -> u64 {
+    id_ref.with(|id| {
+        let mut id = id.borrow_mut();
+        let stable_cell = id.as_mut().unwrap();
+        let current_id = *stable_cell.get();
+        stable_cell.set(current_id + 1).unwrap();
+        current_id
+    })
+} 
+
+#[cfg(test)]
+mod test_env {
+    use super::*;
+    use std::cell::Cell;
+    
+    thread_local! {
+        pub static TIME: Cell<u64> = const { Cell::new(0) };
+        pub static CALLER: Cell<Principal> = const { Cell::new(Principal::anonymous()) };
+        // Delay of the armed timer, if any
+        pub static TIMER: Cell<Option<Duration>> = const { Cell::new(None) };
+    }
+    
+    pub fn time() -> u64 {
+        TIME.with(Cell::get)
+    }
+    
+    pub fn caller() -> Principal {
+        CALLER.with(Cell::get)
+    }
+    
+    pub fn set_timer(delay: Duration, _func: impl FnOnce() + 'static) -> TimerId {
+        TIMER.with(|timer| timer.set(Some(delay)));
+        TimerId::default()
+    }
+    
+    pub fn clear_timer(_timer_id: TimerId) {
+        TIMER.with(|timer| timer.set(None));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    // --- Withdrawal limits ---
+    fn limits() -> WithdrawalLimits {
+        WithdrawalLimits {
+            per_transaction: 1_000,
+            per_day: 2_500,
+            approval_threshold: 500,
+        }
+    }
+    
+    #[test]
+    fn withdrawal_requires_verified_kyc() {
+        for status in [KYCStatus::Pending, KYCStatus::Rejected] {
+            assert!(withdrawal_decision(&status, 100, 0, &limits()).is_err());
+        }
+        assert!(withdrawal_decision(&KYCStatus::Verified, 100, 0, &limits()).is_ok());
+    }
+    
+    #[test]
+    fn withdrawal_below_threshold_is_processed_straight_away() {
+        let status = withdrawal_decision(&KYCStatus::Verified, 499, 0, &limits());
+        assert_eq!(status, Ok(WithdrawalStatus::Processing));
+    }
+    
+    #[test]
+    fn withdrawal_at_or_over_threshold_waits_for_approval() {
+        for amount in [500, 1_000] {
+            let status = withdrawal_decision(&KYCStatus::Verified, amount, 0, &limits());
+            assert_eq!(status, Ok(WithdrawalStatus::PendingApproval));
+        }
+    }
+    
+    #[test]
+    fn withdrawal_over_per_transaction_limit_is_rejected() {
+        assert!(withdrawal_decision(&KYCStatus::Verified, 1_001, 0, &limits()).is_err());
+        assert!(withdrawal_decision(&KYCStatus::Verified, 0, 0, &limits()).is_err());
+    }
+    
+    #[test]
+    fn withdrawal_over_daily_cap_is_rejected() {
+        assert!(withdrawal_decision(&KYCStatus::Verified, 500, 2_000, &limits()).is_ok());
+        assert!(withdrawal_decision(&KYCStatus::Verified, 501, 2_000, &limits()).is_err());
+        assert!(withdrawal_decision(&KYCStatus::Verified, 1, 2_500, &limits()).is_err());
+    }
+    
+    // --- Prize splitting ---
+    fn tier(rank: u32, rank_end: Option<u32>, percentage: f64) -> PrizeDistribution {
+        PrizeDistribution {
+            rank,
+            rank_end,
+            percentage,
+            amount: 0,
+        }
+    }
+    
+    // 1003 tokens over ranks 1..=5: 501, 200, 200, 50 and 50, leaving 2 tokens of dust
+    fn prize_pool() -> PrizePool {
+        let distribution = vec![
+            tier(4, Some(5), 5.0),
+            tier(1, None, 50.0),
+            tier(2, Some(3), 20.0),
+        ];
+        
+        PrizePool {
+            contest_id: "1".to_string(),
+            total_amount: 1_003,
+            distribution: resolve_prize_distribution(1_003, distribution).unwrap(),
+            distributed: false,
+            rake: None,
+        }
+    }
+    
+    #[test]
+    fn prize_tiers_resolve_per_rank_amounts() {
+        let pool = prize_pool();
+        let amounts: Vec<u64> = (1..=6).map(|rank| prize_for_rank(&pool, rank)).collect();
+        
+        assert_eq!(amounts, vec![501, 200, 200, 50, 50, 0]);
+        assert_eq!(prize_pool_allocated(&pool.distribution), 1_001);
+    }
+    
+    #[test]
+    fn prize_tiers_must_not_overlap_or_leave_gaps_in_the_total() {
+        let overlapping = vec![tier(1, Some(2), 50.0), tier(2, None, 50.0)];
+        assert!(resolve_prize_distribution(1_000, overlapping).is_err());
+        
+        let inverted = vec![tier(2, Some(1), 50.0), tier(1, None, 50.0)];
+        assert!(resolve_prize_distribution(1_000, inverted).is_err());
+        
+        let short = vec![tier(1, None, 50.0), tier(2, Some(3), 20.0)];
+        assert!(resolve_prize_distribution(1_000, short).is_err());
+        
+        assert!(resolve_prize_distribution(1_000, Vec::new()).is_err());
+    }
+    
+    #[test]
+    fn rounding_dust_goes_to_rank_one() {
+        let shares = split_prizes_by_group(&prize_pool(), &[1, 1, 1, 1, 1, 1]);
+        
+        assert_eq!(shares, vec![vec![503], vec![200], vec![200], vec![50], vec![50], vec![0]]);
+        assert_eq!(shares.iter().flatten().sum::<u64>(), 1_003);
+    }
+    
+    #[test]
+    fn tied_group_splits_the_positions_it_occupies() {
+        // Ranks 3 and 4 tie across the 20% and 5% tiers
+        let shares = split_prizes_by_group(&prize_pool(), &[1, 1, 2, 1]);
+        assert_eq!(shares, vec![vec![503], vec![200], vec![125, 125], vec![50]]);
+        
+        // The odd token of an uneven split goes to the earliest member
+        let shares = split_prizes_by_group(&prize_pool(), &[2, 3]);
+        assert_eq!(shares, vec![vec![352, 351], vec![100, 100, 100]]);
+    }
+    
+    // --- Ranking ---
+    fn contest(ranking: RankingConfig) -> Contest {
+        Contest {
+            id: "1".to_string(),
+            name: "Test contest".to_string(),
+            match_id: "match-1".to_string(),
+            entry_fee: 100,
+            total_spots: 10,
+            filled_spots: 0,
+            prize_pool: 1_000,
+            contest_type: ContestType::MultiPlayer,
+            status: ContestStatus::Live,
+            created_at: 0,
+            start_time: 0,
+            scoring_rule_set_id: Some("rules-1".to_string()),
+            ranking: Some(ranking),
+            min_entries: None,
+            max_entries_per_user: None,
+            creator: None,
+            visibility: None,
+        }
+    }
+    
+    fn entry(id: u64, points: f64, created_at: u64, captain_id: &str) -> ContestEntry {
+        ContestEntry {
+            id: id.to_string(),
+            contest_id: "1".to_string(),
+            user_id: Principal::anonymous(),
+            team: FantasyTeam {
+                id: format!("team-{}", id),
+                name: format!("Team {}", id),
+                match_id: Some("match-1".to_string()),
+                captain_id: captain_id.to_string(),
+                vice_captain_id: "vice".to_string(),
+                players: vec![captain_id.to_string()],
+                total_points: points,
+                total_price: 0,
+            },
+            points,
+            rank: None,
+            prize: None,
+            created_at,
+            refunded_at: None,
+        }
+    }
+    
+    fn ranked_ids(groups: &[Vec<ContestEntry>]) -> Vec<Vec<(String, Option<u32>)>> {
+        groups
+            .iter()
+            .map(|group| group.iter().map(|entry| (entry.id.clone(), entry.rank)).collect())
+            .collect()
+    }
+    
+    fn ids(groups: &[(&str, u32)]) -> Vec<(String, Option<u32>)> {
+        groups.iter().map(|(id, rank)| (id.to_string(), Some(*rank))).collect()
+    }
+    
+    #[test]
+    fn ties_without_tie_breakers_share_a_rank() {
+        let standard = contest(RankingConfig::default());
+        let entries = vec![entry(1, 50.0, 10, "c1"), entry(2, 80.0, 20, "c2"), entry(3, 50.0, 5, "c3"), entry(4, 40.0, 1, "c4")];
+        let groups = rank_entries(&standard, entries.clone());
+        
+        // Tied entries stay in entry order and the next entry skips their ranks
+        assert_eq!(ranked_ids(&groups), vec![ids(&[("2", 1)]), ids(&[("3", 2), ("1", 2)]), ids(&[("4", 4)])]);
+        
+        let dense = contest(RankingConfig {
+            method: RankingMethod::Dense,
+            tie_breakers: Vec::new(),
+        });
+        let groups = rank_entries(&dense, entries);
+        assert_eq!(ranked_ids(&groups), vec![ids(&[("2", 1)]), ids(&[("3", 2), ("1", 2)]), ids(&[("4", 3)])]);
+    }
+    
+    #[test]
+    fn earliest_entry_breaks_ties() {
+        let contest = contest(RankingConfig {
+            method: RankingMethod::Standard,
+            tie_breakers: vec![TieBreaker::EarliestEntry],
+        });
+        let entries = vec![entry(1, 50.0, 30, "c1"), entry(2, 50.0, 10, "c2"), entry(3, 50.0, 20, "c3"), entry(4, 50.0, 20, "c4")];
+        let groups = rank_entries(&contest, entries);
+        
+        assert_eq!(ranked_ids(&groups), vec![ids(&[("2", 1)]), ids(&[("3", 2), ("4", 2)]), ids(&[("1", 4)])]);
+    }
+    
+    #[test]
+    fn captain_points_break_ties() {
+        SCORING_RULE_SETS.with(|rule_sets| {
+            rule_sets.borrow_mut().insert(
+                "rules-1".to_string(),
+                ScoringRuleSet {
+                    id: "rules-1".to_string(),
+                    sport: Sport::Cricket,
+                    version: 1,
+                    rules: vec![ScoringRule {
+                        action: "run".to_string(),
+                        points: 1.0,
+                        sport: Sport::Cricket,
+                        condition: None,
+                    }],
+                    created_at: 0,
+                },
+            );
+        });
+        
+        for (captain_id, runs) in [("c1", 10), ("c2", 30), ("c3", 30)] {
+            PLAYER_SCORES.with(|scores| {
+                scores.borrow_mut().insert(
+                    format!("{}:match-1", captain_id),
+                    PlayerScore {
+                        player_id: captain_id.to_string(),
+                        match_id: "match-1".to_string(),
+                        points: runs as f64,
+                        stats: PlayerStats {
+                            runs: Some(runs),
+                            ..Default::default()
+                        },
+                        updated_at: 0,
+                    },
+                );
+            });
+        }
+        
+        let contest = contest(RankingConfig {
+            method: RankingMethod::Standard,
+            tie_breakers: vec![TieBreaker::CaptainPoints, TieBreaker::EarliestEntry],
+        });
+        // The captain of entry 4 has no score and counts as zero
+        let entries = vec![entry(1, 50.0, 1, "c1"), entry(2, 50.0, 3, "c2"), entry(3, 50.0, 2, "c3"), entry(4, 50.0, 0, "c4")];
+        let groups = rank_entries(&contest, entries);
+        
+        assert_eq!(
+            ranked_ids(&groups),
+            vec![ids(&[("3", 1)]), ids(&[("2", 2)]), ids(&[("1", 3)]), ids(&[("4", 4)])]
+        );
+    }
+    
+    #[test]
+    fn contests_are_indexed_by_match() {
+        let first = contest(RankingConfig::default());
+        let second = Contest {
+            id: "2".to_string(),
+            ..contest(RankingConfig::default())
+        };
+        let other_match = Contest {
+            id: "3".to_string(),
+            match_id: "match-2".to_string(),
+            ..contest(RankingConfig::default())
+        };
+        
+        CONTESTS.with(|contests| {
+            let mut contests = contests.borrow_mut();
+            for contest in [&first, &second, &other_match] {
+                contests.insert(contest.id.clone(), contest.clone());
+            }
+        });
+        
+        // Contests that predate the index are picked up once, later ones as they are created
+        index_contests_by_match();
+        add_contest_to_match("match-1", "2");
+        
+        let contest_ids = |match_id: &str| -> Vec<String> {
+            match_contests(match_id).into_iter().map(|contest| contest.id).collect()
+        };
+        assert_eq!(contest_ids("match-1"), vec!["1", "2"]);
+        assert_eq!(contest_ids("match-2"), vec!["3"]);
+        
+        discard_contest("1");
+        assert_eq!(contest_ids("match-1"), vec!["2"]);
+    }
+    
+    #[test]
+    fn rescoring_keeps_points_on_each_entry() {
+        for (rule_set_id, points_per_run) in [("rules-1", 1.0), ("rules-2", 3.0)] {
+            SCORING_RULE_SETS.with(|rule_sets| {
+                rule_sets.borrow_mut().insert(
+                    rule_set_id.to_string(),
+                    ScoringRuleSet {
+                        id: rule_set_id.to_string(),
+                        sport: Sport::Cricket,
+                        version: 1,
+                        rules: vec![ScoringRule {
+                            action: "run".to_string(),
+                            points: points_per_run,
+                            sport: Sport::Cricket,
+                            condition: None,
+                        }],
+                        created_at: 0,
+                    },
+                );
+            });
+        }
+        PLAYER_SCORES.with(|scores| {
+            scores.borrow_mut().insert(
+                "c1:match-1".to_string(),
+                PlayerScore {
+                    player_id: "c1".to_string(),
+                    match_id: "match-1".to_string(),
+                    points: 10.0,
+                    stats: PlayerStats {
+                        runs: Some(10),
+                        ..Default::default()
+                    },
+                    updated_at: 0,
+                },
+            );
+        });
+        
+        // The same team entered in two contests scored under different rules
+        let first = contest(RankingConfig::default());
+        let second = Contest {
+            id: "2".to_string(),
+            scoring_rule_set_id: Some("rules-2".to_string()),
+            ..contest(RankingConfig::default())
+        };
+        let team = entry(1, 0.0, 0, "c1").team;
+        FANTASY_TEAMS.with(|teams| {
+            teams.borrow_mut().insert(team.id.clone(), team.clone());
+        });
+        for (entry_id, contest) in [(1, &first), (2, &second)] {
+            let entry = ContestEntry {
+                contest_id: contest.id.clone(),
+                team: team.clone(),
+                ..entry(entry_id, 0.0, 0, "c1")
+            };
+            CONTEST_ENTRIES.with(|entries| {
+                entries.borrow_mut().insert(entry.id.clone(), entry.clone());
+            });
+            CONTEST_ENTRIES_BY_CONTEST.with(|contest_entries| {
+                contest_entries.borrow_mut().insert(contest.id.clone(), StorableVec(vec![entry.id]));
+            });
+        }
+        
+        assert_eq!(rescore_contest_entries(&first)[0].points, 20.0);
+        assert_eq!(rescore_contest_entries(&second)[0].points, 60.0);
+        
+        // Rescoring the second contest leaves the first contest's entry alone
+        assert_eq!(get_contest_entries("1".to_string())[0].points, 20.0);
+        let stored_team = FANTASY_TEAMS.with(|teams| teams.borrow().get(&team.id)).unwrap();
+        assert_eq!(stored_team.total_points, 0.0);
+    }
+    
+    #[test]
+    fn finalized_results_can_be_recomputed() {
+        SCORING_RULE_SETS.with(|rule_sets| {
+            rule_sets.borrow_mut().insert(
+                "rules-1".to_string(),
+                ScoringRuleSet {
+                    id: "rules-1".to_string(),
+                    sport: Sport::Cricket,
+                    version: 1,
+                    rules: vec![ScoringRule {
+                        action: "run".to_string(),
+                        points: 1.0,
+                        sport: Sport::Cricket,
+                        condition: None,
+                    }],
+                    created_at: 0,
+                },
+            );
+        });
+        
+        let mut contest = contest(RankingConfig::default());
+        let mut entry_ids = Vec::new();
+        for (id, captain_id, runs) in [(1, "c1", 30), (2, "c2", 50), (3, "c3", 30)] {
+            PLAYER_SCORES.with(|scores| {
+                scores.borrow_mut().insert(
+                    format!("{}:match-1", captain_id),
+                    PlayerScore {
+                        player_id: captain_id.to_string(),
+                        match_id: "match-1".to_string(),
+                        points: runs as f64,
+                        stats: PlayerStats {
+                            runs: Some(runs),
+                            ..Default::default()
+                        },
+                        updated_at: 0,
+                    },
+                );
+            });
+            let entry = entry(id, 0.0, id, captain_id);
+            CONTEST_ENTRIES.with(|entries| {
+                entries.borrow_mut().insert(entry.id.clone(), entry.clone());
+            });
+            entry_ids.push(entry.id);
+        }
+        CONTEST_ENTRIES_BY_CONTEST.with(|contest_entries| {
+            contest_entries.borrow_mut().insert(contest.id.clone(), StorableVec(entry_ids));
+        });
+        
+        // Store the results the way finalization does
+        let finalized: Vec<ContestEntry> = rank_entries(&contest, rescore_contest_entries(&contest))
+            .into_iter()
+            .flatten()
+            .collect();
+        CONTEST_ENTRIES.with(|entries| {
+            for entry in &finalized {
+                entries.borrow_mut().insert(entry.id.clone(), entry.clone());
+            }
+        });
+        
+        let results = |entries: Vec<ContestEntry>| -> Vec<(String, f64, Option<u32>)> {
+            entries.into_iter().map(|entry| (entry.id, entry.points, entry.rank)).collect()
+        };
+        
+        // Only finalized contests can be recomputed
+        CONTESTS.with(|contests| {
+            contests.borrow_mut().insert(contest.id.clone(), contest.clone());
+        });
+        assert!(!recompute_contest_results(contest.id.clone()).success);
+        
+        contest.status = ContestStatus::Completed;
+        CONTESTS.with(|contests| {
+            contests.borrow_mut().insert(contest.id.clone(), contest.clone());
+        });
+        
+        let recomputed = recompute_contest_results(contest.id.clone()).data.unwrap();
+        assert_eq!(results(recomputed), results(finalized.clone()));
+        assert_eq!(
+            results(finalized),
+            vec![
+                ("2".to_string(), 100.0, Some(1)),
+                ("1".to_string(), 60.0, Some(2)),
+                ("3".to_string(), 60.0, Some(2)),
+            ]
+        );
+    }
+    
+    // --- Contest lifecycle ---
+    #[test]
+    fn contests_only_move_forward() {
+        use ContestStatus::*;
+        
+        let allowed = [
+            (Open, Full),
+            (Full, Open),
+            (Open, Live),
+            (Full, Live),
+            (Live, Completed),
+            (Open, Cancelled),
+            (Full, Cancelled),
+            (Live, Cancelled),
+        ];
+        let statuses = [Open, Full, Live, Completed, Cancelled];
+        
+        for from in &statuses {
+            for to in &statuses {
+                let expected = allowed.iter().any(|(a, b)| a == from && b == to);
+                assert_eq!(contest_transition_allowed(from, to), expected, "{:?} -> {:?}", from, to);
+            }
+        }
+    }
+    
+    #[test]
+    fn operators_cannot_complete_or_reopen_contests() {
+        let mut contest = contest(RankingConfig::default());
+        contest.status = ContestStatus::Completed;
+        CONTESTS.with(|contests| {
+            contests.borrow_mut().insert(contest.id.clone(), contest.clone());
+        });
+        
+        for status in [ContestStatus::Open, ContestStatus::Full, ContestStatus::Live, ContestStatus::Completed, ContestStatus::Cancelled] {
+            assert!(!update_contest_status(contest.id.clone(), status).success);
+        }
+        let stored = CONTESTS.with(|contests| contests.borrow().get(&contest.id)).unwrap();
+        assert_eq!(stored.status, ContestStatus::Completed);
+    }
+    
+    #[test]
+    fn finalizing_needs_a_live_contest_on_a_settled_match() {
+        let mut match_obj = Match {
+            id: "match-1".to_string(),
+            tournament_id: "t1".to_string(),
+            team1_id: "a".to_string(),
+            team2_id: "b".to_string(),
+            start_time: 0,
+            status: MatchStatus::Live,
+            score: None,
+        };
+        let store_match = |match_obj: &Match| {
+            MATCHES.with(|matches| {
+                matches.borrow_mut().insert(match_obj.id.clone(), match_obj.clone());
+            });
+        };
+        let mut contest = contest(RankingConfig::default());
+        contest.status = ContestStatus::Open;
+        CONTESTS.with(|contests| {
+            contests.borrow_mut().insert(contest.id.clone(), contest.clone());
+        });
+        store_match(&match_obj);
+        
+        let error = finalize_contest(contest.id.clone()).error;
+        assert_eq!(error.as_deref(), Some("Contest has not started"));
+        
+        contest.status = ContestStatus::Live;
+        CONTESTS.with(|contests| {
+            contests.borrow_mut().insert(contest.id.clone(), contest.clone());
+        });
+        let error = finalize_contest(contest.id.clone()).error;
+        assert_eq!(error.as_deref(), Some("Match has not been completed and scored"));
+        
+        // Completed but without a final score is not enough
+        match_obj.status = MatchStatus::Completed;
+        store_match(&match_obj);
+        assert!(!is_match_settled(&match_obj));
+        let error = finalize_contest(contest.id.clone()).error;
+        assert_eq!(error.as_deref(), Some("Match has not been completed and scored"));
+        
+        MATCH_SCORES.with(|match_scores| {
+            match_scores.borrow_mut().insert(
+                "match-1".to_string(),
+                MatchScore {
+                    team1_score: "180/6".to_string(),
+                    team2_score: "176/9".to_string(),
+                    team1_overs: Some(20.0),
+                    team2_overs: Some(20.0),
+                },
+            );
+        });
+        assert!(is_match_settled(&match_obj));
+    }
+    
+    // --- Team composition ---
+    #[test]
+    fn team_composition_violations_are_itemised() {
+        use CompositionRule::*;
+        use PlayerPosition::{Batsman, Bowler};
+        
+        let players = [
+            ("p1", "A", Batsman, 10),
+            ("p2", "A", Batsman, 10),
+            ("p3", "B", Bowler, 5),
+            ("p4", "A", Bowler, 5),
+            ("p5", "A", Batsman, 30),
+            ("p6", "C", Batsman, 5),
+            ("p7", "B", Bowler, 5),
+            ("p8", "B", Batsman, 5),
+            ("p9", "B", Batsman, 5),
+            ("p10", "A", Bowler, 5),
+        ];
+        PLAYERS.with(|stored| {
+            for (id, team_id, position, price) in players {
+                stored.borrow_mut().insert(
+                    id.to_string(),
+                    Player {
+                        id: id.to_string(),
+                        name: id.to_string(),
+                        team_id: team_id.to_string(),
+                        position,
+                        points: 0.0,
+                        price,
+                        is_playing: true,
+                    },
+                );
+            }
+        });
+        let rules = TeamCompositionRules {
+            sport: Sport::Cricket,
+            min_squad_size: 3,
+            max_squad_size: 5,
+            position_limits: vec![position_limit(Batsman, 1, 3), position_limit(Bowler, 1, 3)],
+            max_players_per_team: 3,
+            min_credits: 10,
+            max_credits: 35,
+        };
+        let eligible = ["A".to_string(), "B".to_string()];
+        
+        let cases = [
+            ("valid team", vec!["p1", "p2", "p3", "p7"], ("p1", "p3"), vec![]),
+            ("too few players", vec!["p1", "p3"], ("p1", "p3"), vec![SquadSize]),
+            ("role minimum", vec!["p1", "p2", "p8"], ("p1", "p2"), vec![PositionLimit(Bowler)]),
+            ("role maximum", vec!["p1", "p2", "p8", "p9", "p3"], ("p1", "p3"), vec![PositionLimit(Batsman)]),
+            ("per-team cap", vec!["p1", "p2", "p4", "p10"], ("p1", "p4"), vec![MaxPlayersPerTeam]),
+            ("over budget", vec!["p5", "p3", "p7"], ("p5", "p3"), vec![CreditBudget]),
+            ("captain is vice-captain", vec!["p1", "p2", "p3", "p7"], ("p1", "p1"), vec![Captaincy]),
+            ("captain not picked", vec!["p1", "p2", "p3", "p7"], ("p8", "p1"), vec![Captaincy]),
+            ("duplicate player", vec!["p1", "p1", "p3"], ("p1", "p3"), vec![DuplicatePlayer]),
+            ("unknown player", vec!["p1", "ghost", "p3"], ("p1", "p3"), vec![UnknownPlayer]),
+            ("player not in the match", vec!["p1", "p6", "p3"], ("p1", "p3"), vec![PlayerNotInMatch]),
+        ];
+        
+        for (name, player_ids, (captain_id, vice_captain_id), expected) in cases {
+            let player_ids: Vec<String> = player_ids.into_iter().map(String::from).collect();
+            let result = validate_fantasy_team(&eligible, &rules, captain_id, vice_captain_id, &player_ids);
+            let violations: Vec<CompositionRule> = match result {
+                Ok(_) => Vec::new(),
+                Err(violations) => violations.into_iter().map(|violation| violation.rule).collect(),
+            };
+            assert_eq!(violations, expected, "{}", name);
+        }
+        
+        let valid = ["p1", "p2", "p3", "p7"].map(String::from);
+        assert_eq!(validate_fantasy_team(&eligible, &rules, "p1", "p3", &valid).ok(), Some(30));
+    }
+    
+    // --- Match locking ---
+    const SECOND: u64 = 1_000_000_000;
+    
+    fn scheduled_match(id: &str, start_time: u64) -> Match {
+        let match_obj = Match {
+            id: id.to_string(),
+            tournament_id: "t1".to_string(),
+            team1_id: "a".to_string(),
+            team2_id: "b".to_string(),
+            start_time,
+            status: MatchStatus::Scheduled,
+            score: None,
+        };
+        MATCHES.with(|matches| {
+            matches.borrow_mut().insert(match_obj.id.clone(), match_obj.clone());
+        });
+        match_obj
+    }
+    
+    fn set_time(now: u64) {
+        test_env::TIME.with(|time| time.set(now));
+    }
+    
+    #[test]
+    fn match_locks_at_start_time_minus_buffer() {
+        scheduled_match("match-1", 1_000 * SECOND);
+        set_lock_buffer(300);
+        let lock_time = 700 * SECOND;
+        
+        assert_eq!(get_lock_time("match-1".to_string()).data, Some(lock_time));
+        
+        set_time(lock_time - 1);
+        assert!(!is_match_locked("match-1"));
+        set_time(lock_time);
+        assert!(is_match_locked("match-1"));
+        set_time(lock_time + 1);
+        assert!(is_match_locked("match-1"));
+        
+        // Without a buffer the match locks at its start time
+        set_lock_buffer(0);
+        set_time(lock_time);
+        assert!(!is_match_locked("match-1"));
+        set_time(1_000 * SECOND);
+        assert!(is_match_locked("match-1"));
+        
+        // Matches that have left the Scheduled state, or are unknown, are locked
+        set_time(0);
+        assert!(is_match_locked("match-2"));
+        MATCHES.with(|matches| {
+            let mut match_obj = matches.borrow().get(&"match-1".to_string()).unwrap();
+            match_obj.status = MatchStatus::Live;
+            matches.borrow_mut().insert(match_obj.id.clone(), match_obj);
+        });
+        assert!(is_match_locked("match-1"));
+    }
+    
+    #[test]
+    fn lock_buffer_change_reschedules_the_timer() {
+        let match_obj = scheduled_match("match-1", 1_000 * SECOND);
+        let user_id = user(1);
+        MATCHMAKING_QUEUE.with(|queue| {
+            queue.borrow_mut().insert(
+                matchmaking_key(&match_obj.id, 100, &user_id),
+                MatchmakingTicket {
+                    user_id,
+                    match_id: match_obj.id.clone(),
+                    fantasy_team_id: "team-1".to_string(),
+                    stake: 100,
+                    created_at: 0,
+                    refund_failed: None,
+                },
+            );
+        });
+        set_time(100 * SECOND);
+        
+        schedule_lifecycle_timer();
+        let armed = || test_env::TIMER.with(|timer| timer.get());
+        assert_eq!(armed(), Some(Duration::from_secs(900)));
+        
+        // Queued stakes are refunded at the lock, which now comes earlier
+        set_lock_buffer(300);
+        assert_eq!(armed(), Some(Duration::from_secs(600)));
+        
+        set_lock_buffer(0);
+        assert_eq!(armed(), Some(Duration::from_secs(900)));
+    }
+    
+    // --- Refunds and prize retries ---
+    fn user(id: u8) -> Principal {
+        Principal::from_slice(&[id])
+    }
+    
+    fn register(user_id: Principal) {
+        USERS.with(|users| {
+            users.borrow_mut().insert(
+                user_id,
+                UserProfile {
+                    id: user_id,
+                    username: user_id.to_text(),
+                    email: None,
+                    phone: None,
+                    kyc_status: KYCStatus::Verified,
+                    balance: 0,
+                    created_at: 0,
+                    updated_at: 0,
+                    deposit_subaccount: None,
+                },
+            );
+        });
+    }
+    
+    // Registers a user and funds their wallet from the promo pool
+    fn funded_user(id: u8, amount: u64) -> Principal {
+        init_id_counters();
+        let user_id = user(id);
+        register(user_id);
+        post_transfer(LedgerAccount::Promo, LedgerAccount::UserWallet(user_id), amount, TransactionType::Bonus).unwrap();
+        user_id
+    }
+    
+    fn wallet(user_id: Principal) -> i128 {
+        account_balance(&LedgerAccount::UserWallet(user_id))
+    }
+    
+    fn escrow(id: &str) -> i128 {
+        account_balance(&LedgerAccount::ContestEscrow(id.to_string()))
+    }
+    
+    // Stores a contest entry for the user and moves the entry fee into escrow
+    fn enter(contest: &Contest, entry_id: u64, user_id: Principal) -> ContestEntry {
+        let entry = ContestEntry {
+            user_id,
+            contest_id: contest.id.clone(),
+            ..entry(entry_id, 0.0, entry_id, &format!("c{}", entry_id))
+        };
+        post_transfer(
+            LedgerAccount::UserWallet(user_id),
+            LedgerAccount::ContestEscrow(contest.id.clone()),
+            contest.entry_fee,
+            TransactionType::ContestEntry,
+        )
+        .unwrap();
+        CONTEST_ENTRIES.with(|entries| {
+            entries.borrow_mut().insert(entry.id.clone(), entry.clone());
+        });
+        CONTEST_ENTRIES_BY_CONTEST.with(|contest_entries| {
+            let mut contest_entries = contest_entries.borrow_mut();
+            let mut entry_ids = contest_entries.get(&contest.id).unwrap_or_default();
+            entry_ids.0.push(entry.id.clone());
+            contest_entries.insert(contest.id.clone(), entry_ids);
+        });
+        entry
+    }
+    
+    fn transactions(user_id: Principal, transaction_type: TransactionType) -> Vec<TransactionStatus> {
+        REWARD_HISTORY.with(|history| {
+            history
+                .borrow()
+                .iter()
+                .map(|(_, transaction)| transaction)
+                .filter(|transaction| transaction.user_id == user_id && transaction.transaction_type == transaction_type)
+                .map(|transaction| transaction.status)
+                .collect()
+        })
+    }
+    
+    #[test]
+    fn failed_refunds_stay_outstanding_until_retried() {
+        let contest = contest(RankingConfig::default());
+        CONTESTS.with(|contests| {
+            contests.borrow_mut().insert(contest.id.clone(), contest.clone());
+        });
+        let first = funded_user(1, 500);
+        let second = funded_user(2, 500);
+        enter(&contest, 1, first);
+        enter(&contest, 2, second);
+        assert_eq!(escrow("1"), 200);
+        
+        // The second refund fails while the user's profile is missing
+        let profile = USERS.with(|users| users.borrow_mut().remove(&second)).unwrap();
+        let cancelled = cancel_contest_with_refunds(contest.clone(), "Match abandoned");
+        
+        assert_eq!(cancelled.status, ContestStatus::Cancelled);
+        assert_eq!(wallet(first), 500);
+        assert_eq!(escrow("1"), 100);
+        let refunded: Vec<bool> = get_contest_entries("1".to_string()).iter().map(|entry| entry.refunded_at.is_some()).collect();
+        assert_eq!(refunded, vec![true, false]);
+        assert_eq!(transactions(second, TransactionType::Refund), vec![TransactionStatus::Failed]);
+        
+        USERS.with(|users| users.borrow_mut().insert(second, profile));
+        assert!(retry_contest_refunds("1".to_string()).success);
+        
+        assert_eq!(wallet(second), 500);
+        assert_eq!(escrow("1"), 0);
+        assert!(get_contest_entries("1".to_string()).iter().all(|entry| entry.refunded_at.is_some()));
+        assert_eq!(
+            transactions(second, TransactionType::Refund),
+            vec![TransactionStatus::Failed, TransactionStatus::Completed]
+        );
+        
+        // Entries already refunded are not paid twice
+        assert!(retry_contest_refunds("1".to_string()).success);
+        assert_eq!(wallet(first), 500);
+        assert_eq!(escrow("1"), 0);
+    }
+    
+    #[test]
+    fn failed_prize_credits_can_be_retried() {
+        let contest = contest(RankingConfig::default());
+        let winner = funded_user(1, 500);
+        let runner_up = funded_user(2, 500);
+        for (rank, user_id) in [(1, winner), (2, runner_up)] {
+            let entry = ContestEntry {
+                rank: Some(rank),
+                ..enter(&contest, u64::from(rank), user_id)
+            };
+            CONTEST_ENTRIES.with(|entries| {
+                entries.borrow_mut().insert(entry.id.clone(), entry);
+            });
+        }
+        
+        let profile = USERS.with(|users| users.borrow_mut().remove(&winner)).unwrap();
+        assert!(!credit_prize(winner, "1", 1, 150));
+        assert!(credit_prize(runner_up, "1", 2, 50));
+        USERS.with(|users| users.borrow_mut().insert(winner, profile));
+        
+        let reward = USER_REWARDS.with(|rewards| rewards.borrow().get(&winner)).unwrap().0[0].clone();
+        assert_eq!(reward.status, RewardStatus::Failed);
+        assert_eq!(escrow("1"), 150);
+        
+        let retried = retry_reward(reward.id.clone());
+        assert!(retried.success);
+        assert_eq!(retried.data.unwrap().status, RewardStatus::Claimed);
+        assert_eq!(wallet(winner), 550);
+        assert_eq!(transactions(winner, TransactionType::ContestWin), vec![TransactionStatus::Completed]);
+        assert_eq!(get_contest_entries("1".to_string())[0].prize, Some(150));
+        
+        // Claimed rewards cannot be paid again
+        assert!(!retry_reward(reward.id).success);
+        assert_eq!(wallet(winner), 550);
+        assert_eq!(escrow("1"), 0);
+    }
+    
+    // --- Private contests ---
+    fn private_contest(id: &str, creator: Principal, status: ContestStatus) -> Contest {
+        let contest = Contest {
+            id: id.to_string(),
+            status,
+            creator: Some(creator),
+            visibility: Some(ContestVisibility::Private),
+            ..contest(RankingConfig::default())
+        };
+        CONTESTS.with(|contests| {
+            contests.borrow_mut().insert(contest.id.clone(), contest.clone());
+        });
+        contest
+    }
+    
+    // Stores a team picked for the match and links it to its owner
+    fn own_team(user_id: Principal, team_id: &str, match_id: &str) {
+        let team = FantasyTeam {
+            id: team_id.to_string(),
+            match_id: Some(match_id.to_string()),
+            ..entry(0, 0.0, 0, "c1").team
+        };
+        FANTASY_TEAMS.with(|teams| {
+            teams.borrow_mut().insert(team.id.clone(), team);
+        });
+        USER_FANTASY_TEAMS.with(|user_teams| {
+            let mut user_teams = user_teams.borrow_mut();
+            let mut team_ids = user_teams.get(&user_id).unwrap_or_default();
+            team_ids.0.push(team_id.to_string());
+            user_teams.insert(user_id, team_ids);
+        });
+    }
+    
+    #[test]
+    fn private_contest_slots_need_an_unlocked_match_and_a_free_slot() {
+        let cap = MAX_OPEN_PRIVATE_CONTESTS_PER_USER;
+        assert!(private_contest_slot_decision(false, 0).is_ok());
+        assert!(private_contest_slot_decision(false, cap - 1).is_ok());
+        assert_eq!(
+            private_contest_slot_decision(false, cap),
+            Err(format!("You cannot have more than {} open private contests", cap))
+        );
+        assert_eq!(
+            private_contest_slot_decision(true, 0),
+            Err("Match is locked for new contests".to_string())
+        );
+    }
+    
+    #[test]
+    fn only_open_private_contests_count_towards_the_cap() {
+        scheduled_match("match-1", 1_000 * SECOND);
+        let creator = user(1);
+        for id in 0..MAX_OPEN_PRIVATE_CONTESTS_PER_USER - 1 {
+            let status = if id % 2 == 0 { ContestStatus::Open } else { ContestStatus::Full };
+            private_contest(&format!("p{}", id), creator, status);
+        }
+        // Finished, public and other users' contests do not take a slot
+        private_contest("done", creator, ContestStatus::Completed);
+        private_contest("cancelled", creator, ContestStatus::Cancelled);
+        private_contest("other", user(2), ContestStatus::Open);
+        CONTESTS.with(|contests| {
+            let public = Contest { creator: Some(creator), ..contest(RankingConfig::default()) };
+            contests.borrow_mut().insert("public".to_string(), public);
+        });
+        
+        assert_eq!(open_private_contest_count(creator), MAX_OPEN_PRIVATE_CONTESTS_PER_USER - 1);
+        assert!(check_private_contest_slot(creator, "match-1").is_ok());
+        
+        private_contest("last", creator, ContestStatus::Open);
+        assert!(check_private_contest_slot(creator, "match-1").is_err());
+        assert!(check_private_contest_slot(user(2), "match-1").is_ok());
+    }
+    
+    #[test]
+    fn private_contest_slot_is_refused_once_the_match_locks() {
+        // Creation checks the slot again after waiting for randomness, by
+        // which time the match may have locked
+        scheduled_match("match-1", 1_000 * SECOND);
+        set_lock_buffer(300);
+        let creator = user(1);
+        
+        set_time(699 * SECOND);
+        assert!(check_private_contest_slot(creator, "match-1").is_ok());
+        set_time(700 * SECOND);
+        assert_eq!(
+            check_private_contest_slot(creator, "match-1"),
+            Err("Match is locked for new contests".to_string())
+        );
+    }
+    
+    #[test]
+    fn private_contests_are_joined_with_their_invite_code() {
+        scheduled_match("match-1", 1_000 * SECOND);
+        let player = funded_user(2, 500);
+        own_team(player, "team-2", "match-1");
+        let contest = private_contest("p1", user(1), ContestStatus::Open);
+        CONTEST_ENTRIES_BY_CONTEST.with(|contest_entries| {
+            contest_entries.borrow_mut().insert(contest.id.clone(), StorableVec::default());
+        });
+        INVITE_CODES.with(|codes| {
+            codes.borrow_mut().insert("ABC123".to_string(), contest.id.clone());
+        });
+        test_env::CALLER.with(|caller| caller.set(player));
+        
+        assert!(matches!(join_contest("p1".to_string(), "team-2".to_string()), Err(FantasyError::Unauthorized)));
+        assert!(matches!(
+            join_contest_by_code("XYZ999".to_string(), "team-2".to_string()),
+            Err(FantasyError::ContestNotFound)
+        ));
+        
+        // Codes are matched regardless of case and surrounding whitespace
+        let entry = join_contest_by_code(" abc123 ".to_string(), "team-2".to_string()).unwrap();
+        assert_eq!(entry.contest_id, "p1");
+        assert_eq!(entry.user_id, player);
+        assert_eq!(wallet(player), 400);
+        assert_eq!(escrow("p1"), 100);
+        assert_eq!(CONTESTS.with(|contests| contests.borrow().get(&"p1".to_string())).unwrap().filled_spots, 1);
+    }
+    
+    // --- Season league settlement ---
+    fn tournament(status: TournamentStatus) {
+        TOURNAMENTS.with(|tournaments| {
+            tournaments.borrow_mut().insert(
+                "t1".to_string(),
+                Tournament {
+                    id: "t1".to_string(),
+                    name: "Test tournament".to_string(),
+                    sport: Sport::Cricket,
+                    start_time: 0,
+                    end_time: 0,
+                    status,
+                    teams: Vec::new(),
+                    matches: Vec::new(),
+                },
+            );
+        });
+    }
+    
+    fn finished_tournament(match_ids: &[&str]) {
+        tournament(TournamentStatus::Completed);
+        for match_id in match_ids {
+            let match_obj = Match {
+                status: MatchStatus::Completed,
+                ..scheduled_match(match_id, 0)
+            };
+            MATCHES.with(|matches| {
+                matches.borrow_mut().insert(match_obj.id.clone(), match_obj);
+            });
+        }
+    }
+    
+    fn final_score() -> MatchScore {
+        MatchScore {
+            team1_score: "180/6".to_string(),
+            team2_score: "176/9".to_string(),
+            team1_overs: Some(20.0),
+            team2_overs: Some(20.0),
+        }
+    }
+    
+    // A ten-member league with 100 token entries whose tiers were resolved
+    // against a full pool, joined by the given users
+    fn season_league(percentages: &[f64], members: &[Principal]) -> SeasonLeague {
+        let distribution = percentages
+            .iter()
+            .enumerate()
+            .map(|(index, percentage)| PrizeDistribution {
+                amount: (1_000.0 * percentage / 100.0) as u64,
+                ..tier(index as u32 + 1, None, *percentage)
+            })
+            .collect();
+        let league = SeasonLeague {
+            id: "s1".to_string(),
+            name: "Test league".to_string(),
+            tournament_id: "t1".to_string(),
+            entry_fee: 100,
+            max_members: 10,
+            member_count: members.len() as u32,
+            transfers_per_matchday: 1,
+            distribution,
+            scoring_rule_set_id: None,
+            status: SeasonLeagueStatus::Open,
+            created_at: 0,
+        };
+        SEASON_LEAGUES.with(|leagues| {
+            leagues.borrow_mut().insert(league.id.clone(), league.clone());
+        });
+        for (index, user_id) in members.iter().enumerate() {
+            post_transfer(
+                LedgerAccount::UserWallet(*user_id),
+                LedgerAccount::ContestEscrow(league.id.clone()),
+                league.entry_fee,
+                TransactionType::ContestEntry,
+            )
+            .unwrap();
+            let entry = SeasonEntry {
+                id: season_entry_key(&league.id, user_id),
+                league_id: league.id.clone(),
+                user_id: *user_id,
+                team: entry(index as u64, 0.0, 0, "c1").team,
+                matchdays: Vec::new(),
+                total_points: 0.0,
+                transfer_window: None,
+                transfers_used: 0,
+                rank: None,
+                prize: None,
+                joined_at: index as u64,
+                refunded_at: None,
+            };
+            SEASON_ENTRIES.with(|entries| {
+                entries.borrow_mut().insert(entry.id.clone(), entry);
+            });
+        }
+        league
+    }
+    
+    fn league_status() -> SeasonLeagueStatus {
+        SEASON_LEAGUES.with(|leagues| leagues.borrow().get(&"s1".to_string())).unwrap().status
+    }
+    
+    #[test]
+    fn under_filled_league_pays_out_what_was_collected() {
+        finished_tournament(&["m1"]);
+        update_match_score("m1".to_string(), final_score());
+        set_platform_rake(10.0);
+        let members = [funded_user(1, 500), funded_user(2, 500), funded_user(3, 500)];
+        let league = season_league(&[50.0, 30.0, 20.0], &members);
+        
+        // 300 collected, 30 raked, and the 270 left is shared by the three
+        // members tied on points instead of the 1000 a full league would pay
+        let settled = settle_season_league(league).unwrap();
+        
+        assert_eq!(settled.status, SeasonLeagueStatus::Completed);
+        for user_id in members {
+            assert_eq!(wallet(user_id), 490);
+        }
+        assert_eq!(escrow("s1"), 0);
+        assert_eq!(account_balance(&LedgerAccount::House), 30);
+    }
+    
+    #[test]
+    fn league_waits_for_every_match_to_be_settled() {
+        finished_tournament(&["m1", "m2"]);
+        update_match_score("m1".to_string(), final_score());
+        let members = [funded_user(1, 500), funded_user(2, 500)];
+        season_league(&[100.0], &members);
+        
+        close_season_leagues("t1", &TournamentStatus::Completed);
+        assert_eq!(league_status(), SeasonLeagueStatus::Open);
+        assert_eq!(escrow("s1"), 200);
+        assert_eq!(
+            retry_season_league_settlement("s1".to_string()).error.as_deref(),
+            Some("Not every match of the tournament has been completed and scored")
+        );
+        
+        // Scoring the last match settles the league
+        update_match_score_data("m2".to_string(), final_score());
+        assert_eq!(league_status(), SeasonLeagueStatus::Completed);
+        assert_eq!(escrow("s1"), 0);
+        assert_eq!(wallet(members[0]) + wallet(members[1]), 1_000);
+    }
+    
+    #[test]
+    fn unresolvable_prize_tiers_leave_the_league_open() {
+        finished_tournament(&["m1"]);
+        update_match_score("m1".to_string(), final_score());
+        let members = [funded_user(1, 500), funded_user(2, 500)];
+        let league = season_league(&[50.0, 40.0], &members);
+        
+        assert_eq!(
+            settle_season_league(league).err(),
+            Some("Prize percentages add up to 90%, expected 100%".to_string())
+        );
+        assert_eq!(league_status(), SeasonLeagueStatus::Open);
+        assert_eq!(escrow("s1"), 200);
+        for user_id in members {
+            assert_eq!(wallet(user_id), 400);
+            assert!(USER_REWARDS.with(|rewards| rewards.borrow().get(&user_id)).is_none());
+        }
+    }
+    
+    // --- Matchmaking ---
+    // A funded user with a team for match-1, set as the caller
+    fn matchmaking_user(id: u8) -> Principal {
+        let user_id = funded_user(id, 500);
+        own_team(user_id, &format!("team-{}", id), "match-1");
+        user_id
+    }
+    
+    fn queue_as(user_id: Principal, stake: u64) -> Result<MatchmakingOutcome, FantasyError> {
+        test_env::CALLER.with(|caller| caller.set(user_id));
+        let team_id = format!("team-{}", user_id.as_slice()[0]);
+        queue_head_to_head("match-1".to_string(), team_id, stake)
+    }
+    
+    fn queued() -> Vec<(Principal, u64)> {
+        MATCHMAKING_QUEUE.with(|queue| queue.borrow().iter().map(|(_, ticket)| (ticket.user_id, ticket.stake)).collect())
+    }
+    
+    fn held_stakes() -> i128 {
+        account_balance(&LedgerAccount::MatchmakingEscrow("match-1".to_string()))
+    }
+    
+    #[test]
+    fn equal_stakes_are_paired_into_a_head_to_head() {
+        tournament(TournamentStatus::Live);
+        scheduled_match("match-1", 1_000 * SECOND);
+        let (first, second, third) = (matchmaking_user(1), matchmaking_user(2), matchmaking_user(3));
+        
+        assert!(matches!(queue_as(first, 100), Ok(MatchmakingOutcome::Queued(_))));
+        assert!(matches!(queue_as(first, 100), Err(FantasyError::AlreadyJoined)));
+        // A different stake waits for its own opponent
+        assert!(matches!(queue_as(third, 50), Ok(MatchmakingOutcome::Queued(_))));
+        assert_eq!(held_stakes(), 150);
+        
+        let Ok(MatchmakingOutcome::Matched(entry)) = queue_as(second, 100) else {
+            panic!("expected the two 100 token stakes to be paired");
+        };
+        
+        let contest = CONTESTS.with(|contests| contests.borrow().get(&entry.contest_id)).unwrap();
+        assert_eq!(contest.contest_type, ContestType::HeadToHead);
+        assert_eq!(contest.status, ContestStatus::Full);
+        let players: Vec<Principal> = get_contest_entries(contest.id.clone()).iter().map(|entry| entry.user_id).collect();
+        assert_eq!(players, vec![first, second]);
+        assert_eq!(escrow(&contest.id), 200);
+        assert_eq!((wallet(first), wallet(second)), (400, 400));
+        assert_eq!(held_stakes(), 50);
+        assert_eq!(queued(), vec![(third, 50)]);
+    }
+    
+    #[test]
+    fn unpaired_tickets_are_refunded_at_lock() {
+        tournament(TournamentStatus::Live);
+        scheduled_match("match-1", 1_000 * SECOND);
+        let first = matchmaking_user(1);
+        queue_as(first, 100).unwrap();
+        
+        // Nothing happens before the lock
+        set_time(999 * SECOND);
+        refund_unmatched_tickets();
+        assert_eq!(queued(), vec![(first, 100)]);
+        
+        set_time(1_000 * SECOND);
+        refund_unmatched_tickets();
+        assert!(queued().is_empty());
+        assert_eq!(wallet(first), 500);
+        assert_eq!(held_stakes(), 0);
+        assert_eq!(transactions(first, TransactionType::Refund), vec![TransactionStatus::Completed]);
+        
+        let second = matchmaking_user(2);
+        assert!(matches!(queue_as(second, 100), Err(FantasyError::ContestAlreadyStarted)));
+    }
+    
+    #[test]
+    fn failed_pairing_discards_the_contest_before_stakes_are_lost() {
+        tournament(TournamentStatus::Live);
+        scheduled_match("match-1", 1_000 * SECOND);
+        let (first, second) = (matchmaking_user(1), matchmaking_user(2));
+        queue_as(first, 100).unwrap();
+        
+        // A profile balance out of step with the ledger lets the caller past
+        // validation, but their payment into the new contest then fails
+        post_transfer(LedgerAccount::UserWallet(second), LedgerAccount::House, 500, TransactionType::HouseRevenue).unwrap();
+        USERS.with(|users| {
+            let mut profile = users.borrow().get(&second).unwrap();
+            profile.balance = 500;
+            users.borrow_mut().insert(second, profile);
+        });
+        
+        assert!(matches!(queue_as(second, 100), Err(FantasyError::InsufficientBalance)));
+        // The contest created for the pair is gone and the waiting stake is untouched
+        assert!(CONTESTS.with(|contests| contests.borrow().is_empty()));
+        assert!(match_contests("match-1").is_empty());
+        assert_eq!(wallet(second), 0);
+        assert_eq!(held_stakes(), 100);
+        assert_eq!(queued(), vec![(first, 100)]);
+    }
+    
+    // --- Global leaderboard ---
+    #[test]
+    fn global_leaderboard_is_ordered_and_paged() {
+        let users: Vec<Principal> = (1..=5).map(|id| funded_user(id, 500)).collect();
+        let live = contest(RankingConfig::default());
+        let cancelled = Contest {
+            id: "2".to_string(),
+            status: ContestStatus::Cancelled,
+            ..contest(RankingConfig::default())
+        };
+        // (contest, user, points, prize); cancelled contests do not count
+        let results = [
+            (&live, 0, 50.0, 0),
+            (&live, 1, 80.0, 100),
+            (&live, 2, 50.0, 0),
+            (&live, 3, 30.0, 300),
+            (&live, 4, 50.0, 0),
+            (&cancelled, 3, 100.0, 0),
+        ];
+        for (entry_id, (contest, user_index, points, prize)) in results.into_iter().enumerate() {
+            CONTESTS.with(|contests| {
+                contests.borrow_mut().insert(contest.id.clone(), contest.clone());
+            });
+            let entry = ContestEntry {
+                points,
+                prize: Some(prize),
+                ..enter(contest, entry_id as u64 + 1, users[user_index])
+            };
+            CONTEST_ENTRIES.with(|entries| {
+                entries.borrow_mut().insert(entry.id.clone(), entry);
+            });
+        }
+        // Season league points add to the same user's contest totals
+        season_league(&[100.0], &users[..1]);
+        SEASON_ENTRIES.with(|entries| {
+            let key = season_entry_key("s1", &users[0]);
+            let entry = entries.borrow().get(&key).unwrap();
+            entries.borrow_mut().insert(key, SeasonEntry { total_points: 30.0, ..entry });
+        });
+        
+        let page = |metric, offset, limit| {
+            let page = get_global_leaderboard(None, metric, offset, limit).data.unwrap();
+            let ranks: Vec<(u32, Principal, f64, Option<u64>)> = page
+                .entries
+                .into_iter()
+                .map(|entry| (entry.rank, entry.user_id, entry.points, entry.prize))
+                .collect();
+            (ranks, page.total)
+        };
+        
+        // Level on points, winnings decide; level on both, ranks are shared
+        let (by_points, total) = page(LeaderboardMetric::Points, 0, 10);
+        assert_eq!(total, 5);
+        assert_eq!(
+            by_points,
+            vec![
+                (1, users[1], 80.0, Some(100)),
+                (2, users[0], 80.0, Some(0)),
+                (3, users[2], 50.0, Some(0)),
+                (3, users[4], 50.0, Some(0)),
+                (5, users[3], 30.0, Some(300)),
+            ]
+        );
+        
+        let (by_winnings, _) = page(LeaderboardMetric::Winnings, 0, 10);
+        let order: Vec<(u32, Principal)> = by_winnings.into_iter().map(|(rank, user_id, _, _)| (rank, user_id)).collect();
+        assert_eq!(
+            order,
+            vec![(1, users[3]), (2, users[1]), (3, users[0]), (4, users[2]), (4, users[4])]
+        );
+        
+        // Pages keep the ranks of the full standings
+        assert_eq!(page(LeaderboardMetric::Points, 2, 2), (by_points[2..4].to_vec(), 5));
+        assert_eq!(page(LeaderboardMetric::Points, 5, 2), (Vec::new(), 5));
+    }
+    
+    // --- Ledger ---
+    fn ledger_len() -> u64 {
+        LEDGER.with(|ledger| ledger.borrow().len())
+    }
+    
+    fn profile_balance(user_id: Principal) -> u64 {
+        USERS.with(|users| users.borrow().get(&user_id)).unwrap().balance
+    }
+    
+    #[test]
+    fn every_transfer_nets_to_zero_and_is_mirrored_on_profiles() {
+        let first = funded_user(1, 500);
+        let second = funded_user(2, 200);
+        let transfers = [
+            (LedgerAccount::UserWallet(first), LedgerAccount::ContestEscrow("1".to_string()), 300),
+            (LedgerAccount::UserWallet(second), LedgerAccount::ContestEscrow("1".to_string()), 200),
+            (LedgerAccount::ContestEscrow("1".to_string()), LedgerAccount::House, 50),
+            (LedgerAccount::ContestEscrow("1".to_string()), LedgerAccount::UserWallet(second), 450),
+            (LedgerAccount::UserWallet(first), LedgerAccount::Treasury, 100),
+        ];
+        
+        for (from, to, amount) in transfers {
+            let before = (account_balance(&from), account_balance(&to));
+            post_transfer(from.clone(), to.clone(), amount, TransactionType::ContestEntry).unwrap();
+            assert_eq!(account_balance(&from), before.0 - i128::from(amount));
+            assert_eq!(account_balance(&to), before.1 + i128::from(amount));
+            
+            let audit = audit_ledger();
+            assert!(audit.balanced, "unbalanced after moving {} from {:?}", amount, from);
+            assert_eq!(audit.total_balance, 0);
+            for user_id in [first, second] {
+                assert_eq!(i128::from(profile_balance(user_id)), wallet(user_id));
+            }
+        }
+        
+        assert_eq!((wallet(first), wallet(second)), (100, 450));
+        assert_eq!(escrow("1"), 0);
+        assert_eq!(account_balance(&LedgerAccount::Promo), -700);
+        assert_eq!(ledger_len(), 7);
+    }
+    
+    #[test]
+    fn wallets_cannot_be_overdrawn() {
+        let user_id = funded_user(1, 100);
+        let entries = ledger_len();
+        let to_escrow = |amount| {
+            post_transfer(
+                LedgerAccount::UserWallet(user_id),
+                LedgerAccount::ContestEscrow("1".to_string()),
+                amount,
+                TransactionType::ContestEntry,
+            )
+        };
+        
+        assert_eq!(to_escrow(101), Err("Insufficient balance".to_string()));
+        assert_eq!((wallet(user_id), profile_balance(user_id), escrow("1")), (100, 100, 0));
+        assert_eq!(ledger_len(), entries);
+        
+        // Unknown wallets and transfers to the same account are refused too
+        let stranger = LedgerAccount::UserWallet(user(9));
+        assert!(post_transfer(LedgerAccount::Promo, stranger, 10, TransactionType::Bonus).is_err());
+        let own = LedgerAccount::UserWallet(user_id);
+        assert!(post_transfer(own.clone(), own, 10, TransactionType::Bonus).is_err());
+        assert_eq!(ledger_len(), entries);
+        
+        // The whole balance can be spent, and escrows may run a deficit
+        assert!(to_escrow(100).is_ok());
+        assert_eq!((wallet(user_id), profile_balance(user_id)), (0, 0));
+        assert!(post_transfer(LedgerAccount::ContestEscrow("2".to_string()), LedgerAccount::House, 50, TransactionType::Rake).is_ok());
+        assert_eq!(escrow("2"), -50);
+        assert!(audit_ledger().balanced);
+    }
+    
+    #[test]
+    fn claiming_a_bonus_completes_its_transaction() {
+        init_id_counters();
+        let user_id = user(1);
+        register(user_id);
+        
+        let reward = create_bonus_reward(user_id, 250, "Welcome".to_string()).data.unwrap();
+        assert_eq!(transactions(user_id, TransactionType::Bonus), vec![TransactionStatus::Pending]);
+        assert_eq!(wallet(user_id), 0);
+        
+        let claimed = claim_reward(reward.id.clone());
+        assert!(claimed.success);
+        assert_eq!(claimed.data.unwrap().status, RewardStatus::Claimed);
+        assert_eq!(transactions(user_id, TransactionType::Bonus), vec![TransactionStatus::Completed]);
+        assert_eq!((wallet(user_id), profile_balance(user_id)), (250, 250));
+        assert_eq!(account_balance(&LedgerAccount::Promo), -250);
+        
+        // A claimed bonus is not paid again
+        assert!(!claim_reward(reward.id).success);
+        assert_eq!(wallet(user_id), 250);
+        assert!(audit_ledger().balanced);
+    }
+    
+    // --- Rake and settlement ---
+    // Finalizes a contest paying its whole pool to rank 1, entered by users
+    // `first_user..first_user + entrants`, and returns what the winners were
+    // paid and how the house balance moved.
+    fn finalize_paying(contest_id: &str, contest_type: ContestType, pool: u64, entrants: u8, first_user: u8) -> (i128, i128) {
+        let contest = Contest {
+            id: contest_id.to_string(),
+            contest_type,
+            prize_pool: pool,
+            filled_spots: u32::from(entrants),
+            ..contest(RankingConfig::default())
+        };
+        CONTESTS.with(|contests| {
+            contests.borrow_mut().insert(contest.id.clone(), contest.clone());
+        });
+        PRIZE_POOLS.with(|pools| {
+            pools.borrow_mut().insert(
+                contest.id.clone(),
+                PrizePool {
+                    contest_id: contest.id.clone(),
+                    total_amount: pool,
+                    distribution: resolve_prize_distribution(pool, vec![tier(1, None, 100.0)]).unwrap(),
+                    distributed: false,
+                    rake: None,
+                },
+            );
+        });
+        let users: Vec<Principal> = (first_user..first_user + entrants).map(|id| funded_user(id, 100)).collect();
+        for user_id in &users {
+            enter(&contest, u64::from(user_id.as_slice()[0]), *user_id);
+        }
+        
+        let house = account_balance(&LedgerAccount::House);
+        assert!(finalize_contest(contest.id.clone()).success);
+        let paid = users.iter().map(|user_id| wallet(*user_id)).sum();
+        (paid, account_balance(&LedgerAccount::House) - house)
+    }
+    
+    #[test]
+    fn rake_and_guarantees_settle_every_escrow_to_zero() {
+        let mut match_obj = scheduled_match("match-1", 0);
+        match_obj.status = MatchStatus::Completed;
+        match_obj.score = Some(final_score());
+        MATCHES.with(|matches| {
+            matches.borrow_mut().insert(match_obj.id.clone(), match_obj);
+        });
+        set_platform_rake(10.0);
+        
+        // (contest, type, pool, entrants, paid to winners, house gain)
+        let cases = [
+            // 400 collected under a 1000 guarantee: no rake, the house covers 600
+            ("g-short", ContestType::Guaranteed, 1_000, 4, 1_000, -600),
+            // 500 collected over a 300 guarantee: 50 rake plus 150 left over
+            ("g-over", ContestType::Guaranteed, 300, 5, 300, 200),
+            // 400 collected over 380: the 40 rake is capped at the 20 above the pool
+            ("g-capped", ContestType::Guaranteed, 380, 4, 380, 20),
+            // Other contests shrink to the spots filled, less the rake
+            ("shared", ContestType::MultiPlayer, 1_000, 4, 360, 40),
+        ];
+        
+        let mut first_user = 1;
+        for (contest_id, contest_type, pool, entrants, paid, house) in cases {
+            let settled = finalize_paying(contest_id, contest_type, pool, entrants, first_user);
+            assert_eq!(settled, (paid, house), "{}", contest_id);
+            assert_eq!(escrow(contest_id), 0, "{}", contest_id);
+            first_user += entrants;
+        }
+        assert!(audit_ledger().balanced);
+    }
+    
+    // --- Token transfers ---
+    fn transfer_block(memo: &str, created_at_time: u64, amount: u64) -> Icrc3Value {
+        Icrc3Value::Map(vec![
+            ("ts".to_string(), Icrc3Value::Nat(Nat::from(created_at_time + 5))),
+            (
+                "tx".to_string(),
+                Icrc3Value::Map(vec![
+                    ("op".to_string(), Icrc3Value::Text("xfer".to_string())),
+                    ("memo".to_string(), Icrc3Value::Blob(memo.as_bytes().to_vec())),
+                    ("ts".to_string(), Icrc3Value::Nat(Nat::from(created_at_time))),
+                    ("amt".to_string(), Icrc3Value::Nat(Nat::from(amount))),
+                ]),
+            ),
+        ])
+    }
+    
+    // Records a deposit as sent to the token ledger with its outcome unknown
+    fn pending_deposit(user_id: Principal, amount: u64) -> PendingDeposit {
+        let transaction = create_transaction(user_id, amount, TransactionType::Deposit, TransactionStatus::Pending);
+        let pending = PendingDeposit {
+            transaction_id: transaction.id,
+            amount,
+            created_at_time: transaction.created_at,
+            source: DepositSource::Allowance { from_subaccount: None },
+        };
+        PENDING_DEPOSITS.with(|deposits| {
+            deposits.borrow_mut().insert(user_id, pending.clone());
+        });
+        pending
+    }
+    
+    fn is_pending(user_id: Principal) -> bool {
+        PENDING_DEPOSITS.with(|deposits| deposits.borrow().contains_key(&user_id))
+    }
+    
+    #[test]
+    fn ledger_blocks_are_matched_on_memo_time_and_amount() {
+        let block = transfer_block("42", 1_000, 250);
+        
+        assert!(block_matches_transfer(&block, b"42", 1_000, 250));
+        assert!(!block_matches_transfer(&block, b"43", 1_000, 250));
+        assert!(!block_matches_transfer(&block, b"42", 1_001, 250));
+        assert!(!block_matches_transfer(&block, b"42", 1_000, 249));
+        assert!(!block_matches_transfer(&Icrc3Value::Text("42".to_string()), b"42", 1_000, 250));
+        assert_eq!(block_field_u64(&block, &["ts"]), Some(1_005));
+        assert_eq!(block_field_u64(&block, &["tx", "op"]), None);
+    }
+    
+    #[test]
+    fn deposits_missing_from_the_ledger_log_wait_for_review() {
+        let user_id = funded_user(1, 0);
+        let pending = pending_deposit(user_id, 300);
+        
+        // Not found, or not searchable, leaves the deposit pending
+        for found in [Ok(false), Err("Older ledger blocks have been archived".to_string())] {
+            assert!(!settle_deposit(user_id, &pending, logged_transfer_outcome(LedgerOutcome::TooOld, found)).success);
+            assert!(is_pending(user_id));
+            assert_eq!(transactions(user_id, TransactionType::Deposit), vec![TransactionStatus::Pending]);
+            assert_eq!(wallet(user_id), 0);
+        }
+        
+        // Found in the log, it is credited once
+        assert!(settle_deposit(user_id, &pending, logged_transfer_outcome(LedgerOutcome::TooOld, Ok(true))).success);
+        assert!(!is_pending(user_id));
+        assert_eq!(transactions(user_id, TransactionType::Deposit), vec![TransactionStatus::Completed]);
+        assert_eq!(wallet(user_id), 300);
+        assert!(!settle_deposit(user_id, &pending, LedgerOutcome::Executed).success);
+        assert_eq!(wallet(user_id), 300);
+    }
+    
+    #[test]
+    fn admins_settle_deposits_left_for_review() {
+        let received = funded_user(1, 0);
+        let missing = funded_user(2, 0);
+        pending_deposit(received, 300);
+        pending_deposit(missing, 200);
+        
+        assert!(review_pending_deposit(received, true).success);
+        assert_eq!(wallet(received), 300);
+        
+        assert!(!review_pending_deposit(missing, false).success);
+        assert!(!is_pending(missing));
+        assert_eq!(transactions(missing, TransactionType::Deposit), vec![TransactionStatus::Failed]);
+        assert_eq!(wallet(missing), 0);
+        
+        assert_eq!(review_pending_deposit(missing, true).error.as_deref(), Some("No pending deposit"));
+        assert!(audit_ledger().balanced);
+    }
+    
+    // Holds the user's funds for a withdrawal that has been sent to the ledger
+    fn sent_withdrawal_request(user_id: Principal, amount: u64) -> WithdrawalRequest {
+        post_transfer(LedgerAccount::UserWallet(user_id), LedgerAccount::PendingWithdrawals, amount, TransactionType::Withdrawal).unwrap();
+        let transaction = create_transaction(user_id, amount, TransactionType::Withdrawal, TransactionStatus::Pending);
+        let request = WithdrawalRequest {
+            id: transaction.id,
+            user_id,
+            amount,
+            destination: Account {
+                owner: user_id,
+                subaccount: None,
+            },
+            status: WithdrawalStatus::Processing,
+            created_at: 0,
+            decided_by: None,
+            completed_at: None,
+            error: None,
+            sent_at: Some(0),
+            fee: Some(10),
+        };
+        save_withdrawal(&request);
+        request
+    }
+    
+    fn withdrawal_status(id: &str) -> WithdrawalStatus {
+        WITHDRAWALS.with(|withdrawals| withdrawals.borrow().get(&id.to_string())).unwrap().status
+    }
+    
+    #[test]
+    fn withdrawals_with_an_unknown_outcome_keep_their_funds_held() {
+        let user_id = funded_user(1, 500);
+        let request = sent_withdrawal_request(user_id, 300);
+        let held = || account_balance(&LedgerAccount::PendingWithdrawals);
+        
+        for outcome in [LedgerOutcome::Unknown("Failed to call the token ledger".to_string()), LedgerOutcome::TooOld] {
+            let response = settle_withdrawal(request.clone(), outcome);
+            assert!(!response.success);
+            assert_eq!(response.data.unwrap().status, WithdrawalStatus::Processing);
+            assert_eq!(withdrawal_status(&request.id), WithdrawalStatus::Processing);
+            assert_eq!((wallet(user_id), held()), (200, 300));
+            assert_eq!(transactions(user_id, TransactionType::Withdrawal), vec![TransactionStatus::Pending]);
+            assert!(transactions(user_id, TransactionType::Refund).is_empty());
+        }
+        
+        // The resend is deduplicated by the ledger and completes the withdrawal once
+        assert!(settle_withdrawal(request.clone(), LedgerOutcome::Executed).success);
+        assert_eq!(withdrawal_status(&request.id), WithdrawalStatus::Completed);
+        assert_eq!((wallet(user_id), held()), (200, 0));
+        assert_eq!(transactions(user_id, TransactionType::Withdrawal), vec![TransactionStatus::Completed]);
+        
+        assert!(!settle_withdrawal(request, LedgerOutcome::Rejected("late".to_string())).success);
+        assert_eq!(wallet(user_id), 200);
+        assert!(audit_ledger().balanced);
+    }
+    
+    #[test]
+    fn withdrawals_are_released_only_when_definitely_not_sent() {
+        let rejected = || LedgerOutcome::Rejected("Withdrawal rejected by the token ledger".to_string());
+        let archived = || Err("Older ledger blocks have been archived".to_string());
+        
+        // A rejected resend only counts once the log shows the first send failed too
+        assert_eq!(logged_transfer_outcome(rejected(), Ok(true)), LedgerOutcome::Executed);
+        assert_eq!(logged_transfer_outcome(rejected(), Ok(false)), rejected());
+        assert!(matches!(logged_transfer_outcome(rejected(), archived()), LedgerOutcome::Unknown(_)));
+        assert_eq!(logged_transfer_outcome(LedgerOutcome::TooOld, Ok(false)), LedgerOutcome::TooOld);
+        
+        let user_id = funded_user(1, 500);
+        let request = sent_withdrawal_request(user_id, 300);
+        let response = settle_withdrawal(request.clone(), rejected());
+        
+        assert!(!response.success);
+        assert_eq!(withdrawal_status(&request.id), WithdrawalStatus::Failed);
+        assert_eq!(wallet(user_id), 500);
+        assert_eq!(transactions(user_id, TransactionType::Withdrawal), vec![TransactionStatus::Failed]);
+        assert_eq!(transactions(user_id, TransactionType::Refund), vec![TransactionStatus::Completed]);
+        
+        // Settled withdrawals cannot be reviewed or resent
+        assert_eq!(
+            review_withdrawal(request.id, true).error.as_deref(),
+            Some("Withdrawal is not awaiting a ledger outcome")
+        );
+    }
+    
+    #[test]
+    fn admins_settle_withdrawals_left_for_review() {
+        let user_id = funded_user(1, 500);
+        let sent = sent_withdrawal_request(user_id, 100);
+        let not_sent = sent_withdrawal_request(user_id, 200);
+        
+        assert!(review_withdrawal(sent.id.clone(), true).success);
+        assert_eq!(withdrawal_status(&sent.id), WithdrawalStatus::Completed);
+        
+        assert!(!review_withdrawal(not_sent.id.clone(), false).success);
+        assert_eq!(withdrawal_status(&not_sent.id), WithdrawalStatus::Failed);
+        assert_eq!(wallet(user_id), 400);
+        assert_eq!(account_balance(&LedgerAccount::PendingWithdrawals), 0);
+    }
+    
+    // --- Scoring rules ---
+    fn rule(action: &str, points: f64, condition: Option<(Comparison, f64)>) -> ScoringRule {
+        ScoringRule {
+            action: action.to_string(),
+            points,
+            sport: Sport::Cricket,
+            condition: condition.map(|(comparison, threshold)| RuleCondition { comparison, threshold }),
+        }
+    }
+    
+    fn batting(runs: u32, economy: f64) -> PlayerStats {
+        PlayerStats {
+            runs: Some(runs),
+            economy: Some(economy),
+            ..Default::default()
+        }
+    }
+    
+    #[test]
+    fn rule_without_condition_scores_per_unit() {
+        assert_eq!(evaluate_rule(&rule("run", 1.5, None), &batting(40, 6.0)), 60.0);
+    }
+    
+    #[test]
+    fn rule_conditions_compare_against_threshold() {
+        let stats = batting(50, 6.0);
+        let cases = [
+            (Comparison::LessThan, 50.0, 0.0),
+            (Comparison::LessThan, 51.0, 8.0),
+            (Comparison::LessThanOrEqual, 50.0, 8.0),
+            (Comparison::LessThanOrEqual, 49.0, 0.0),
+            (Comparison::GreaterThan, 50.0, 0.0),
+            (Comparison::GreaterThan, 49.0, 8.0),
+            (Comparison::GreaterThanOrEqual, 50.0, 8.0),
+            (Comparison::GreaterThanOrEqual, 51.0, 0.0),
+        ];
+        
+        for (comparison, threshold, expected) in cases {
+            let points = evaluate_rule(&rule("run", 8.0, Some((comparison.clone(), threshold))), &stats);
+            assert_eq!(points, expected, "{:?} {}", comparison, threshold);
+        }
+    }
+    
+    #[test]
+    fn rule_conditions_work_on_fractional_stats() {
+        let bonus = rule("economy", 6.0, Some((Comparison::LessThan, 5.0)));
+        assert_eq!(evaluate_rule(&bonus, &batting(0, 4.5)), 6.0);
+        assert_eq!(evaluate_rule(&bonus, &batting(0, 5.0)), 0.0);
+    }
+    
+    #[test]
+    fn rule_for_missing_or_unknown_stat_scores_nothing() {
+        let stats = PlayerStats::default();
+        assert_eq!(evaluate_rule(&rule("run", 1.0, None), &stats), 0.0);
+        // A "less than" condition must not treat a missing stat as zero
+        assert_eq!(evaluate_rule(&rule("wicket", -2.0, Some((Comparison::LessThan, 1.0))), &stats), 0.0);
+        assert_eq!(evaluate_rule(&rule("home_run", 4.0, None), &batting(10, 6.0)), 0.0);
+    }
+    
+    #[test]
+    fn rules_add_up_across_a_rule_set() {
+        let rules = [
+            rule("run", 1.0, None),
+            rule("run", 8.0, Some((Comparison::GreaterThanOrEqual, 50.0))),
+            rule("economy", 6.0, Some((Comparison::LessThan, 5.0))),
+        ];
+        assert_eq!(calculate_points_with_rules(&batting(52, 7.0), &rules), 60.0);
+    }
+}
\ No newline at end of file